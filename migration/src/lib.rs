@@ -1,9 +1,26 @@
 pub use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{DbConn, EntityTrait};
+use sea_orm_migration::seaql_migrations;
 
 mod m20220101_000001_create_user_table;
 mod m20220101_000002_create_conversation_table;
 mod m20220101_000003_create_tweet_table;
 mod m20220101_000004_create_tweet_reference_table;
+mod m20220101_000005_create_watched_account_table;
+mod m20220101_000006_create_tweet_status_table;
+mod m20220101_000007_create_tweet_version_table;
+mod m20220101_000008_create_embedding_table;
+mod m20220101_000009_create_api_key_table;
+mod m20220101_000010_create_hidden_tweet_table;
+mod m20220101_000011_create_redacted_user_table;
+mod m20220101_000012_create_audit_log_table;
+mod m20220101_000013_create_collection_table;
+mod m20220101_000014_create_collection_tweet_table;
+mod m20220101_000015_create_tweet_tag_table;
+mod m20220101_000016_create_annotation_table;
+mod m20220101_000017_create_archive_tables;
+mod m20220101_000018_key_tweet_reference_table_by_both_tweets;
+mod m20220101_000019_make_api_key_names_unique;
 
 pub struct Migrator;
 
@@ -15,6 +32,39 @@ impl MigratorTrait for Migrator {
             Box::new(m20220101_000002_create_conversation_table::Migration),
             Box::new(m20220101_000003_create_tweet_table::Migration),
             Box::new(m20220101_000004_create_tweet_reference_table::Migration),
+            Box::new(m20220101_000005_create_watched_account_table::Migration),
+            Box::new(m20220101_000006_create_tweet_status_table::Migration),
+            Box::new(m20220101_000007_create_tweet_version_table::Migration),
+            Box::new(m20220101_000008_create_embedding_table::Migration),
+            Box::new(m20220101_000009_create_api_key_table::Migration),
+            Box::new(m20220101_000010_create_hidden_tweet_table::Migration),
+            Box::new(m20220101_000011_create_redacted_user_table::Migration),
+            Box::new(m20220101_000012_create_audit_log_table::Migration),
+            Box::new(m20220101_000013_create_collection_table::Migration),
+            Box::new(m20220101_000014_create_collection_tweet_table::Migration),
+            Box::new(m20220101_000015_create_tweet_tag_table::Migration),
+            Box::new(m20220101_000016_create_annotation_table::Migration),
+            Box::new(m20220101_000017_create_archive_tables::Migration),
+            Box::new(m20220101_000018_key_tweet_reference_table_by_both_tweets::Migration),
+            Box::new(m20220101_000019_make_api_key_names_unique::Migration),
         ]
     }
+
+    // Applied migrations are matched against the list above one by one. By default they're
+    // read back in the order of their names, which puts the tweet table's, the one named
+    // without an underscore after the m, before all the others, so they're put in the
+    // list's order instead. Names that aren't in the list go last, to be reported missing.
+    async fn get_migration_models(db: &DbConn) -> Result<Vec<seaql_migrations::Model>, DbErr> {
+        Self::install(db).await?;
+        let names: Vec<String> = Self::migrations()
+            .iter()
+            .map(|migration| migration.name().to_owned())
+            .collect();
+        let mut applied = seaql_migrations::Entity::find().all(db).await?;
+        applied.sort_by_key(|migration| {
+            let position = names.iter().position(|name| *name == migration.version);
+            (position.unwrap_or(names.len()), migration.version.clone())
+        });
+        Ok(applied)
+    }
 }
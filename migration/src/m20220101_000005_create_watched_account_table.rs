@@ -0,0 +1,57 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20220101_000005_create_watched_account_table" // Make sure this matches with the file name
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the WatchedAccount table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(WatchedAccount::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(WatchedAccount::Username)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(WatchedAccount::IntervalSeconds)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(WatchedAccount::LastSyncedAt).date_time())
+                    .col(ColumnDef::new(WatchedAccount::LastSeenTweetId).big_integer())
+                    .col(ColumnDef::new(WatchedAccount::LastError).string())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the WatchedAccount table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(WatchedAccount::Table).to_owned())
+            .await
+    }
+}
+
+// For ease of access
+#[derive(Iden)]
+pub enum WatchedAccount {
+    #[iden = "watched_accounts"]
+    Table,
+    Username,
+    IntervalSeconds,
+    LastSyncedAt,
+    LastSeenTweetId,
+    LastError,
+}
@@ -0,0 +1,50 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20220101_000006_create_tweet_status_table" // Make sure this matches with the file name
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the TweetStatus table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(TweetStatus::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(TweetStatus::TweetId)
+                            .big_integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(TweetStatus::Status).string().not_null())
+                    .col(ColumnDef::new(TweetStatus::Detail).string())
+                    .col(ColumnDef::new(TweetStatus::CheckedAt).date_time().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the TweetStatus table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(TweetStatus::Table).to_owned())
+            .await
+    }
+}
+
+// For ease of access
+#[derive(Iden)]
+pub enum TweetStatus {
+    Table,
+    TweetId,
+    Status,
+    Detail,
+    CheckedAt,
+}
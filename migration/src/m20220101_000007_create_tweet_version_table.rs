@@ -0,0 +1,53 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20220101_000007_create_tweet_version_table" // Make sure this matches with the file name
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the TweetVersion table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(TweetVersion::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(TweetVersion::TweetId).big_integer().not_null())
+                    .col(ColumnDef::new(TweetVersion::Version).integer().not_null())
+                    .col(ColumnDef::new(TweetVersion::EditTweetId).big_integer().not_null())
+                    .col(ColumnDef::new(TweetVersion::Content).string().not_null())
+                    .col(ColumnDef::new(TweetVersion::RecordedAt).date_time().not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(TweetVersion::TweetId)
+                            .col(TweetVersion::Version),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the TweetVersion table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(TweetVersion::Table).to_owned())
+            .await
+    }
+}
+
+// For ease of access
+#[derive(Iden)]
+pub enum TweetVersion {
+    #[iden = "tweet_versions"]
+    Table,
+    TweetId,
+    Version,
+    EditTweetId,
+    Content,
+    RecordedAt,
+}
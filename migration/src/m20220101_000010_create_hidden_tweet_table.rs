@@ -0,0 +1,49 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20220101_000010_create_hidden_tweet_table" // Make sure this matches with the file name
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the HiddenTweet table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(HiddenTweet::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(HiddenTweet::TweetId)
+                            .big_integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(HiddenTweet::Reason).string().not_null())
+                    .col(ColumnDef::new(HiddenTweet::HiddenAt).date_time().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the HiddenTweet table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(HiddenTweet::Table).to_owned())
+            .await
+    }
+}
+
+// For ease of access
+#[derive(Iden)]
+pub enum HiddenTweet {
+    #[iden = "hidden_tweets"]
+    Table,
+    TweetId,
+    Reason,
+    HiddenAt,
+}
@@ -0,0 +1,54 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20220101_000011_create_redacted_user_table" // Make sure this matches with the file name
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the RedactedUser table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RedactedUser::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RedactedUser::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(RedactedUser::UserId).big_integer())
+                    .col(ColumnDef::new(RedactedUser::Username).string())
+                    .col(ColumnDef::new(RedactedUser::Reason).string().not_null())
+                    .col(ColumnDef::new(RedactedUser::RedactedAt).date_time().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the RedactedUser table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RedactedUser::Table).to_owned())
+            .await
+    }
+}
+
+// For ease of access
+#[derive(Iden)]
+pub enum RedactedUser {
+    #[iden = "redacted_users"]
+    Table,
+    Id,
+    UserId,
+    Username,
+    Reason,
+    RedactedAt,
+}
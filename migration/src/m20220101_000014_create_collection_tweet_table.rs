@@ -0,0 +1,63 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20220101_000014_create_collection_tweet_table" // Make sure this matches with the file name
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the CollectionTweet table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CollectionTweet::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CollectionTweet::CollectionId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CollectionTweet::TweetId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(CollectionTweet::Note).string())
+                    .col(
+                        ColumnDef::new(CollectionTweet::AddedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(CollectionTweet::CollectionId)
+                            .col(CollectionTweet::TweetId),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the CollectionTweet table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CollectionTweet::Table).to_owned())
+            .await
+    }
+}
+
+// For ease of access
+#[derive(Iden)]
+pub enum CollectionTweet {
+    #[iden = "collection_tweets"]
+    Table,
+    CollectionId,
+    TweetId,
+    Note,
+    AddedAt,
+}
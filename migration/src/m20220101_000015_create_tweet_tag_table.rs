@@ -0,0 +1,45 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20220101_000015_create_tweet_tag_table" // Make sure this matches with the file name
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the TweetTag table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(TweetTag::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(TweetTag::TweetId).big_integer().not_null())
+                    .col(ColumnDef::new(TweetTag::Tag).string().not_null())
+                    .col(ColumnDef::new(TweetTag::TaggedAt).date_time().not_null())
+                    .primary_key(Index::create().col(TweetTag::TweetId).col(TweetTag::Tag))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the TweetTag table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(TweetTag::Table).to_owned())
+            .await
+    }
+}
+
+// For ease of access
+#[derive(Iden)]
+pub enum TweetTag {
+    #[iden = "tweet_tags"]
+    Table,
+    TweetId,
+    Tag,
+    TaggedAt,
+}
@@ -0,0 +1,58 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20220101_000016_create_annotation_table" // Make sure this matches with the file name
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the Annotation table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Annotation::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Annotation::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Annotation::Author).string().not_null())
+                    .col(ColumnDef::new(Annotation::TweetId).big_integer())
+                    .col(ColumnDef::new(Annotation::ConversationId).big_integer())
+                    .col(ColumnDef::new(Annotation::Body).string().not_null())
+                    .col(ColumnDef::new(Annotation::CreatedAt).date_time().not_null())
+                    .col(ColumnDef::new(Annotation::UpdatedAt).date_time().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the Annotation table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Annotation::Table).to_owned())
+            .await
+    }
+}
+
+// For ease of access
+#[derive(Iden)]
+pub enum Annotation {
+    #[iden = "annotations"]
+    Table,
+    Id,
+    Author,
+    TweetId,
+    ConversationId,
+    Body,
+    CreatedAt,
+    UpdatedAt,
+}
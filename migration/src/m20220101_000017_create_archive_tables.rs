@@ -0,0 +1,157 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20220101_000017_create_archive_tables" // Make sure this matches with the file name
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the tables the archive is read from and
+    // written to. The first four migrations made tables with singular names, 32-bit ids and
+    // text conversation ids, which the entities never used; they are left as they are.
+    // Archives made before there were migrations already have these tables and keep them.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(User::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(User::Id)
+                            .big_integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(User::Name).string().not_null())
+                    .col(ColumnDef::new(User::Username).string().not_null())
+                    .col(ColumnDef::new(User::Description).string().not_null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(Conversation::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Conversation::Id)
+                            .big_integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(Tweet::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Tweet::Id)
+                            .big_integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Tweet::Content).string().not_null())
+                    .col(ColumnDef::new(Tweet::AuthorId).big_integer().not_null())
+                    .col(ColumnDef::new(Tweet::ConversationId).big_integer().not_null())
+                    .col(ColumnDef::new(Tweet::CreatedAt).date_time().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-tweet-author_id")
+                            .from(Tweet::Table, Tweet::AuthorId)
+                            .to(User::Table, User::Id),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-tweet-conversation_id")
+                            .from(Tweet::Table, Tweet::ConversationId)
+                            .to(Conversation::Table, Conversation::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(TweetReference::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(TweetReference::SourceTweetId)
+                            .big_integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(TweetReference::ReferenceType).string().not_null())
+                    .col(
+                        ColumnDef::new(TweetReference::ReferencedTweetId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-tweet-reference-source_tweet_id")
+                            .from(TweetReference::Table, TweetReference::SourceTweetId)
+                            .to(Tweet::Table, Tweet::Id),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-tweet-reference-referenced_tweet_id")
+                            .from(TweetReference::Table, TweetReference::ReferencedTweetId)
+                            .to(Tweet::Table, Tweet::Id),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Nothing. The tables are usually older than
+    // this migration, since archives had them before there were migrations, and dropping
+    // them would drop the archive.
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
+
+// For ease of access
+#[derive(Iden)]
+pub enum User {
+    #[iden = "users"]
+    Table,
+    Id,
+    Name,
+    Username,
+    Description,
+}
+
+#[derive(Iden)]
+pub enum Conversation {
+    #[iden = "conversations"]
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+pub enum Tweet {
+    #[iden = "tweets"]
+    Table,
+    Id,
+    Content,
+    AuthorId,
+    ConversationId,
+    CreatedAt,
+}
+
+#[derive(Iden)]
+pub enum TweetReference {
+    #[iden = "tweet_references"]
+    Table,
+    SourceTweetId,
+    ReferenceType,
+    ReferencedTweetId,
+}
@@ -0,0 +1,131 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20220101_000017_create_archive_tables::{Tweet, TweetReference};
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20220101_000018_key_tweet_reference_table_by_both_tweets" // Make sure this matches with the file name
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Key the TweetReference table by both tweets, so a
+    // tweet can quote one tweet and reply to another. Sqlite can't change a primary key, so
+    // the rows are moved out to a copy and back into a recreated table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        rekey(manager, || {
+            Index::create()
+                .col(TweetReference::SourceTweetId)
+                .col(TweetReference::ReferencedTweetId)
+                .to_owned()
+        })
+        .await
+    }
+
+    // Define how to rollback this migration: Key the TweetReference table by its source
+    // again, keeping one reference per tweet.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        rekey(manager, || {
+            Index::create().col(TweetReference::SourceTweetId).to_owned()
+        })
+        .await
+    }
+}
+
+// Recreates the TweetReference table with the given primary key, dropping any rows that the
+// key makes duplicates.
+async fn rekey(
+    manager: &SchemaManager<'_>,
+    primary_key: impl Fn() -> IndexCreateStatement,
+) -> Result<(), DbErr> {
+    manager
+        .create_table(table(CopiedTweetReference::Table, primary_key()))
+        .await?;
+    copy(manager, TweetReference::Table, CopiedTweetReference::Table).await?;
+    manager
+        .drop_table(Table::drop().table(TweetReference::Table).to_owned())
+        .await?;
+    manager
+        .create_table(table(TweetReference::Table, primary_key()))
+        .await?;
+    copy(manager, CopiedTweetReference::Table, TweetReference::Table).await?;
+    manager
+        .drop_table(Table::drop().table(CopiedTweetReference::Table).to_owned())
+        .await
+}
+
+fn table<T: Iden + 'static>(name: T, mut primary_key: IndexCreateStatement) -> TableCreateStatement {
+    Table::create()
+        .table(name)
+        .col(
+            ColumnDef::new(TweetReference::SourceTweetId)
+                .big_integer()
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(TweetReference::ReferenceType)
+                .string()
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(TweetReference::ReferencedTweetId)
+                .big_integer()
+                .not_null(),
+        )
+        .primary_key(&mut primary_key)
+        .foreign_key(
+            ForeignKey::create()
+                .name("fk-tweet-reference-source_tweet_id")
+                .from_col(TweetReference::SourceTweetId)
+                .to(Tweet::Table, Tweet::Id),
+        )
+        .foreign_key(
+            ForeignKey::create()
+                .name("fk-tweet-reference-referenced_tweet_id")
+                .from_col(TweetReference::ReferencedTweetId)
+                .to(Tweet::Table, Tweet::Id),
+        )
+        .to_owned()
+}
+
+// Copies the references from one table into another, skipping those already there.
+async fn copy<F, T>(manager: &SchemaManager<'_>, from: F, to: T) -> Result<(), DbErr>
+where
+    F: Iden + 'static,
+    T: Iden + 'static,
+{
+    let columns = || {
+        [
+            TweetReference::SourceTweetId,
+            TweetReference::ReferenceType,
+            TweetReference::ReferencedTweetId,
+        ]
+    };
+    manager
+        .exec_stmt(
+            Query::insert()
+                .into_table(to)
+                .columns(columns())
+                .select_from(
+                    Query::select()
+                        .columns(columns())
+                        .from(from)
+                        // Sqlite needs a where clause to tell the upsert apart from a join.
+                        .and_where(Expr::cust("true"))
+                        .to_owned(),
+                )
+                .map_err(|error| DbErr::Migration(error.to_string()))?
+                .on_conflict(OnConflict::new().do_nothing().to_owned())
+                .to_owned(),
+        )
+        .await
+}
+
+#[derive(Iden)]
+enum CopiedTweetReference {
+    #[iden = "tweet_references_copy"]
+    Table,
+}
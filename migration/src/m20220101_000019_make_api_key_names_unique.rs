@@ -0,0 +1,51 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, Statement};
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20220101_000019_make_api_key_names_unique" // Make sure this matches with the file name
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Make the names of keys that aren't revoked unique,
+    // as annotations are put down to the name of the key that wrote them. A revoked key's
+    // name can be given to the key replacing it. Keys that already share a name, other than
+    // the oldest, have their id added to it.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        execute(
+            manager,
+            "UPDATE api_keys SET name = name || ' (' || id || ')' \
+             WHERE revoked_at IS NULL AND id NOT IN \
+             (SELECT MIN(id) FROM api_keys WHERE revoked_at IS NULL GROUP BY name)",
+        )
+        .await?;
+        execute(
+            manager,
+            "CREATE UNIQUE INDEX IF NOT EXISTS \"idx-api-key-name\" \
+             ON api_keys (name) WHERE revoked_at IS NULL",
+        )
+        .await
+    }
+
+    // Define how to rollback this migration: Let keys share a name again. Renamed keys keep
+    // their new names.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        execute(manager, "DROP INDEX IF EXISTS \"idx-api-key-name\"").await
+    }
+}
+
+// Sea-query can't make a partial index, so the index is written out for sqlite.
+async fn execute(manager: &SchemaManager<'_>, sql: &str) -> Result<(), DbErr> {
+    manager
+        .get_connection()
+        .execute(Statement::from_string(
+            manager.get_database_backend(),
+            sql.to_owned(),
+        ))
+        .await
+        .map(|_result| ())
+}
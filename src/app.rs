@@ -1,37 +1,160 @@
-use crate::{
-    seed,
-    utils::{convert_chrono_to_date, i64_to_u64, ConversationData, TweetData, UserData},
+use crate::utils::{
+    convert_chrono_to_date, i64_to_u64, u64_to_i64, ConversationData, DayTweets, OnThisDay,
+    TweetData, TweetStatusKind, UserData,
 };
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate};
+use dates::DateWindow;
+use data::entities::tweet_status;
+use data::entities::watched_accounts;
 use rocket::{time::OffsetDateTime, State};
 use sea_orm::DatabaseConnection;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+pub mod annotations;
 pub mod api;
+pub mod archive;
+pub mod collections;
 pub mod data;
+pub mod dates;
+pub mod export;
+pub mod history;
+pub mod keys;
+pub mod moderation;
+pub mod network;
+pub mod repair;
+#[cfg(feature = "semantic-search")]
+pub mod semantic;
+pub mod vocabulary;
+pub mod web;
+
+// Tweets the api said were protected, suspended or missing are asked for again after this
+// long. Deleted tweets don't come back, so they aren't.
+const RECHECK_UNAVAILABLE_DAYS: i64 = 30;
 
 pub async fn load_tweet_from_id(db: &State<DatabaseConnection>, id: i64) -> TweetData {
-    let tweet_data = data::read::tweet_by_id(db, id).await;
+    let tweet_data = load_tweet_from_archive(db, id).await;
+    if tweet_data.tweet.is_some() || tweet_data.status.is_some() || api::is_offline() {
+        return tweet_data;
+    }
+    let (tweet_data, errors) = api::get_tweet_by_id_with_errors(i64_to_u64(id)).await;
     let tweet = tweet_data.tweet.clone();
     match tweet {
-        Some(_tweet) => tweet_data,
+        Some(_tweet) => {
+            data::write::tweet(db, &tweet_data).await;
+            record_tweet_status(db, id, TweetStatusKind::Available, None).await;
+            tweet_data
+        }
         None => {
-            let tweet_data = api::get_tweet_by_id(i64_to_u64(id)).await;
-            let tweet = tweet_data.tweet.clone();
-            match tweet {
-                Some(_tweet) => {
-                    data::write::tweet(db, &tweet_data).await;
-                    tweet_data
-                }
-                None => TweetData::empty(),
+            let (kind, detail) = api::tweet_status_from_errors(i64_to_u64(id), &errors, false);
+            TweetData {
+                status: Some(record_tweet_status(db, id, kind, detail).await),
+                ..TweetData::empty()
             }
         }
     }
 }
 
+// The archived tweet, or why it's known to be unavailable, without asking the api.
+pub async fn load_tweet_from_archive(db: &State<DatabaseConnection>, id: i64) -> TweetData {
+    let tweet_data = data::read::tweet_by_id(db, id).await;
+    if tweet_data.tweet.is_some() {
+        return tweet_data;
+    }
+    match load_known_unavailable_status(db, id).await {
+        Some(status) => TweetData {
+            status: Some(status),
+            ..TweetData::empty()
+        },
+        None => TweetData::empty(),
+    }
+}
+
+// The stored status of a tweet, if it says the tweet is gone and is recent enough to trust.
+async fn load_known_unavailable_status(
+    db: &State<DatabaseConnection>,
+    id: i64,
+) -> Option<tweet_status::Model> {
+    let status = data::read::tweet_status(db, id).await?;
+    let age = chrono::Utc::now().timestamp() - status.checked_at.timestamp();
+    match TweetStatusKind::kind_from_string(&status.status) {
+        Some(TweetStatusKind::Deleted) => Some(status),
+        Some(TweetStatusKind::Available) | None => None,
+        Some(_kind) if age < RECHECK_UNAVAILABLE_DAYS * 24 * 60 * 60 => Some(status),
+        Some(_kind) => None,
+    }
+}
+
+async fn record_tweet_status(
+    db: &State<DatabaseConnection>,
+    id: i64,
+    kind: TweetStatusKind,
+    detail: Option<String>,
+) -> tweet_status::Model {
+    let status = tweet_status::Model {
+        tweet_id: id,
+        status: kind.as_str().to_string(),
+        detail,
+        checked_at: chrono::Utc::now().into(),
+    };
+    data::write::tweet_status(db, &status).await;
+    status
+}
+
+// Asks the api about every archived tweet of the user, 100 at a time, and records which
+// ones have since been deleted or hidden. Tweets that come back are written again so any
+// change to their text is kept as a new version. Returns how many tweets have each status.
+pub async fn check_users_tweets_status(
+    db: &State<DatabaseConnection>,
+    twitter_handle: &str,
+) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    if api::is_offline() {
+        println!("Not checking @{twitter_handle}'s tweets in offline mode");
+        return counts;
+    }
+    let ids: Vec<u64> = data::read::users_tweets(db, twitter_handle)
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|tweet_data| tweet_data.tweet.as_ref())
+        .map(|tweet| i64_to_u64(tweet.id))
+        .collect();
+    for chunk in ids.chunks(100) {
+        let (available, errors) = api::get_tweets_by_ids_with_errors(chunk).await;
+        data::write::tweets(db, &available).await;
+        let available: HashSet<u64> = available
+            .iter()
+            .filter_map(|tweet_data| tweet_data.tweet.as_ref())
+            .map(|tweet| i64_to_u64(tweet.id))
+            .collect();
+        let checked_at: DateTime<FixedOffset> = chrono::Utc::now().into();
+        let statuses: Vec<tweet_status::Model> = chunk
+            .iter()
+            .map(|id| {
+                let (kind, detail) = if available.contains(id) {
+                    (TweetStatusKind::Available, None)
+                } else {
+                    api::tweet_status_from_errors(*id, &errors, true)
+                };
+                *counts.entry(kind.as_str().to_string()).or_default() += 1;
+                tweet_status::Model {
+                    tweet_id: u64_to_i64(*id),
+                    status: kind.as_str().to_string(),
+                    detail,
+                    checked_at,
+                }
+            })
+            .collect();
+        data::write::tweet_statuses(db, &statuses).await;
+    }
+    counts
+}
+
 pub async fn load_user_from_id(db: &State<DatabaseConnection>, id: i64) -> UserData {
     let user_data = UserData::read(db, id).await;
     let user = user_data.user.clone();
     match user {
         Some(_user) => user_data,
+        None if api::is_offline() => UserData::empty().await,
         None => {
             let user_data = api::get_user_by_id(i64_to_u64(id)).await;
             let user = user_data.user.clone();
@@ -54,6 +177,7 @@ pub async fn load_user_from_twitter_handle(
     let user = user_data.user.clone();
     match user {
         Some(_user) => user_data,
+        None if api::is_offline() => UserData::empty().await,
         None => {
             let user_data = api::get_user_by_twitter_handle(twitter_handle).await;
             let user = user_data.user.clone();
@@ -71,143 +195,277 @@ pub async fn load_user_from_twitter_handle(
 pub async fn load_user_tweets_from_twitter_handle(
     db: &State<DatabaseConnection>,
     twitter_handle: &str,
-) -> Vec<TweetData> {
-    let user_tweets = data::read::users_tweets(db, twitter_handle).await;
-    if user_tweets.is_empty() {
-        seed::all_tweets(db).await;
-        data::write::tweets(db, &user_tweets).await;
-        data::read::users_tweets(db, twitter_handle).await
-    } else if has_new_tweets(db, twitter_handle).await {
-        println!("Adding new tweets");
-        let new_tweets = load_users_new_tweets(db, twitter_handle).await;
-        data::write::tweets(db, &new_tweets).await;
-        data::read::users_tweets(db, twitter_handle).await
-    } else {
-        println!("No new tweets to add");
-        user_tweets
+    sync: bool,
+) -> Option<Vec<TweetData>> {
+    if sync {
+        sync_before_read(db, twitter_handle).await;
     }
+    data::read::users_tweets(db, twitter_handle).await
 }
 
 pub async fn load_user_conversations_from_twitter_handle(
     db: &State<DatabaseConnection>,
     twitter_handle: &str,
-) -> Vec<ConversationData> {
-    let users_tweets = load_user_tweets_from_twitter_handle(db, twitter_handle).await;
+    sync: bool,
+) -> Option<Vec<ConversationData>> {
+    let users_tweets = load_user_tweets_from_twitter_handle(db, twitter_handle, sync).await?;
     let mut output: Vec<ConversationData> = Vec::<ConversationData>::new();
-    for (i, tweet_data) in users_tweets.iter().enumerate() {
-        let tweet = tweet_data.tweet.clone().unwrap();
+    for (i, tweet) in users_tweets
+        .iter()
+        .filter_map(|tweet_data| tweet_data.tweet.as_ref())
+        .enumerate()
+    {
         let tweet_id = &tweet.id;
         println!("Loading conversation {i} from tweet of id {tweet_id}");
-        output.push(load_twitter_conversation_from_tweet_id(db, *tweet_id).await);
+        output.extend(load_twitter_conversation_from_tweet_id(db, *tweet_id).await);
     }
-    output
+    Some(output)
 }
 
 
+// Returns None when the user has no archived tweets that are still shown.
 pub async fn load_offset_datetime_for_users_latest_tweet_in_database(
+    db: &State<DatabaseConnection>,
+    user_id: i64,
+) -> Option<OffsetDateTime> {
+    let tweet = data::read::latest_tweet_from_user(db, user_id).await?.tweet?;
+    Some(convert_chrono_to_date(tweet.created_at))
+}
+
+pub async fn has_user_tweeted_since_date(
     db: &State<DatabaseConnection>,
     twitter_handle: &str,
-) -> OffsetDateTime {
-    let user_data = load_user_from_twitter_handle(db, twitter_handle).await;
-    let user = user_data
-        .user
-        .clone()
-        .unwrap_or_else(|| panic!("User @{twitter_handle} is None"));
-    let user_id: i64 = user.id;
-    convert_chrono_to_date(
-        data::read::latest_tweet_from_user(db, user_id)
-            .await
-            .tweet
-            .unwrap_or_else(|| panic!("Invalid tweet"))
-            .created_at,
-    )
-}
-
-pub async fn load_offset_datetime_for_users_latest_tweet(
+    user_id: i64,
+    date_unix_timestamp: i64,
+    sync: bool,
+) -> bool {
+    if sync {
+        sync_before_read(db, twitter_handle).await;
+    }
+    match load_offset_datetime_for_users_latest_tweet_in_database(db, user_id).await {
+        Some(latest_tweet_date) => latest_tweet_date.unix_timestamp() - date_unix_timestamp > 0,
+        None => false,
+    }
+}
+
+pub async fn load_users_tweets_since_date(
     db: &State<DatabaseConnection>,
     twitter_handle: &str,
-) -> OffsetDateTime {
-    let user = load_user_from_twitter_handle(db, twitter_handle).await;
-    convert_chrono_to_date(
-        api::get_latest_tweet_from_user(&user)
-            .await
-            .tweet
-            .expect("Failed to get the tweet (was None)")
-            .created_at,
-    )
+    user_id: i64,
+    date: DateTime<FixedOffset>,
+    sync: bool,
+) -> Vec<TweetData> {
+    if sync {
+        sync_before_read(db, twitter_handle).await;
+    }
+    data::read::users_tweets_since_date(db, user_id, date).await
 }
 
-pub async fn has_new_tweets(db: &State<DatabaseConnection>, twitter_handle: &str) -> bool {
-    let latest_db_tweet_date =
-        load_offset_datetime_for_users_latest_tweet_in_database(db, twitter_handle).await;
-    let latest_tweet_date = load_offset_datetime_for_users_latest_tweet(db, twitter_handle).await;
-    let difference = latest_tweet_date.unix_timestamp() - latest_db_tweet_date.unix_timestamp();
-    difference > 0
+pub async fn load_users_tweets_between(
+    db: &State<DatabaseConnection>,
+    twitter_handle: &str,
+    user_id: i64,
+    window: &DateWindow,
+    sync: bool,
+) -> Vec<TweetData> {
+    if sync {
+        sync_before_read(db, twitter_handle).await;
+    }
+    data::read::users_tweets_between(db, user_id, window.from, window.to).await
 }
 
-pub async fn has_user_tweeted_since_date(
+pub async fn has_user_tweeted_between(
     db: &State<DatabaseConnection>,
     twitter_handle: &str,
-    date_unix_timestamp: i64,
+    user_id: i64,
+    window: &DateWindow,
+    sync: bool,
 ) -> bool {
-    let latest_tweet_date = load_offset_datetime_for_users_latest_tweet(db, twitter_handle).await;
-    let difference = latest_tweet_date.unix_timestamp() - date_unix_timestamp;
-    difference > 0
+    if sync {
+        sync_before_read(db, twitter_handle).await;
+    }
+    data::read::count_users_tweets_between(db, user_id, window.from, window.to).await > 0
 }
 
-pub async fn load_users_tweets_since_date(
+// The user's tweets on `day`, and on the same day of each earlier year they tweeted that
+// day, newest year first. On years without a 29th of February those tweets are left out.
+pub async fn load_users_tweets_on_day(
+    db: &State<DatabaseConnection>,
+    user_id: i64,
+    day: NaiveDate,
+    offset: FixedOffset,
+) -> OnThisDay {
+    let first_year = data::read::earliest_tweet_date_from_user(db, user_id)
+        .await
+        .map(|date| date.with_timezone(&offset).year())
+        .unwrap_or_else(|| day.year());
+    let days: Vec<NaiveDate> = (first_year.min(day.year())..=day.year())
+        .rev()
+        .filter_map(|year| NaiveDate::from_ymd_opt(year, day.month(), day.day()))
+        .collect();
+    let spans: Vec<(DateTime<FixedOffset>, DateTime<FixedOffset>)> = days
+        .iter()
+        .map(|day| dates::day_bounds(*day, offset))
+        .collect();
+    let mut by_day: BTreeMap<NaiveDate, Vec<data::entities::tweets::Model>> = BTreeMap::new();
+    for tweet in data::read::users_tweets_in_spans(db, user_id, &spans).await {
+        let tweet_day = tweet.created_at.with_timezone(&offset).naive_local().date();
+        by_day.entry(tweet_day).or_default().push(tweet);
+    }
+    let tweets = TweetData::read_many_from_data_models(db, by_day.remove(&day).unwrap_or_default()).await;
+    let mut previous_years = Vec::new();
+    for (previous_day, tweet_models) in by_day.into_iter().rev() {
+        previous_years.push(DayTweets {
+            date: previous_day.to_string(),
+            tweets: TweetData::read_many_from_data_models(db, tweet_models).await,
+        });
+    }
+    OnThisDay {
+        user_id,
+        date: day.to_string(),
+        utc_offset: offset.to_string(),
+        tweets,
+        previous_years,
+    }
+}
+
+pub async fn load_users_latest_archived_tweet_id(
     db: &State<DatabaseConnection>,
     twitter_handle: &str,
-    rfc3339_date: &str,
-) -> Vec<TweetData> {
-    data::write::tweets(db, &load_users_new_tweets(db, twitter_handle).await).await;
-    data::read::users_tweets_since_date(db, twitter_handle, rfc3339_date).await
+) -> Option<i64> {
+    let user = load_user_from_twitter_handle(db, twitter_handle).await.user?;
+    data::read::latest_tweet_id_from_user(db, user.id).await
 }
 
+// Fetches only the tweets newer than the highest snowflake id already archived for the user.
 pub async fn load_users_new_tweets(
     db: &State<DatabaseConnection>,
     twitter_handle: &str,
-) -> Vec<TweetData> {
-    let user = load_user_from_twitter_handle(db, twitter_handle).await;
-    let from = load_offset_datetime_for_users_latest_tweet_in_database(db, twitter_handle).await;
-    api::get_new_tweets_from_user(&user, &from).await
+) -> Result<Vec<TweetData>, String> {
+    let user_data = load_user_from_twitter_handle(db, twitter_handle).await;
+    let user_id = match &user_data.user {
+        Some(user) => user.id,
+        None => return Err(format!("@{twitter_handle} isn't archived and couldn't be fetched")),
+    };
+    let since_id = data::read::latest_tweet_id_from_user(db, user_id).await;
+    Ok(api::get_new_tweets_from_user(&user_data, since_id).await)
+}
+
+pub async fn sync_users_new_tweets(
+    db: &State<DatabaseConnection>,
+    twitter_handle: &str,
+) -> Result<Vec<TweetData>, String> {
+    if api::is_offline() {
+        println!("Not syncing @{twitter_handle}'s tweets in offline mode");
+        return Ok(Vec::new());
+    }
+    let new_tweets = load_users_new_tweets(db, twitter_handle).await?;
+    data::write::tweets(db, &new_tweets).await;
+    Ok(new_tweets)
+}
+
+// Syncs before a read that asked for it. The read goes on with what's archived if the sync
+// fails.
+async fn sync_before_read(db: &State<DatabaseConnection>, twitter_handle: &str) {
+    match sync_users_new_tweets(db, twitter_handle).await {
+        Ok(new_tweets) => println!("Added {} new tweets", new_tweets.len()),
+        Err(error) => println!("Failed to sync @{twitter_handle}'s tweets. Error: {error}"),
+    }
 }
+
+pub async fn load_watched_accounts(
+    db: &State<DatabaseConnection>,
+) -> Vec<watched_accounts::Model> {
+    data::read::watched_accounts(db).await
+}
+
+pub async fn load_watched_account(
+    db: &State<DatabaseConnection>,
+    twitter_handle: &str,
+) -> Option<watched_accounts::Model> {
+    data::read::watched_account(db, twitter_handle).await
+}
+
+pub async fn watch_account(
+    db: &State<DatabaseConnection>,
+    twitter_handle: &str,
+    interval_seconds: i64,
+) -> Result<watched_accounts::Model, String> {
+    if interval_seconds < crate::scheduler::MIN_INTERVAL_SECONDS {
+        return Err(format!(
+            "interval_seconds must be at least {}",
+            crate::scheduler::MIN_INTERVAL_SECONDS
+        ));
+    }
+    let account = match data::read::watched_account(db, twitter_handle).await {
+        Some(account) => watched_accounts::Model {
+            interval_seconds,
+            ..account
+        },
+        None => watched_accounts::Model {
+            username: twitter_handle.to_owned(),
+            interval_seconds,
+            last_synced_at: None,
+            last_seen_tweet_id: None,
+            last_error: None,
+        },
+    };
+    data::write::watched_account(db, &account).await;
+    Ok(account)
+}
+
+pub async fn unwatch_account(db: &State<DatabaseConnection>, twitter_handle: &str) -> bool {
+    data::write::remove_watched_account(db, twitter_handle).await
+}
+
+// Walks up the replies from an archived tweet to the start of its thread, using only what
+// is archived. The walk stops at the first tweet that isn't, keeping it if it's known to
+// be unavailable. Returns None when the tweet itself isn't archived.
 pub async fn load_twitter_conversation_from_tweet_id(
     db: &State<DatabaseConnection>,
     tweet_id: i64,
-) -> ConversationData {
-    let tweet_data = load_tweet_from_id(db, tweet_id).await;
-    let conversation_id = tweet_data
-        .clone()
-        .tweet
-        .unwrap_or_else(|| panic!("The tweet of id {tweet_id} is None"))
-        .conversation_id;
-    let mut conversation: VecDeque<TweetData> = VecDeque::from(vec![tweet_data.clone()])
-        .into_iter()
-        .collect();
-    let mut references = conversation[0].references.clone();
-    while !references.is_empty()
-        && references
-            .clone()
-            .iter()
-            .any(|reference| reference.reference_type == "replied_to")
+) -> Option<ConversationData> {
+    let tweet_data = load_tweet_from_archive(db, tweet_id).await;
+    let conversation_id = tweet_data.tweet.as_ref()?.conversation_id;
+    let mut conversation: VecDeque<TweetData> = VecDeque::from(vec![tweet_data]);
+    while let Some(replied_to_id) = conversation[0]
+        .references
+        .iter()
+        .find(|reference| reference.reference_type == "replied_to")
+        .map(|reference| reference.referenced_tweet_id)
     {
-        let replied_to_id: i64 = references
-            .iter()
-            .find(|reference| reference.reference_type == "replied_to")
-            .expect("Failed to find the replied to tweet")
-            .referenced_tweet_id;
-        conversation.push_front(load_tweet_from_id(db, replied_to_id).await);
-        references = conversation[0].clone().references;
+        let replied_to = load_tweet_from_archive(db, replied_to_id).await;
+        if replied_to.tweet.is_none() && replied_to.status.is_none() {
+            break;
+        }
+        conversation.push_front(replied_to);
     }
-    ConversationData {
+    let ids: Vec<i64> = conversation
+        .iter()
+        .filter_map(|tweet_data| tweet_data.tweet.as_ref().map(|tweet| tweet.id))
+        .collect();
+    let hidden: HashSet<i64> = data::read::hidden_tweet_ids(db, &ids)
+        .await
+        .into_iter()
+        .collect();
+    Some(ConversationData {
         id: conversation_id,
-        tweets: Vec::from(conversation),
-    }
+        tweets: conversation
+            .into_iter()
+            .filter(|tweet_data| {
+                tweet_data
+                    .tweet
+                    .as_ref()
+                    .is_none_or(|tweet| !hidden.contains(&tweet.id))
+            })
+            .collect(),
+        annotations: Vec::new(),
+    })
 }
 pub async fn search_tweets_in_db(
     db: &State<DatabaseConnection>,
     search_query: &str,
+    filter: &data::read::SearchFilter,
 ) -> Vec<TweetData> {
-    data::read::search_tweets_in_db(db, search_query).await
+    data::read::search_tweets_in_db(db, search_query, filter).await
 }
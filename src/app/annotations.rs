@@ -0,0 +1,190 @@
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use rocket::State;
+use sea_orm::DatabaseConnection;
+
+use super::data;
+use super::data::entities::annotations;
+use crate::utils::{ConversationData, TweetData};
+
+// What an annotation is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    Tweet(i64),
+    Conversation(i64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnnotationError {
+    NoSuchAnnotation(i32),
+    TweetNotArchived(i64),
+    ConversationNotArchived(i64),
+    NotTheAuthor(i32),
+    EmptyBody,
+}
+
+impl AnnotationError {
+    // Whether the error is about something that doesn't exist, rather than a bad request.
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            Self::NoSuchAnnotation(_)
+                | Self::TweetNotArchived(_)
+                | Self::ConversationNotArchived(_)
+        )
+    }
+}
+
+impl fmt::Display for AnnotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSuchAnnotation(id) => write!(f, "annotation {id}"),
+            Self::TweetNotArchived(id) => write!(f, "tweet of id {id}"),
+            Self::ConversationNotArchived(id) => write!(f, "conversation {id}"),
+            Self::NotTheAuthor(id) => write!(f, "Only the author of annotation {id} can change it"),
+            Self::EmptyBody => f.write_str("An annotation can't be empty"),
+        }
+    }
+}
+
+pub async fn annotation(
+    db: &State<DatabaseConnection>,
+    id: i32,
+) -> Result<annotations::Model, AnnotationError> {
+    data::read::annotation(db, id)
+        .await
+        .ok_or(AnnotationError::NoSuchAnnotation(id))
+}
+
+// Notes the markdown `body` on an archived tweet or conversation as `author`.
+pub async fn annotate(
+    db: &State<DatabaseConnection>,
+    anchor: Anchor,
+    author: &str,
+    body: &str,
+) -> Result<annotations::Model, AnnotationError> {
+    let body = valid_body(body)?;
+    let (tweet_id, conversation_id) = match anchor {
+        Anchor::Tweet(id) => {
+            if !data::read::does_tweet_exist(db, id).await {
+                return Err(AnnotationError::TweetNotArchived(id));
+            }
+            (Some(id), None)
+        }
+        Anchor::Conversation(id) => {
+            if !data::read::does_conversation_exist(db, id).await {
+                return Err(AnnotationError::ConversationNotArchived(id));
+            }
+            (None, Some(id))
+        }
+    };
+    Ok(data::write::annotation(
+        db,
+        &annotations::Model {
+            id: 0,
+            author: author.to_owned(),
+            tweet_id,
+            conversation_id,
+            body,
+            created_at: now(),
+            updated_at: now(),
+        },
+    )
+    .await)
+}
+
+// Replaces the annotation's text, which only its author may do.
+pub async fn edit(
+    db: &State<DatabaseConnection>,
+    id: i32,
+    author: &str,
+    body: &str,
+) -> Result<annotations::Model, AnnotationError> {
+    let body = valid_body(body)?;
+    let annotation = authored(db, id, author).await?;
+    let updated_at = now();
+    data::write::annotation_body(db, id, &body, updated_at).await;
+    Ok(annotations::Model {
+        body,
+        updated_at,
+        ..annotation
+    })
+}
+
+pub async fn delete(
+    db: &State<DatabaseConnection>,
+    id: i32,
+    author: &str,
+) -> Result<bool, AnnotationError> {
+    authored(db, id, author).await?;
+    Ok(data::write::remove_annotation(db, id).await)
+}
+
+pub async fn search(db: &State<DatabaseConnection>, query: &str) -> Vec<annotations::Model> {
+    data::read::search_annotations(db, query).await
+}
+
+pub async fn annotated_tweet(db: &State<DatabaseConnection>, tweet_data: TweetData) -> TweetData {
+    annotated_tweets(db, vec![tweet_data])
+        .await
+        .pop()
+        .unwrap_or_else(|| panic!("Failed to annotate a tweet"))
+}
+
+pub async fn annotated_tweets(
+    db: &State<DatabaseConnection>,
+    tweets: Vec<TweetData>,
+) -> Vec<TweetData> {
+    let ids: Vec<i64> = tweets
+        .iter()
+        .filter_map(|tweet_data| tweet_data.tweet.as_ref().map(|tweet| tweet.id))
+        .collect();
+    let mut annotations = data::read::tweet_annotations(db, &ids).await;
+    tweets
+        .into_iter()
+        .map(|tweet_data| TweetData {
+            annotations: tweet_data
+                .tweet
+                .as_ref()
+                .and_then(|tweet| annotations.remove(&tweet.id))
+                .unwrap_or_default(),
+            ..tweet_data
+        })
+        .collect()
+}
+
+// Fills in the notes on the conversation and on each of its tweets.
+pub async fn annotated_conversation(
+    db: &State<DatabaseConnection>,
+    conversation: ConversationData,
+) -> ConversationData {
+    ConversationData {
+        annotations: data::read::conversation_annotations(db, conversation.id).await,
+        tweets: annotated_tweets(db, conversation.tweets).await,
+        ..conversation
+    }
+}
+
+async fn authored(
+    db: &State<DatabaseConnection>,
+    id: i32,
+    author: &str,
+) -> Result<annotations::Model, AnnotationError> {
+    let annotation = annotation(db, id).await?;
+    if annotation.author != author {
+        return Err(AnnotationError::NotTheAuthor(id));
+    }
+    Ok(annotation)
+}
+
+fn valid_body(body: &str) -> Result<String, AnnotationError> {
+    match body.trim() {
+        "" => Err(AnnotationError::EmptyBody),
+        _body => Ok(body.to_owned()),
+    }
+}
+
+fn now() -> DateTime<FixedOffset> {
+    Utc::now().with_timezone(&FixedOffset::east(0))
+}
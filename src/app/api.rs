@@ -1,19 +1,22 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 use futures::future::join_all;
-use rocket::time::OffsetDateTime;
+use twitter_v2::api_result::{ApiError, PaginableApiResponse};
 use twitter_v2::authorization::BearerToken;
 use twitter_v2::query::{TweetField, UserField};
-use twitter_v2::{Tweet, TwitterApi, User};
+use twitter_v2::{Tweet, TwitterApi};
 
-use crate::utils::{TweetData, UserData, i64_to_u64};
+use crate::utils::{TweetData, TweetStatusKind, UserData, i64_to_u64};
 
-pub async fn get_tweets_from_user(user_data: &UserData) -> Vec<TweetData> {
+pub async fn get_new_tweets_from_user(user_data: &UserData, since_id: Option<i64>) -> Vec<TweetData> {
     let user = user_data.user.clone().unwrap_or_else(||panic!("Failed to get user, let alone their tweets"));
     let twitter_handle = &user.username;
-    let api_tweets:Vec<Tweet> =load_api()
-        .await
-        .get_user_tweets(i64_to_u64(user.id))
+    let api = load_api().await;
+    let mut request = api.get_user_tweets(i64_to_u64(user.id));
+    request
         .max_results(100)
         .tweet_fields([
             TweetField::Attachments,
@@ -21,129 +24,43 @@ pub async fn get_tweets_from_user(user_data: &UserData) -> Vec<TweetData> {
             TweetField::AuthorId,
             TweetField::ConversationId,
             TweetField::CreatedAt,
-        ])
-        .send()
-        .await
-        .unwrap_or_else(|error| {
-            panic!(
-                "Failed to get @{twitter_handle}'s tweets from twitter api. \n\nError: {:?}",
-                error
-            )
-        })
-        .into_data()
-        .unwrap_or_else(|| {
-            panic!("Failed to open @{twitter_handle}'s tweets after fetching them from the server.")
-        });
-    join_all(api_tweets.into_iter().map(|api_tweet|TweetData::from_api_tweet(Some(api_tweet)))).await
-}
-
-pub async fn get_latest_tweet_from_user(user_data: &UserData) -> TweetData {
-    let user = user_data.user.clone().unwrap_or_else(||panic!("Failed to get user, let alone their tweets"));
-    let twitter_handle = &user.username;
-    let api_tweet =load_api()
-        .await
-        .get_user_tweets(i64_to_u64(user.id))
-        .max_results(5)
-        .tweet_fields([
-            TweetField::Attachments,
-            TweetField::ReferencedTweets,
-            TweetField::AuthorId,
-            TweetField::ConversationId,
-            TweetField::CreatedAt,
-        ])
-        .send()
-        .await
-        .unwrap_or_else(|error| {
-            panic!(
-                "User @{twitter_handle}'s tweets not loading. \n\nError: {:?}",
-                error
-            )
-        })
-        .into_data()
-        .unwrap_or_else(|| {
-            panic!("Failed to open @{twitter_handle}'s tweets after fetching them from the server.")
-        })
-        .first()
-        .unwrap_or_else(|| panic!("Failed to get @{twitter_handle}'s latest tweet."))
-        .clone();
-    TweetData::from_api_tweet(Some(api_tweet)).await
-}
-
-pub async fn get_new_tweets_from_user(user_data: &UserData, from: &OffsetDateTime) -> Vec<TweetData> {
-    let user = user_data.user.clone().unwrap_or_else(||panic!("Failed to get user, let alone their tweets"));
-    let twitter_handle = &user.username;
-    let api_tweets:Vec<Tweet> =load_api()
-        .await
-        .get_user_tweets(i64_to_u64(user.id))
-        .start_time(*from)
-        .tweet_fields([
-            TweetField::Attachments,
-            TweetField::ReferencedTweets,
-            TweetField::AuthorId,
-            TweetField::ConversationId,
-            TweetField::CreatedAt,
-        ])
+        ]);
+    if let Some(since_id) = since_id {
+        request.since_id(i64_to_u64(since_id));
+    }
+    let mut response = request
         .send()
         .await
-        .unwrap_or_else(|error|panic!("Failed to get @{twitter_handle}'s tweets. \n\nError: {:?}", error))
-        .into_data()
-        .unwrap_or_else(|| {
-            panic!("Failed to open @{twitter_handle}'s new tweets after fetching them from the server.")
-        });
+        .unwrap_or_else(|error|panic!("Failed to get @{twitter_handle}'s tweets. \n\nError: {:?}", error));
+    let mut api_tweets: Vec<Tweet> = Vec::new();
+    loop {
+        // the api sends no data when there are no new tweets
+        api_tweets.extend(response.data().cloned().unwrap_or_default());
+        match response
+            .next_page()
+            .await
+            .unwrap_or_else(|error|panic!("Failed to get the next page of @{twitter_handle}'s tweets. \n\nError: {:?}", error))
+        {
+            Some(next_page) => {
+                // Pages after the first are fetched without going through load_api.
+                record_call();
+                response = next_page
+            }
+            None => break,
+        }
+    }
     join_all(api_tweets.into_iter().map(|api_tweet|TweetData::from_api_tweet(Some(api_tweet)))).await
 }
 
 
-pub async fn get_first_hundred_tweets_from_user(user: &User) -> Vec<TweetData> {
-    let twitter_handle = &user.username;
-    let api_tweets:Vec<Tweet> =load_api()
-        .await
-        .get_user_tweets(user.id)
-        .max_results(100) //this line gets the max results
-        .tweet_fields([
-            TweetField::Attachments,
-            TweetField::ReferencedTweets,
-            TweetField::AuthorId,
-            TweetField::ConversationId,
-            TweetField::CreatedAt,
-        ])
-        .send()
-        .await
-        .unwrap_or_else(|error| panic!("Failed to load @{twitter_handle}'s first hundred tweets from the twitter api. \n\nError: {:?}", error))
-        .into_data()
-        .unwrap_or_else(|| {
-            panic!("Failed to open @{twitter_handle}'s first hunrdred tweets after fetching them from the twitter api.")
-        });
-    join_all(api_tweets.into_iter().map(|api_tweet|TweetData::from_api_tweet(Some(api_tweet)))).await
-}
-
-pub async fn get_tweets_from_user_until_id(user: &User, id: u64) -> Vec<TweetData> {
-    let twitter_handle = &user.username;
-    let api_tweets:Vec<Tweet> =load_api()
-        .await
-        .get_user_tweets(user.id)
-        .max_results(100) 
-        .until_id(id)
-        .tweet_fields([
-            TweetField::Attachments,
-            TweetField::ReferencedTweets,
-            TweetField::AuthorId,
-            TweetField::ConversationId,
-            TweetField::CreatedAt,
-        ])
-        .send()
-        .await
-        .unwrap_or_else(|error| panic!("Failed to get this batch of @{twitter_handle}'s tweets from the twitter api. \n\nError: {:?}", error))
-        .into_data()
-        .unwrap_or_else(|| {
-            panic!("Failed to open this batch of @{twitter_handle}'s tweets after fetching them from the twitter api.")
-        });
-    join_all(api_tweets.into_iter().map(|api_tweet|TweetData::from_api_tweet(Some(api_tweet)))).await
+pub async fn get_tweet_by_id(id: u64) -> TweetData {
+    get_tweet_by_id_with_errors(id).await.0
 }
 
-pub async fn get_tweet_by_id(id: u64) -> TweetData {
+// Also returns the errors the api gave in place of the tweet, which say why it's missing.
+pub async fn get_tweet_by_id_with_errors(id: u64) -> (TweetData, Vec<ApiError>) {
 
-    let api_tweet = match load_api()
+    let payload = match load_api()
         .await
         .get_tweet(id)
         .tweet_fields([
@@ -155,7 +72,7 @@ pub async fn get_tweet_by_id(id: u64) -> TweetData {
         ])
         .send()
         .await{
-            Ok(tweet_response) => tweet_response.into_data(),
+            Ok(tweet_response) => tweet_response.into_payload(),
             Err(error) => {
 
             println!("Failed to get tweet of id {id} from the twitter api. \n\nError: {:?}\n\nWaiting 15 minutes and trying again...", error);
@@ -174,12 +91,71 @@ pub async fn get_tweet_by_id(id: u64) -> TweetData {
                 .send()
                 .await
                 .unwrap_or_else(|error|panic!("Second Attempt: Failed to get tweet of id {id} from the twitter api. \n\nError: {:?}", error))
-                .into_data()
+                .into_payload()
             }
         };
 
-    TweetData::from_api_tweet(api_tweet).await
+    let errors = payload.errors().unwrap_or_default().to_vec();
+    (TweetData::from_api_tweet(payload.into_data()).await, errors)
+
+}
+
+// Looks up to 100 tweets in one request, returning the ones that are still available and
+// the errors given for the rest.
+pub async fn get_tweets_by_ids_with_errors(ids: &[u64]) -> (Vec<TweetData>, Vec<ApiError>) {
+    let payload = load_api()
+        .await
+        .get_tweets(ids.iter().copied())
+        .tweet_fields([
+            TweetField::Attachments,
+            TweetField::ReferencedTweets,
+            TweetField::ConversationId,
+            TweetField::AuthorId,
+            TweetField::CreatedAt,
+        ])
+        .send()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to look up tweets {:?} in the twitter api. \n\nError: {:?}", ids, error))
+        .into_payload();
+    let errors = payload.errors().unwrap_or_default().to_vec();
+    let tweets = join_all(
+        payload
+            .into_data()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|api_tweet| TweetData::from_api_tweet(Some(api_tweet))),
+    )
+    .await;
+    (tweets, errors)
+}
 
+// Works out why the api didn't return a tweet, from the error that names it. Deleted tweets
+// are reported the same way as ids that never existed, so a missing tweet only counts as
+// deleted if it was archived.
+pub fn tweet_status_from_errors(
+    id: u64,
+    errors: &[ApiError],
+    was_archived: bool,
+) -> (TweetStatusKind, Option<String>) {
+    let id = id.to_string();
+    let error = errors.iter().find(|error| {
+        error
+            .detail
+            .split(|c: char| !c.is_ascii_digit())
+            .any(|number| number == id)
+    });
+    let detail = error.map(|error| format!("{}: {}", error.title, error.detail));
+    let kind = match error {
+        Some(error) if error.kind.ends_with("not-authorized-for-resource") => {
+            TweetStatusKind::Protected
+        }
+        Some(error) if error.detail.to_lowercase().contains("suspended") => {
+            TweetStatusKind::Suspended
+        }
+        _ if was_archived => TweetStatusKind::Deleted,
+        _ => TweetStatusKind::NotFound,
+    };
+    (kind, detail)
 }
 
 
@@ -215,7 +191,85 @@ pub async fn get_user_by_id(id: u64) -> UserData {
     UserData::from_api_user(&api_user).await
 }
 
+tokio::task_local! {
+    // Where the api calls made while doing some work are counted, see `attributed`.
+    static UPSTREAM_CALLS: Arc<AtomicUsize>;
+}
+
+// Runs `work`, counting every api call it makes into `calls`, so they can be put down to
+// whoever asked for the work.
+pub async fn attributed<F: Future>(calls: Arc<AtomicUsize>, work: F) -> F::Output {
+    UPSTREAM_CALLS.scope(calls, work).await
+}
+
+fn record_call() {
+    // Work nobody in particular asked for, like the scheduler's syncs, isn't counted.
+    let _ = UPSTREAM_CALLS.try_with(|calls| calls.fetch_add(1, Ordering::Relaxed));
+}
+
+// Each call to the api starts here, so this is where they're counted.
 pub async fn load_api() -> TwitterApi<BearerToken> {
-    let auth = BearerToken::new(std::env::var("TWITTER_DEV_BEARER_TOKEN").unwrap());
+    record_call();
+    let auth = BearerToken::new(std::env::var("TWITTER_DEV_BEARER_TOKEN").unwrap_or_else(|_error| {
+        panic!("TWITTER_DEV_BEARER_TOKEN must be set unless OFFLINE_MODE is enabled")
+    }));
     TwitterApi::new(auth)
 }
+
+// In offline mode the server only serves what is already archived and never calls the twitter api.
+pub fn is_offline() -> bool {
+    std::env::var("OFFLINE_MODE")
+        .map(|value| value == "true" || value == "1")
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(title: &str, kind: &str, detail: &str) -> ApiError {
+        ApiError {
+            title: title.to_string(),
+            kind: kind.to_string(),
+            detail: detail.to_string(),
+            ..ApiError::default()
+        }
+    }
+
+    #[test]
+    fn each_tweet_gets_the_error_that_names_it() {
+        let errors = [
+            error(
+                "Authorization Error",
+                "https://api.twitter.com/2/problems/not-authorized-for-resource",
+                "Sorry, you are not authorized to see the Tweet with id: [12].",
+            ),
+            error(
+                "Not Found Error",
+                "https://api.twitter.com/2/problems/resource-not-found",
+                "Could not find tweet with ids: [1234].",
+            ),
+        ];
+        let (kind, detail) = tweet_status_from_errors(12, &errors, true);
+        assert_eq!(kind, TweetStatusKind::Protected);
+        assert_eq!(
+            detail.as_deref(),
+            Some("Authorization Error: Sorry, you are not authorized to see the Tweet with id: [12].")
+        );
+        let (kind, detail) = tweet_status_from_errors(1234, &errors, false);
+        assert_eq!(kind, TweetStatusKind::NotFound);
+        assert_eq!(
+            detail.as_deref(),
+            Some("Not Found Error: Could not find tweet with ids: [1234].")
+        );
+        // Neither error names tweet 1, even though both contain its digits.
+        assert_eq!(
+            tweet_status_from_errors(1, &errors, true),
+            (TweetStatusKind::Deleted, None)
+        );
+        assert_eq!(
+            tweet_status_from_errors(1, &errors, false),
+            (TweetStatusKind::NotFound, None)
+        );
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek};
+
+use chrono::{DateTime, FixedOffset};
+use rocket::State;
+use sea_orm::DatabaseConnection;
+use serde_json::Value;
+
+use super::data;
+use super::data::entities::{tweet_references, tweets, users};
+use crate::utils::{TweetData, UserData};
+
+// Imports the account and tweets from a twitter archive zip (the one you download from
+// your twitter settings) and returns how many tweets were read from it. Nothing is written
+// when the archive is missing a file or has one that can't be read.
+pub async fn import(db: &State<DatabaseConnection>, zip_path: &str) -> Result<usize, String> {
+    let file = File::open(zip_path)
+        .map_err(|error| format!("Failed to open archive {zip_path}. Error: {:?}", error))?;
+    let mut zip = zip::ZipArchive::new(file)
+        .map_err(|error| format!("Failed to read archive {zip_path}. Error: {:?}", error))?;
+    import_zip(db, &mut zip).await
+}
+
+async fn import_zip<R: Read + Seek>(
+    db: &State<DatabaseConnection>,
+    zip: &mut zip::ZipArchive<R>,
+) -> Result<usize, String> {
+    let accounts = read_archive_file(zip, &["data/account.js"])?;
+    let account = &accounts
+        .first()
+        .ok_or("data/account.js in the archive has no account")?["account"];
+    let profile = read_archive_file(zip, &["data/profile.js"])?;
+    let user = users::Model {
+        id: parse_id(&account["accountId"])?,
+        name: json_string(&account["accountDisplayName"]),
+        username: json_string(&account["username"]),
+        description: profile
+            .first()
+            .map(|profile| json_string(&profile["profile"]["description"]["bio"]))
+            .unwrap_or_default(),
+    };
+    let mut archive_tweets: Vec<(i64, Value)> = Vec::new();
+    for name in tweet_file_names(zip)? {
+        for entry in read_named_file(zip, &name)? {
+            archive_tweets.push((parse_id(&entry["tweet"]["id_str"])?, entry["tweet"].clone()));
+        }
+    }
+    archive_tweets.sort_by_key(|(id, _tweet)| *id);
+    println!("Importing archive of @{}", user.username);
+    data::write::user(db, &UserData { user: Some(user.clone()) }).await;
+
+    // Archives don't include conversation ids, so replies take their parent's conversation
+    // when the parent has been seen and fall back to the parent's id otherwise.
+    let mut conversation_ids: HashMap<i64, i64> = HashMap::new();
+    let mut tweets_to_write: Vec<TweetData> = Vec::new();
+    for (id, archive_tweet) in archive_tweets {
+        let replied_to_id = archive_tweet["in_reply_to_status_id_str"]
+            .as_str()
+            .and_then(|replied_to_id| replied_to_id.parse::<i64>().ok());
+        let conversation_id = match replied_to_id {
+            Some(replied_to_id) => match conversation_ids.get(&replied_to_id) {
+                Some(conversation_id) => *conversation_id,
+                None => data::read::tweet_by_id(db, replied_to_id)
+                    .await
+                    .tweet
+                    .map(|tweet| tweet.conversation_id)
+                    .unwrap_or(replied_to_id),
+            },
+            None => id,
+        };
+        conversation_ids.insert(id, conversation_id);
+
+        let tweet_data = TweetData::new(
+            tweets::Model {
+                id,
+                content: json_string(&archive_tweet["full_text"]),
+                author_id: user.id,
+                conversation_id,
+                created_at: parse_archive_date(&json_string(&archive_tweet["created_at"]))?,
+            },
+            replied_to_id
+                .map(|replied_to_id| tweet_references::Model {
+                    source_tweet_id: id,
+                    reference_type: "replied_to".to_string(),
+                    referenced_tweet_id: replied_to_id,
+                })
+                .into_iter()
+                .collect(),
+        );
+        tweets_to_write.push(TweetData {
+            edit_history_tweet_ids: edit_history_tweet_ids(&archive_tweet)?,
+            ..tweet_data
+        });
+    }
+
+    data::write::tweets(db, &tweets_to_write).await;
+    Ok(tweets_to_write.len())
+}
+
+// Tweets that were edited list every version's id under edit_info, in `initial` for the
+// first version and in `edit.editControlInitial` for the edits.
+fn edit_history_tweet_ids(archive_tweet: &Value) -> Result<Vec<i64>, String> {
+    let edit_info = &archive_tweet["edit_info"];
+    let edit_tweet_ids = match &edit_info["initial"]["editTweetIds"] {
+        Value::Null => &edit_info["edit"]["editControlInitial"]["editTweetIds"],
+        edit_tweet_ids => edit_tweet_ids,
+    };
+    let ids: Vec<i64> = edit_tweet_ids
+        .as_array()
+        .map(|ids| ids.iter().map(parse_id).collect())
+        .transpose()?
+        .unwrap_or_default();
+    // Tweets that were never edited list just their own id.
+    if ids.len() > 1 {
+        Ok(ids)
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+// Large archives split the tweets across data/tweets.js, data/tweets-part1.js,
+// data/tweets-part2.js and so on, and older ones call them tweet rather than tweets.
+fn tweet_file_names<R: Read + Seek>(zip: &zip::ZipArchive<R>) -> Result<Vec<String>, String> {
+    let mut parts: Vec<(u32, String)> = zip
+        .file_names()
+        .filter_map(|name| {
+            let stem = name.strip_prefix("data/")?.strip_suffix(".js")?;
+            let part = stem
+                .strip_prefix("tweets")
+                .or_else(|| stem.strip_prefix("tweet"))?;
+            match part.strip_prefix("-part") {
+                None if part.is_empty() => Some((0, name.to_owned())),
+                None => None,
+                Some(number) => Some((number.parse().ok()?, name.to_owned())),
+            }
+        })
+        .collect();
+    if parts.is_empty() {
+        return Err("Failed to find data/tweets.js in the archive".to_string());
+    }
+    parts.sort();
+    Ok(parts.into_iter().map(|(_part, name)| name).collect())
+}
+
+// Archive files are javascript of the form `window.YTD.tweets.part0 = [...]`.
+fn read_archive_file<R: Read + Seek>(
+    zip: &mut zip::ZipArchive<R>,
+    names: &[&str],
+) -> Result<Vec<Value>, String> {
+    let name = names
+        .iter()
+        .find(|name| zip.by_name(name).is_ok())
+        .ok_or_else(|| format!("Failed to find any of {:?} in the archive", names))?;
+    read_named_file(zip, name)
+}
+
+fn read_named_file<R: Read + Seek>(
+    zip: &mut zip::ZipArchive<R>,
+    name: &str,
+) -> Result<Vec<Value>, String> {
+    let mut contents = String::new();
+    zip.by_name(name)
+        .map_err(|error| format!("Failed to open {name} in the archive. Error: {:?}", error))?
+        .read_to_string(&mut contents)
+        .map_err(|error| format!("Failed to read {name} in the archive. Error: {:?}", error))?;
+    let json = contents
+        .split_once('=')
+        .map(|(_assignment, json)| json)
+        .unwrap_or(&contents);
+    serde_json::from_str(json)
+        .map_err(|error| format!("Failed to parse {name} in the archive. Error: {:?}", error))
+}
+
+fn json_string(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+fn parse_id(value: &Value) -> Result<i64, String> {
+    value
+        .as_str()
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| format!("Failed to parse id {:?} from the archive", value))
+}
+
+fn parse_archive_date(date: &str) -> Result<DateTime<FixedOffset>, String> {
+    DateTime::parse_from_str(date, "%a %b %d %H:%M:%S %z %Y").map_err(|error| {
+        format!(
+            "Failed to parse date {:?} from the archive. Error: {:?}",
+            date, error
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::data::setup;
+    use std::io::{Cursor, Write};
+
+    fn archive_tweet(id: i64, replied_to: Option<i64>) -> String {
+        let replied_to = replied_to
+            .map(|replied_to| format!(r#", "in_reply_to_status_id_str": "{replied_to}""#))
+            .unwrap_or_default();
+        format!(
+            r#"{{"tweet": {{"id_str": "{id}", "full_text": "Tweet {id}", "created_at": "Wed Jan 01 12:00:00 +0000 2020"{replied_to}}}}}"#
+        )
+    }
+
+    // An archive big enough that twitter split its tweets over three files, along with one
+    // that only looks like them.
+    fn split_archive() -> zip::ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let files = [
+            (
+                "data/account.js",
+                r#"window.YTD.account.part0 = [{"account": {"accountId": "7", "username": "archivist", "accountDisplayName": "Archivist"}}]"#.to_string(),
+            ),
+            (
+                "data/profile.js",
+                r#"window.YTD.profile.part0 = [{"profile": {"description": {"bio": "Keeps things"}}}]"#.to_string(),
+            ),
+            (
+                "data/tweets.js",
+                format!("window.YTD.tweets.part0 = [{}]", archive_tweet(1, None)),
+            ),
+            (
+                "data/tweets-part1.js",
+                format!("window.YTD.tweets.part1 = [{}]", archive_tweet(2, Some(1))),
+            ),
+            (
+                "data/tweets-part2.js",
+                format!(
+                    "window.YTD.tweets.part2 = [{}, {}]",
+                    archive_tweet(3, Some(2)),
+                    archive_tweet(4, None)
+                ),
+            ),
+            ("data/tweetdeck.js", "not an archive file".to_string()),
+        ];
+        for (name, contents) in files {
+            writer
+                .start_file(name, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        zip::ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn every_part_of_the_tweets_is_found_in_order() {
+        assert_eq!(
+            tweet_file_names(&split_archive()).unwrap(),
+            vec!["data/tweets.js", "data/tweets-part1.js", "data/tweets-part2.js"]
+        );
+    }
+
+    #[tokio::test]
+    async fn split_archives_import_every_part() {
+        let db = setup::test_db("split-archive").await;
+        let state = State::from(&db);
+        assert_eq!(import_zip(state, &mut split_archive()).await, Ok(4));
+
+        let user = data::read::user_by_id(state, 7).await.user.unwrap();
+        assert_eq!(user.username, "archivist");
+        assert_eq!(user.description, "Keeps things");
+        for id in 1..=4 {
+            assert!(data::read::does_tweet_exist(state, id).await, "tweet {id}");
+        }
+        let reply = data::read::tweet_by_id(state, 3).await;
+        assert_eq!(reply.tweet.unwrap().conversation_id, 1);
+        assert_eq!(reply.references[0].referenced_tweet_id, 2);
+    }
+}
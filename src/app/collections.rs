@@ -0,0 +1,216 @@
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use rocket::State;
+use sea_orm::DatabaseConnection;
+
+use super::data;
+use super::data::entities::{collection_tweets, collections, tweet_tags};
+use crate::utils::{CollectionData, CollectionSummary, TagCount};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CollectionError {
+    NoSuchCollection(i32),
+    TweetNotArchived(i64),
+    NameTaken(String),
+    EmptyName,
+    EmptyTag,
+}
+
+impl CollectionError {
+    // Whether the error is about something that doesn't exist, rather than a bad request.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Self::NoSuchCollection(_) | Self::TweetNotArchived(_))
+    }
+}
+
+impl fmt::Display for CollectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSuchCollection(id) => write!(f, "collection {id}"),
+            Self::TweetNotArchived(id) => write!(f, "tweet of id {id}"),
+            Self::NameTaken(name) => write!(f, "There is already a collection called {name}"),
+            Self::EmptyName => f.write_str("A collection needs a name"),
+            Self::EmptyTag => f.write_str("A tag can't be empty"),
+        }
+    }
+}
+
+pub async fn list(db: &State<DatabaseConnection>) -> Vec<CollectionSummary> {
+    data::read::collections(db).await
+}
+
+pub async fn load(
+    db: &State<DatabaseConnection>,
+    id: i32,
+) -> Result<CollectionData, CollectionError> {
+    let collection = existing(db, id).await?;
+    Ok(CollectionData {
+        tweets: data::read::collected_tweets(db, id).await,
+        collection,
+    })
+}
+
+pub async fn create(
+    db: &State<DatabaseConnection>,
+    name: &str,
+    description: Option<&str>,
+) -> Result<collections::Model, CollectionError> {
+    let name = valid_name(db, name, None).await?;
+    Ok(data::write::collection(
+        db,
+        &collections::Model {
+            id: 0,
+            name,
+            description: description.map(str::to_owned),
+            created_at: now(),
+            updated_at: now(),
+        },
+    )
+    .await)
+}
+
+// Renames the collection or changes its description, leaving whichever isn't given as it is.
+pub async fn update(
+    db: &State<DatabaseConnection>,
+    id: i32,
+    name: Option<&str>,
+    description: Option<&str>,
+) -> Result<collections::Model, CollectionError> {
+    let collection = existing(db, id).await?;
+    let name = match name {
+        Some(name) => valid_name(db, name, Some(id)).await?,
+        None => collection.name,
+    };
+    let collection = collections::Model {
+        name,
+        description: description.map(str::to_owned).or(collection.description),
+        updated_at: now(),
+        ..collection
+    };
+    data::write::update_collection(db, &collection).await;
+    Ok(collection)
+}
+
+// Returns whether there was such a collection. Its tweets stay archived.
+pub async fn delete(db: &State<DatabaseConnection>, id: i32) -> bool {
+    data::write::remove_collection(db, id).await
+}
+
+// Adds an archived tweet to the collection, or changes its note if it's already in it.
+pub async fn add_tweet(
+    db: &State<DatabaseConnection>,
+    id: i32,
+    tweet_id: i64,
+    note: Option<&str>,
+) -> Result<collection_tweets::Model, CollectionError> {
+    existing(db, id).await?;
+    if !data::read::does_tweet_exist(db, tweet_id).await {
+        return Err(CollectionError::TweetNotArchived(tweet_id));
+    }
+    let added_at = match data::read::collection_tweet(db, id, tweet_id).await {
+        Some(entry) => entry.added_at,
+        None => now(),
+    };
+    let entry = collection_tweets::Model {
+        collection_id: id,
+        tweet_id,
+        note: note.map(str::to_owned),
+        added_at,
+    };
+    data::write::collection_tweet(db, &entry).await;
+    data::write::collection_updated(db, id, now()).await;
+    Ok(entry)
+}
+
+// Returns whether the tweet was in the collection.
+pub async fn remove_tweet(
+    db: &State<DatabaseConnection>,
+    id: i32,
+    tweet_id: i64,
+) -> Result<bool, CollectionError> {
+    existing(db, id).await?;
+    let removed = data::write::remove_collection_tweet(db, id, tweet_id).await;
+    if removed {
+        data::write::collection_updated(db, id, now()).await;
+    }
+    Ok(removed)
+}
+
+pub async fn tags(db: &State<DatabaseConnection>) -> Vec<TagCount> {
+    data::read::tags(db).await
+}
+
+pub async fn tweet_tags(db: &State<DatabaseConnection>, tweet_id: i64) -> Vec<String> {
+    data::read::tags_of_tweets(db, &[tweet_id])
+        .await
+        .remove(&tweet_id)
+        .unwrap_or_default()
+}
+
+// Returns whether the tweet didn't have the tag already.
+pub async fn tag_tweet(
+    db: &State<DatabaseConnection>,
+    tweet_id: i64,
+    tag: &str,
+) -> Result<bool, CollectionError> {
+    let tag = normalize_tag(tag)?;
+    if !data::read::does_tweet_exist(db, tweet_id).await {
+        return Err(CollectionError::TweetNotArchived(tweet_id));
+    }
+    Ok(data::write::tweet_tag(
+        db,
+        &tweet_tags::Model {
+            tweet_id,
+            tag,
+            tagged_at: now(),
+        },
+    )
+    .await)
+}
+
+// Returns whether the tweet had the tag.
+pub async fn untag_tweet(
+    db: &State<DatabaseConnection>,
+    tweet_id: i64,
+    tag: &str,
+) -> Result<bool, CollectionError> {
+    Ok(data::write::remove_tweet_tag(db, tweet_id, &normalize_tag(tag)?).await)
+}
+
+// Tags are matched the way they're stored, so filters go through this too.
+pub fn normalize_tag(tag: &str) -> Result<String, CollectionError> {
+    match tag.trim() {
+        "" => Err(CollectionError::EmptyTag),
+        tag => Ok(tag.to_lowercase()),
+    }
+}
+
+async fn existing(
+    db: &State<DatabaseConnection>,
+    id: i32,
+) -> Result<collections::Model, CollectionError> {
+    data::read::collection(db, id)
+        .await
+        .ok_or(CollectionError::NoSuchCollection(id))
+}
+
+// The trimmed name, unless it's empty or another collection than `id` has it.
+async fn valid_name(
+    db: &State<DatabaseConnection>,
+    name: &str,
+    id: Option<i32>,
+) -> Result<String, CollectionError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(CollectionError::EmptyName);
+    }
+    match data::read::collection_by_name(db, name).await {
+        Some(other) if Some(other.id) != id => Err(CollectionError::NameTaken(name.to_owned())),
+        _ => Ok(name.to_owned()),
+    }
+}
+
+fn now() -> DateTime<FixedOffset> {
+    Utc::now().with_timezone(&FixedOffset::east(0))
+}
@@ -2,10 +2,22 @@
 
 pub mod prelude;
 
+pub mod annotations;
+pub mod api_keys;
+pub mod audit_log;
+pub mod collection_tweets;
+pub mod collections;
 pub mod conversations;
+pub mod embeddings;
+pub mod hidden_tweets;
+pub mod redacted_users;
 pub mod seaql_migrations;
 
 pub mod tweet_references;
+pub mod tweet_status;
+pub mod tweet_tags;
+pub mod tweet_versions;
 pub mod tweets;
 
 pub mod users;
+pub mod watched_accounts;
@@ -1,9 +1,22 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.8.0
 
+pub use super::annotations::Entity as Annotations;
+pub use super::api_keys::Entity as ApiKeys;
+pub use super::audit_log::Entity as AuditLog;
+pub use super::collection_tweets::Entity as CollectionTweets;
+pub use super::collections::Entity as Collections;
 pub use super::conversations::Entity as Conversations;
+pub use super::embeddings::Entity as Embeddings;
+pub use super::hidden_tweets::Entity as HiddenTweets;
+pub use super::redacted_users::Entity as RedactedUsers;
+#[allow(unused_imports)]
 pub use super::seaql_migrations::Entity as SeaqlMigrations;
 
 pub use super::tweet_references::Entity as TweetReferences;
+pub use super::tweet_status::Entity as TweetStatus;
+pub use super::tweet_tags::Entity as TweetTags;
+pub use super::tweet_versions::Entity as TweetVersions;
 pub use super::tweets::Entity as Tweets;
 
 pub use super::users::Entity as Users;
+pub use super::watched_accounts::Entity as WatchedAccounts;
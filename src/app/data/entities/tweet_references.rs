@@ -11,6 +11,7 @@ pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub source_tweet_id: i64,
     pub reference_type: String,
+    #[sea_orm(primary_key, auto_increment = false)]
     pub referenced_tweet_id: i64,
 }
 
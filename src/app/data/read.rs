@@ -1,19 +1,50 @@
 use crate::{
-    app::load_user_from_twitter_handle,
-    utils::{ConversationData, TweetData, TweetReferenceData, UserData},
+    utils::{
+        ArchiveStats, CollectedTweet, CollectionSummary, ConversationData, InteractionEdge,
+        MalformedDate, PeriodCount, PostingStreak, TagCount, TweetData, TweetKindCounts,
+        TweetPage, UserActivity, UserData, VerifyReport, WeekdayHours, MAX_QUERY_PARAMETERS,
+    },
 };
+use std::collections::HashMap;
 
 use super::entities::prelude::*;
 use super::entities::*;
-use chrono::FixedOffset;
+use chrono::{DateTime, FixedOffset};
 use futures::future::join_all;
+use futures::{stream, Stream, StreamExt};
 use rocket::State;
-use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use sea_orm::{
+    ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, EntityTrait, FromQueryResult,
+    JoinType, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, RelationTrait, Select,
+    Statement,
+};
+use sea_orm::sea_query::{Expr, Func, Query, SelectStatement};
 
 pub async fn tweet_by_id(db: &State<DatabaseConnection>, id: i64) -> TweetData {
     TweetData::read(db, id).await
 }
 
+// The tweet, or nothing if it has been hidden.
+pub async fn visible_tweet_by_id(db: &State<DatabaseConnection>, id: i64) -> TweetData {
+    match hidden_tweet(db, id).await {
+        Some(_hidden) => TweetData::empty(),
+        None => tweet_by_id(db, id).await,
+    }
+}
+
+// Leaves out the tweets an admin has hidden. Everything that serves tweets to clients
+// selects through this; backups and maintenance don't.
+fn visible(select: Select<Tweets>) -> Select<Tweets> {
+    select.filter(tweets::Column::Id.not_in_subquery(hidden_ids()))
+}
+
+fn hidden_ids() -> SelectStatement {
+    Query::select()
+        .column(hidden_tweets::Column::TweetId)
+        .from(HiddenTweets)
+        .to_owned()
+}
+
 pub async fn user_by_id(db: &State<DatabaseConnection>, id: i64) -> UserData {
     UserData::read(db, id).await
 }
@@ -25,24 +56,99 @@ pub async fn user_by_twitter_handle(
     UserData::read_from_twitter_handle(db, twitter_handle).await
 }
 
+// The user with this handle in any case, as twitter matches handles, for removing them
+// whatever case they were asked for in.
+pub async fn user_by_handle_in_any_case(
+    db: &State<DatabaseConnection>,
+    twitter_handle: &str,
+) -> Option<users::Model> {
+    Users::find()
+        .filter(
+            Expr::expr(Func::lower(Expr::col(users::Column::Username)))
+                .eq(twitter_handle.to_lowercase()),
+        )
+        .one(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to read user @{twitter_handle} from database. Error: {:?}", error)
+        })
+}
+
 pub async fn tweets(db: &State<DatabaseConnection>) -> Vec<TweetData> {
-    let tweet_models: Vec<tweets::Model> = Tweets::find()
+    let tweet_models: Vec<tweets::Model> = visible(Tweets::find())
         .all(db as &DatabaseConnection)
         .await
         .unwrap_or_else(|error| panic!("Failed to get tweets from database. Error: {:?}", error));
-    join_all(
-        tweet_models
-            .into_iter()
-            .map(|tweet_model| TweetData::read_from_data_model(db, tweet_model)),
-    )
-    .await
+    TweetData::read_many_from_data_models(db, tweet_models).await
+}
+
+// Streams read the table a page of this many rows at a time, each page with a query of its
+// own, so a connection is only held while a page is read rather than for the whole response.
+const STREAM_PAGE_SIZE: u64 = 500;
+
+// Streams every tweet that isn't hidden in id order, so the whole table never has to be
+// held in memory. Takes the connection itself so it can be owned by a response body.
+pub fn tweets_stream(db: &DatabaseConnection) -> impl Stream<Item = TweetData> + Send + '_ {
+    stream_tweets(db, visible(Tweets::find()))
+}
+
+// Every tweet including hidden ones, for backups.
+pub fn archived_tweets_stream(
+    db: &DatabaseConnection,
+) -> impl Stream<Item = TweetData> + Send + '_ {
+    stream_tweets(db, Tweets::find())
+}
+
+// Pages through the tweets by id, each page starting after the last id of the one before.
+fn stream_tweets(
+    db: &DatabaseConnection,
+    select: Select<Tweets>,
+) -> impl Stream<Item = TweetData> + Send + '_ {
+    stream::unfold(Some(i64::MIN), move |after| {
+        let select = select.clone();
+        async move {
+            let page = select
+                .filter(tweets::Column::Id.gt(after?))
+                .order_by_asc(tweets::Column::Id)
+                .limit(STREAM_PAGE_SIZE)
+                .all(db)
+                .await
+                .unwrap_or_else(|error| {
+                    panic!("Failed to get tweets from database. Error: {:?}", error)
+                });
+            let last_id = page.last()?.id;
+            let next = (page.len() as u64 == STREAM_PAGE_SIZE).then_some(last_id);
+            Some((
+                TweetData::read_many_from_data_models(State::from(db), page).await,
+                next,
+            ))
+        }
+    })
+    .flat_map(stream::iter)
+}
+
+pub fn users_stream(db: &DatabaseConnection) -> impl Stream<Item = UserData> + Send + '_ {
+    stream::unfold(Some(i64::MIN), move |after| async move {
+        let page = Users::find()
+            .filter(users::Column::Id.gt(after?))
+            .order_by_asc(users::Column::Id)
+            .limit(STREAM_PAGE_SIZE)
+            .all(db)
+            .await
+            .unwrap_or_else(|error| panic!("Failed to get users from database. Error: {:?}", error));
+        let last_id = page.last()?.id;
+        let next = (page.len() as u64 == STREAM_PAGE_SIZE).then_some(last_id);
+        Some((page, next))
+    })
+    .flat_map(stream::iter)
+    .map(|user| UserData { user: Some(user) })
 }
 
 pub async fn conversation(
     db: &State<DatabaseConnection>,
     conversation_id: i64,
 ) -> ConversationData {
-    let conversation_tweets_from_db = Tweets::find()
+    let conversation_tweets_from_db = visible(Tweets::find())
         .filter(tweets::Column::ConversationId.eq(conversation_id))
         .order_by_asc(tweets::Column::CreatedAt)
         .all(db as &DatabaseConnection)
@@ -53,18 +159,25 @@ pub async fn conversation(
                 error,
             )
         });
-    let tweets = join_all(
-        conversation_tweets_from_db
-            .into_iter()
-            .map(|tweet_model| TweetData::read_from_data_model(db, tweet_model)),
-    )
-    .await;
+    let tweets = TweetData::read_many_from_data_models(db, conversation_tweets_from_db).await;
     ConversationData {
         id: conversation_id,
         tweets,
+        annotations: Vec::new(),
     }
 }
 
+// The archived users out of `ids`.
+pub async fn users_by_ids(db: &State<DatabaseConnection>, ids: &[i64]) -> Vec<users::Model> {
+    Users::find()
+        .filter(users::Column::Id.is_in(ids.to_vec()))
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to get users {:?} from database. Error: {:?}", ids, error)
+        })
+}
+
 pub async fn users(db: &State<DatabaseConnection>) -> Vec<UserData> {
     let db = db as &DatabaseConnection;
 
@@ -76,17 +189,20 @@ pub async fn users(db: &State<DatabaseConnection>) -> Vec<UserData> {
     join_all(
         users_from_db
             .into_iter()
-            .map(|user| UserData::from_data_model(user)),
+            .map(UserData::from_data_model),
     )
     .await
 }
 
-pub async fn users_tweets(db: &State<DatabaseConnection>, twitter_handle: &str) -> Vec<TweetData> {
-    let user_data = load_user_from_twitter_handle(db, twitter_handle).await;
-    let user = user_data.user.clone().expect("Failed to get user");
+// Returns None when the user isn't archived.
+pub async fn users_tweets(
+    db: &State<DatabaseConnection>,
+    twitter_handle: &str,
+) -> Option<Vec<TweetData>> {
+    let user = user_by_twitter_handle(db, twitter_handle).await.user?;
     let username = user.name;
 
-    let users_tweets_from_db = Tweets::find()
+    let users_tweets_from_db = visible(Tweets::find())
         .filter(tweets::Column::AuthorId.eq(user.id))
         .order_by_desc(tweets::Column::CreatedAt)
         .all(db as &DatabaseConnection)
@@ -98,50 +214,155 @@ pub async fn users_tweets(db: &State<DatabaseConnection>, twitter_handle: &str)
             )
         });
 
-    join_all(
-        users_tweets_from_db
-            .into_iter()
-            .map(|user_tweet| TweetData::read_from_data_model(db, user_tweet)),
-    )
-    .await
+    Some(TweetData::read_many_from_data_models(db, users_tweets_from_db).await)
+}
+
+// One page of the user's tweets, newest first. `page` counts from 0.
+pub async fn users_tweets_page(
+    db: &State<DatabaseConnection>,
+    user_id: i64,
+    page: usize,
+    per_page: usize,
+) -> TweetPage {
+    let paginator = visible(Tweets::find())
+        .filter(tweets::Column::AuthorId.eq(user_id))
+        .order_by_desc(tweets::Column::CreatedAt)
+        .paginate(db as &DatabaseConnection, per_page);
+    let pages = paginator.num_pages().await.unwrap_or_else(|error| {
+        panic!("Failed to count user {user_id}'s tweets. Error: {:?}", error)
+    });
+    let tweets = paginator.fetch_page(page).await.unwrap_or_else(|error| {
+        panic!(
+            "Failed to get page {page} of user {user_id}'s tweets from database. Error: {:?}",
+            error
+        )
+    });
+    TweetPage {
+        tweets: TweetData::read_many_from_data_models(db, tweets).await,
+        page,
+        pages,
+    }
 }
 
 pub async fn users_tweets_since_date(
     db: &State<DatabaseConnection>,
-    twitter_handle: &str,
-    rfc3339_date: &str,
+    user_id: i64,
+    date: DateTime<FixedOffset>,
 ) -> Vec<TweetData> {
-    let user_data = load_user_from_twitter_handle(db, twitter_handle).await;
-    let user = user_data.user.clone().expect("Failed to get user");
-    let username = user.name;
-
-    let date =
-        chrono::DateTime::<FixedOffset>::parse_from_rfc3339(rfc3339_date).unwrap_or_else(|error| {
+    let tweets_from_db = visible(Tweets::find())
+        .filter(tweets::Column::AuthorId.eq(user_id))
+        .filter(tweets::Column::CreatedAt.gt(date))
+        .order_by_desc(tweets::Column::CreatedAt)
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
             panic!(
-                "Failed to parse date from rfc3339_date {:?}. Error: {:?}",
-                rfc3339_date, error
+                "Failed to get user {user_id}'s tweets from the database. Error: {:?}",
+                error
             )
         });
 
-    let tweets_from_db = Tweets::find()
-        .filter(tweets::Column::AuthorId.eq(user.id))
-        .filter(tweets::Column::CreatedAt.gt(date))
+    TweetData::read_many_from_data_models(db, tweets_from_db).await
+}
+
+// The user's tweets from `from` up to but not including `to`, newest first.
+pub async fn users_tweets_between(
+    db: &State<DatabaseConnection>,
+    user_id: i64,
+    from: Option<DateTime<FixedOffset>>,
+    to: Option<DateTime<FixedOffset>>,
+) -> Vec<TweetData> {
+    let tweets_from_db = users_tweets_between_query(user_id, from, to)
         .order_by_desc(tweets::Column::CreatedAt)
         .all(db as &DatabaseConnection)
         .await
         .unwrap_or_else(|error| {
             panic!(
-                "Failed to get @{username}'s tweets from the database. Error: {:?}",
+                "Failed to get user {user_id}'s tweets between {:?} and {:?} from the database. Error: {:?}",
+                from, to, error
+            )
+        });
+    TweetData::read_many_from_data_models(db, tweets_from_db).await
+}
+
+pub async fn count_users_tweets_between(
+    db: &State<DatabaseConnection>,
+    user_id: i64,
+    from: Option<DateTime<FixedOffset>>,
+    to: Option<DateTime<FixedOffset>>,
+) -> usize {
+    users_tweets_between_query(user_id, from, to)
+        .count(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to count user {user_id}'s tweets between {:?} and {:?} in the database. Error: {:?}",
+                from, to, error
+            )
+        })
+}
+
+fn users_tweets_between_query(
+    user_id: i64,
+    from: Option<DateTime<FixedOffset>>,
+    to: Option<DateTime<FixedOffset>>,
+) -> Select<Tweets> {
+    let mut select = visible(Tweets::find()).filter(tweets::Column::AuthorId.eq(user_id));
+    if let Some(from) = from {
+        select = select.filter(tweets::Column::CreatedAt.gte(from));
+    }
+    if let Some(to) = to {
+        select = select.filter(tweets::Column::CreatedAt.lt(to));
+    }
+    select
+}
+
+// The user's tweets that fall in any of the given spans, each from inclusive and to
+// exclusive, oldest first.
+pub async fn users_tweets_in_spans(
+    db: &State<DatabaseConnection>,
+    user_id: i64,
+    spans: &[(DateTime<FixedOffset>, DateTime<FixedOffset>)],
+) -> Vec<tweets::Model> {
+    let mut condition = Condition::any();
+    for (from, to) in spans {
+        condition = condition.add(
+            Condition::all()
+                .add(tweets::Column::CreatedAt.gte(*from))
+                .add(tweets::Column::CreatedAt.lt(*to)),
+        );
+    }
+    let mut tweets_from_db = visible(Tweets::find())
+        .filter(tweets::Column::AuthorId.eq(user_id))
+        .filter(condition)
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to get user {user_id}'s tweets on the given days from the database. Error: {:?}",
                 error
             )
         });
+    tweets_from_db.sort_by_key(|tweet| (tweet.created_at, tweet.id));
+    tweets_from_db
+}
 
-    join_all(
-        tweets_from_db
-            .into_iter()
-            .map(|tweet| TweetData::read_from_data_model(db, tweet)),
-    )
-    .await
+pub async fn earliest_tweet_date_from_user(
+    db: &State<DatabaseConnection>,
+    user_id: i64,
+) -> Option<DateTime<FixedOffset>> {
+    visible(Tweets::find())
+        .filter(tweets::Column::AuthorId.eq(user_id))
+        .order_by_asc(tweets::Column::CreatedAt)
+        .one(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to get user {user_id}'s earliest tweet from the database. Error: {:?}",
+                error
+            )
+        })
+        .map(|tweet| tweet.created_at)
 }
 
 pub async fn does_conversation_exist(db: &State<DatabaseConnection>, id: i64) -> bool {
@@ -175,37 +396,1189 @@ pub async fn does_tweet_exist(db: &State<DatabaseConnection>, id: i64) -> bool {
         == 1
 }
 
-pub async fn latest_tweet_from_user(db: &State<DatabaseConnection>, id: i64) -> TweetData {
-    let tweet_model = Tweets::find()
+// Returns None when the user has no visible tweets.
+pub async fn latest_tweet_from_user(db: &State<DatabaseConnection>, id: i64) -> Option<TweetData> {
+    let tweet_model = visible(Tweets::find())
         .filter(tweets::Column::AuthorId.eq(id))
         .order_by_desc(tweets::Column::CreatedAt)
         .one(db as &DatabaseConnection)
         .await
         .unwrap_or_else(|error| {
-            panic!("Failed to run query for getting the latest tweet for user of id {id}")
+            panic!(
+                "Failed to run query for getting the latest tweet for user of id {id}. Error: {:?}",
+                error
+            )
+        })?;
+
+    Some(TweetData::read_from_data_model(db, tweet_model).await)
+}
+
+pub async fn latest_tweet_id_from_user(db: &State<DatabaseConnection>, id: i64) -> Option<i64> {
+    Tweets::find()
+        .filter(tweets::Column::AuthorId.eq(id))
+        .order_by_desc(tweets::Column::Id)
+        .one(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to run query for getting the latest tweet id for user of id {id}. Error: {:?}",
+                error
+            )
         })
-        .unwrap_or_else(|| {
-            panic!("Failed to open option for getting the latest tweet for user of id {id}")
-        });
+        .map(|tweet| tweet.id)
+}
 
-    TweetData::read_from_data_model(db, tweet_model).await
+pub async fn references_to_tweets(
+    db: &State<DatabaseConnection>,
+    ids: &[i64],
+) -> Vec<tweet_references::Model> {
+    TweetReferences::find()
+        .filter(tweet_references::Column::ReferencedTweetId.is_in(ids.to_vec()))
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to get references to tweets {:?} from database. Error: {:?}",
+                ids, error
+            )
+        })
+}
+
+// Narrows a search down to the tweets in a collection, with a tag, or both.
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilter {
+    pub collection_id: Option<i32>,
+    pub tag: Option<String>,
 }
 
 pub async fn search_tweets_in_db(
     db: &State<DatabaseConnection>,
     search_query: &str,
+    filter: &SearchFilter,
 ) -> Vec<TweetData> {
-    let search_result_from_db = Tweets::find()
+    let search_result_from_db = filtered(visible(Tweets::find()), filter)
         .filter(tweets::Column::Content.contains(search_query))
         .order_by_desc(tweets::Column::CreatedAt)
         .all(db as &DatabaseConnection)
         .await
         .unwrap_or_else(|error| panic!("Failed to run tweet search. \n\nError:\n{:?}", error));
 
-    join_all(
-        search_result_from_db
-            .into_iter()
-            .map(|result| TweetData::read_from_data_model(db, result)),
+    TweetData::read_many_from_data_models(db, search_result_from_db).await
+}
+
+// The newest `limit` tweets matching the search.
+pub async fn latest_search_results(
+    db: &State<DatabaseConnection>,
+    search_query: &str,
+    limit: u64,
+) -> Vec<TweetData> {
+    let search_result_from_db = visible(Tweets::find())
+        .filter(tweets::Column::Content.contains(search_query))
+        .order_by_desc(tweets::Column::CreatedAt)
+        .limit(limit)
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to run tweet search. \n\nError:\n{:?}", error));
+
+    TweetData::read_many_from_data_models(db, search_result_from_db).await
+}
+
+fn filtered(mut select: Select<Tweets>, filter: &SearchFilter) -> Select<Tweets> {
+    if let Some(collection_id) = filter.collection_id {
+        select = select.filter(
+            tweets::Column::Id.in_subquery(
+                Query::select()
+                    .column(collection_tweets::Column::TweetId)
+                    .from(CollectionTweets)
+                    .and_where(collection_tweets::Column::CollectionId.eq(collection_id))
+                    .to_owned(),
+            ),
+        );
+    }
+    if let Some(tag) = &filter.tag {
+        select = select.filter(
+            tweets::Column::Id.in_subquery(
+                Query::select()
+                    .column(tweet_tags::Column::TweetId)
+                    .from(TweetTags)
+                    .and_where(tweet_tags::Column::Tag.eq(tag.as_str()))
+                    .to_owned(),
+            ),
+        );
+    }
+    select
+}
+
+pub async fn watched_accounts(db: &State<DatabaseConnection>) -> Vec<watched_accounts::Model> {
+    WatchedAccounts::find()
+        .order_by_asc(watched_accounts::Column::Username)
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to get watched accounts from database. Error: {:?}",
+                error
+            )
+        })
+}
+
+pub async fn watched_account(
+    db: &State<DatabaseConnection>,
+    twitter_handle: &str,
+) -> Option<watched_accounts::Model> {
+    WatchedAccounts::find_by_id(twitter_handle.to_owned())
+        .one(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to get watched account @{twitter_handle} from database. Error: {:?}",
+                error
+            )
+        })
+}
+
+pub async fn api_keys(db: &State<DatabaseConnection>) -> Vec<api_keys::Model> {
+    ApiKeys::find()
+        .order_by_asc(api_keys::Column::Id)
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to get api keys from database. Error: {:?}", error)
+        })
+}
+
+// The key of that name that isn't revoked, which there is at most one of.
+pub async fn live_api_key_by_name(
+    db: &State<DatabaseConnection>,
+    name: &str,
+) -> Option<api_keys::Model> {
+    ApiKeys::find()
+        .filter(api_keys::Column::Name.eq(name))
+        .filter(api_keys::Column::RevokedAt.is_null())
+        .one(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to look up api key {name} in the database. Error: {:?}", error)
+        })
+}
+
+pub async fn api_key_by_hash(
+    db: &State<DatabaseConnection>,
+    key_hash: &str,
+) -> Option<api_keys::Model> {
+    ApiKeys::find()
+        .filter(api_keys::Column::KeyHash.eq(key_hash))
+        .one(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to look up an api key in the database. Error: {:?}", error)
+        })
+}
+
+pub async fn hidden_tweet(
+    db: &State<DatabaseConnection>,
+    id: i64,
+) -> Option<hidden_tweets::Model> {
+    HiddenTweets::find_by_id(id)
+        .one(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to check whether tweet {id} is hidden. Error: {:?}", error)
+        })
+}
+
+// Newest first.
+pub async fn hidden_tweets(db: &State<DatabaseConnection>) -> Vec<hidden_tweets::Model> {
+    HiddenTweets::find()
+        .order_by_desc(hidden_tweets::Column::HiddenAt)
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to get hidden tweets from database. Error: {:?}", error)
+        })
+}
+
+// Which of the given tweets are hidden.
+pub async fn hidden_tweet_ids(db: &State<DatabaseConnection>, tweet_ids: &[i64]) -> Vec<i64> {
+    let mut hidden = Vec::new();
+    for chunk in tweet_ids.chunks(MAX_QUERY_PARAMETERS) {
+        hidden.extend(
+            ids(
+                db,
+                "hidden tweets",
+                HiddenTweets::find()
+                    .select_only()
+                    .column_as(hidden_tweets::Column::TweetId, "id")
+                    .filter(hidden_tweets::Column::TweetId.is_in(chunk.to_vec())),
+            )
+            .await,
+        );
+    }
+    hidden
+}
+
+pub async fn redacted_users(db: &State<DatabaseConnection>) -> Vec<redacted_users::Model> {
+    RedactedUsers::find()
+        .order_by_asc(redacted_users::Column::Id)
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to get redacted users from database. Error: {:?}", error)
+        })
+}
+
+// The redaction covering the user with this id or handle, if any.
+pub async fn redaction(
+    db: &State<DatabaseConnection>,
+    user_id: Option<i64>,
+    username: Option<&str>,
+) -> Option<redacted_users::Model> {
+    let mut condition = Condition::any();
+    if let Some(user_id) = user_id {
+        condition = condition.add(redacted_users::Column::UserId.eq(user_id));
+    }
+    if let Some(username) = username {
+        condition = condition.add(redacted_users::Column::Username.eq(username.to_lowercase()));
+    }
+    if condition.is_empty() {
+        return None;
+    }
+    RedactedUsers::find()
+        .filter(condition)
+        .one(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to look up redactions in the database. Error: {:?}", error)
+        })
+}
+
+// Every id of a tweet the user wrote, hidden or not.
+pub async fn users_tweet_ids(db: &State<DatabaseConnection>, user_id: i64) -> Vec<i64> {
+    ids(
+        db,
+        "the user's tweets",
+        Tweets::find()
+            .select_only()
+            .column(tweets::Column::Id)
+            .filter(tweets::Column::AuthorId.eq(user_id)),
+    )
+    .await
+}
+
+// The latest `limit` entries, newest first.
+pub async fn audit_log(db: &State<DatabaseConnection>, limit: u64) -> Vec<audit_log::Model> {
+    AuditLog::find()
+        .order_by_desc(audit_log::Column::Id)
+        .limit(limit)
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to get the audit log from database. Error: {:?}", error))
+}
+
+// Every collection with how many tweets it has, by name. Hidden tweets aren't counted.
+pub async fn collections(db: &State<DatabaseConnection>) -> Vec<CollectionSummary> {
+    let db = db as &DatabaseConnection;
+    let collections = Collections::find()
+        .order_by_asc(collections::Column::Name)
+        .all(db)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to get collections from database. Error: {:?}", error)
+        });
+    let mut counts: HashMap<i32, i64> = CollectionTweets::find()
+        .select_only()
+        .column(collection_tweets::Column::CollectionId)
+        .column_as(collection_tweets::Column::TweetId.count(), "tweets")
+        .filter(collection_tweets::Column::TweetId.not_in_subquery(hidden_ids()))
+        .group_by(collection_tweets::Column::CollectionId)
+        .into_model::<CollectionCountRow>()
+        .all(db)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to count the tweets in each collection. Error: {:?}", error)
+        })
+        .into_iter()
+        .map(|row| (row.collection_id, row.tweets))
+        .collect();
+    collections
+        .into_iter()
+        .map(|collection| CollectionSummary {
+            tweets: counts.remove(&collection.id).unwrap_or(0),
+            collection,
+        })
+        .collect()
+}
+
+pub async fn collection(db: &State<DatabaseConnection>, id: i32) -> Option<collections::Model> {
+    Collections::find_by_id(id)
+        .one(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to get collection {id} from database. Error: {:?}", error)
+        })
+}
+
+pub async fn collection_by_name(
+    db: &State<DatabaseConnection>,
+    name: &str,
+) -> Option<collections::Model> {
+    Collections::find()
+        .filter(collections::Column::Name.eq(name))
+        .one(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to get collection {name} from database. Error: {:?}", error)
+        })
+}
+
+// The collection's tweets in the order they were added, with their notes and tags,
+// leaving out hidden tweets.
+pub async fn collected_tweets(
+    db: &State<DatabaseConnection>,
+    collection_id: i32,
+) -> Vec<CollectedTweet> {
+    let entries = CollectionTweets::find()
+        .filter(collection_tweets::Column::CollectionId.eq(collection_id))
+        .filter(collection_tweets::Column::TweetId.not_in_subquery(hidden_ids()))
+        .order_by_asc(collection_tweets::Column::AddedAt)
+        .order_by_asc(collection_tweets::Column::TweetId)
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to get the tweets of collection {collection_id} from database. Error: {:?}",
+                error
+            )
+        });
+    let ids: Vec<i64> = entries.iter().map(|entry| entry.tweet_id).collect();
+    let mut tags = tags_of_tweets(db, &ids).await;
+    entries
+        .into_iter()
+        .zip(TweetData::read_many(db, &ids).await)
+        .map(|(entry, tweet)| CollectedTweet {
+            tweet,
+            note: entry.note,
+            added_at: entry.added_at,
+            tags: tags.remove(&entry.tweet_id).unwrap_or_default(),
+        })
+        .collect()
+}
+
+pub async fn collection_tweet(
+    db: &State<DatabaseConnection>,
+    collection_id: i32,
+    tweet_id: i64,
+) -> Option<collection_tweets::Model> {
+    CollectionTweets::find_by_id((collection_id, tweet_id))
+        .one(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to get tweet {tweet_id} of collection {collection_id} from database. Error: {:?}",
+                error
+            )
+        })
+}
+
+// The tags of each of the tweets that has any, in alphabetical order.
+pub async fn tags_of_tweets(
+    db: &State<DatabaseConnection>,
+    tweet_ids: &[i64],
+) -> HashMap<i64, Vec<String>> {
+    let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+    for chunk in tweet_ids.chunks(MAX_QUERY_PARAMETERS) {
+        for tag in TweetTags::find()
+            .filter(tweet_tags::Column::TweetId.is_in(chunk.to_vec()))
+            .order_by_asc(tweet_tags::Column::Tag)
+            .all(db as &DatabaseConnection)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to get tweet tags from database. Error: {:?}", error)
+            })
+        {
+            tags.entry(tag.tweet_id).or_default().push(tag.tag);
+        }
+    }
+    tags
+}
+
+// Every tag with how many tweets have it, most used first. Hidden tweets aren't counted.
+pub async fn tags(db: &State<DatabaseConnection>) -> Vec<TagCount> {
+    TweetTags::find()
+        .select_only()
+        .column(tweet_tags::Column::Tag)
+        .column_as(tweet_tags::Column::TweetId.count(), "tweets")
+        .filter(tweet_tags::Column::TweetId.not_in_subquery(hidden_ids()))
+        .group_by(tweet_tags::Column::Tag)
+        .order_by_desc(Expr::cust("tweets"))
+        .order_by_asc(tweet_tags::Column::Tag)
+        .into_model::<TagCount>()
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to count tweet tags. Error: {:?}", error))
+}
+
+pub async fn annotation(db: &State<DatabaseConnection>, id: i32) -> Option<annotations::Model> {
+    Annotations::find_by_id(id)
+        .one(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to get annotation {id} from database. Error: {:?}", error)
+        })
+}
+
+// The annotations on each of the tweets, oldest first.
+pub async fn tweet_annotations(
+    db: &State<DatabaseConnection>,
+    tweet_ids: &[i64],
+) -> HashMap<i64, Vec<annotations::Model>> {
+    let mut annotations: HashMap<i64, Vec<annotations::Model>> = HashMap::new();
+    for chunk in tweet_ids.chunks(MAX_QUERY_PARAMETERS) {
+        for annotation in Annotations::find()
+            .filter(annotations::Column::TweetId.is_in(chunk.to_vec()))
+            .order_by_asc(annotations::Column::CreatedAt)
+            .all(db as &DatabaseConnection)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to get tweet annotations from database. Error: {:?}", error)
+            })
+        {
+            if let Some(tweet_id) = annotation.tweet_id {
+                annotations.entry(tweet_id).or_default().push(annotation);
+            }
+        }
+    }
+    annotations
+}
+
+// The annotations on the conversation as a whole, oldest first.
+pub async fn conversation_annotations(
+    db: &State<DatabaseConnection>,
+    conversation_id: i64,
+) -> Vec<annotations::Model> {
+    Annotations::find()
+        .filter(annotations::Column::ConversationId.eq(conversation_id))
+        .order_by_asc(annotations::Column::CreatedAt)
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to get the annotations of conversation {conversation_id}. Error: {:?}",
+                error
+            )
+        })
+}
+
+// Annotations whose text contains the query, most recently edited first. Those on hidden
+// tweets are left out along with the tweets.
+pub async fn search_annotations(
+    db: &State<DatabaseConnection>,
+    search_query: &str,
+) -> Vec<annotations::Model> {
+    Annotations::find()
+        .filter(annotations::Column::Body.contains(search_query))
+        .filter(
+            Condition::any()
+                .add(annotations::Column::TweetId.is_null())
+                .add(annotations::Column::TweetId.not_in_subquery(hidden_ids())),
+        )
+        .order_by_desc(annotations::Column::UpdatedAt)
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to run annotation search. Error: {:?}", error))
+}
+
+pub async fn tweet_status(db: &State<DatabaseConnection>, id: i64) -> Option<tweet_status::Model> {
+    TweetStatus::find_by_id(id)
+        .one(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to get the status of tweet {id} from database. Error: {:?}", error)
+        })
+}
+
+// Every version of a tweet, oldest first. `id` can be the tweet or any of its edits.
+pub async fn tweet_versions(
+    db: &State<DatabaseConnection>,
+    id: i64,
+) -> Vec<tweet_versions::Model> {
+    let db = db as &DatabaseConnection;
+    let tweet_id = TweetVersions::find()
+        .filter(
+            Condition::any()
+                .add(tweet_versions::Column::TweetId.eq(id))
+                .add(tweet_versions::Column::EditTweetId.eq(id)),
+        )
+        .one(db)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to find the versions of tweet {id} in database. Error: {:?}", error)
+        })
+        .map(|version| version.tweet_id);
+    match tweet_id {
+        Some(tweet_id) => TweetVersions::find()
+            .filter(tweet_versions::Column::TweetId.eq(tweet_id))
+            .order_by_asc(tweet_versions::Column::Version)
+            .all(db)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to get the versions of tweet {id} from database. Error: {:?}", error)
+            }),
+        None => Vec::new(),
+    }
+}
+
+pub async fn stats(db: &State<DatabaseConnection>) -> ArchiveStats {
+    let db = db as &DatabaseConnection;
+    let count_error = |error| panic!("Failed to count rows in the database. Error: {:?}", error);
+    ArchiveStats {
+        users: Users::find().count(db).await.unwrap_or_else(count_error),
+        conversations: Conversations::find()
+            .count(db)
+            .await
+            .unwrap_or_else(count_error),
+        tweets: Tweets::find().count(db).await.unwrap_or_else(count_error),
+        tweet_references: TweetReferences::find()
+            .count(db)
+            .await
+            .unwrap_or_else(count_error),
+        watched_accounts: WatchedAccounts::find()
+            .count(db)
+            .await
+            .unwrap_or_else(count_error),
+        tweet_statuses: TweetStatus::find()
+            .count(db)
+            .await
+            .unwrap_or_else(count_error),
+        tweet_versions: TweetVersions::find()
+            .count(db)
+            .await
+            .unwrap_or_else(count_error),
+        embeddings: Embeddings::find().count(db).await.unwrap_or_else(count_error),
+    }
+}
+
+#[cfg(feature = "semantic-search")]
+pub async fn embedding(db: &State<DatabaseConnection>, tweet_id: i64) -> Option<embeddings::Model> {
+    Embeddings::find_by_id(tweet_id)
+        .one(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to get the embedding of tweet {tweet_id} from database. Error: {:?}", error)
+        })
+}
+
+#[cfg(feature = "semantic-search")]
+pub async fn embeddings(
+    db: &State<DatabaseConnection>,
+    vectorizer: &str,
+) -> Vec<embeddings::Model> {
+    Embeddings::find()
+        .filter(embeddings::Column::Vectorizer.eq(vectorizer))
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to get embeddings from database. Error: {:?}", error))
+}
+
+// Tweets with no embedding made by `vectorizer`, lowest ids first.
+#[cfg(feature = "semantic-search")]
+pub async fn tweets_without_embedding(
+    db: &State<DatabaseConnection>,
+    vectorizer: &str,
+    limit: u64,
+) -> Vec<tweets::Model> {
+    Tweets::find()
+        .filter(
+            tweets::Column::Id.not_in_subquery(
+                Query::select()
+                    .column(embeddings::Column::TweetId)
+                    .from(Embeddings)
+                    .and_where(embeddings::Column::Vectorizer.eq(vectorizer))
+                    .to_owned(),
+            ),
+        )
+        .order_by_asc(tweets::Column::Id)
+        .limit(limit)
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to get tweets without embeddings from database. Error: {:?}", error)
+        })
+}
+
+// Just the text and date of the user's tweets, oldest first, for analyses that don't need
+// the rest. `from` is inclusive and `to` exclusive.
+pub async fn users_tweet_texts(
+    db: &State<DatabaseConnection>,
+    user_id: i64,
+    from: Option<DateTime<FixedOffset>>,
+    to: Option<DateTime<FixedOffset>>,
+) -> Vec<TweetText> {
+    let mut select = visible(Tweets::find())
+        .select_only()
+        .column(tweets::Column::Content)
+        .column(tweets::Column::CreatedAt)
+        .filter(tweets::Column::AuthorId.eq(user_id));
+    if let Some(from) = from {
+        select = select.filter(tweets::Column::CreatedAt.gte(from));
+    }
+    if let Some(to) = to {
+        select = select.filter(tweets::Column::CreatedAt.lt(to));
+    }
+    select
+        .order_by_asc(tweets::Column::CreatedAt)
+        .into_model::<TweetText>()
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to get the text of user {user_id}'s tweets from the database. Error: {:?}",
+                error
+            )
+        })
+}
+
+// How many longest posting streaks `user_activity` lists.
+const LONGEST_STREAKS: u64 = 5;
+
+// Works out the user's activity with aggregate queries so their tweets are never loaded.
+// The date functions are sqlite's, which reads the stored dates as UTC. Every query reads
+// `user_tweets`, the user's tweets that aren't hidden.
+pub async fn user_activity(db: &State<DatabaseConnection>, user_id: i64) -> UserActivity {
+    let db = db as &DatabaseConnection;
+    let backend = db.get_database_backend();
+    let statement = |sql: &str| {
+        Statement::from_sql_and_values(
+            backend,
+            &format!(
+                "WITH user_tweets AS (SELECT * FROM tweets WHERE author_id = ?1 \
+                 AND id NOT IN (SELECT tweet_id FROM hidden_tweets)) {sql}"
+            ),
+            vec![user_id.into()],
+        )
+    };
+    let period_counts = |period: &str| {
+        statement(&format!(
+            "SELECT {period} AS period, COUNT(*) AS tweets FROM user_tweets \
+             GROUP BY period ORDER BY period"
+        ))
+    };
+    let per_day =
+        query_rows::<PeriodCount>(db, period_counts("strftime('%Y-%m-%d', created_at)")).await;
+    // ISO weeks run monday to sunday and belong to the year their thursday falls in, so the
+    // week is numbered from that thursday.
+    let per_week = query_rows::<PeriodCount>(
+        db,
+        period_counts(
+            "printf('%s-W%02d', strftime('%Y', date(created_at, 'weekday 0', '-3 days')), \
+             (strftime('%j', date(created_at, 'weekday 0', '-3 days')) - 1) / 7 + 1)",
+        ),
+    )
+    .await;
+    let per_month =
+        query_rows::<PeriodCount>(db, period_counts("strftime('%Y-%m', created_at)")).await;
+
+    let mut weekday_hours = vec![vec![0; 24]; 7];
+    for row in query_rows::<WeekdayHourRow>(
+        db,
+        statement(
+            "SELECT CAST(strftime('%w', created_at) AS INTEGER) AS weekday, \
+             CAST(strftime('%H', created_at) AS INTEGER) AS hour, COUNT(*) AS tweets \
+             FROM user_tweets GROUP BY weekday, hour",
+        ),
+    )
+    .await
+    {
+        weekday_hours[row.weekday as usize][row.hour as usize] = row.tweets;
+    }
+    let per_hour: Vec<i64> = (0..24)
+        .map(|hour| weekday_hours.iter().map(|hours| hours[hour]).sum())
+        .collect();
+    let tweets = per_hour.iter().sum();
+    // sqlite counts weekdays from sunday
+    let weekday_hours = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"]
+        .iter()
+        .zip([1, 2, 3, 4, 5, 6, 0])
+        .map(|(weekday, index)| WeekdayHours {
+            weekday: weekday.to_string(),
+            hours: weekday_hours[index].clone(),
+        })
+        .collect();
+
+    // A tweet can reference more than one tweet but counts once: as a retweet, else a reply,
+    // else a quote.
+    let mut kinds = TweetKindCounts::default();
+    for row in query_rows::<KindRow>(
+        db,
+        statement(
+            "SELECT COALESCE((SELECT reference_type FROM tweet_references \
+             WHERE source_tweet_id = user_tweets.id ORDER BY CASE reference_type \
+             WHEN 'retweeted' THEN 0 WHEN 'replied_to' THEN 1 WHEN 'quoted' THEN 2 ELSE 3 END \
+             LIMIT 1), 'original') AS kind, COUNT(*) AS tweets FROM user_tweets GROUP BY kind",
+        ),
+    )
+    .await
+    {
+        match row.kind.as_str() {
+            "replied_to" => kinds.replies += row.tweets,
+            "quoted" => kinds.quotes += row.tweets,
+            "retweeted" => kinds.retweets += row.tweets,
+            _ => kinds.original += row.tweets,
+        }
+    }
+    let ratio = |count: i64| match tweets {
+        0 => 0.0,
+        tweets => count as f64 / tweets as f64,
+    };
+    kinds.original_ratio = ratio(kinds.original);
+    kinds.reply_ratio = ratio(kinds.replies);
+    kinds.quote_ratio = ratio(kinds.quotes);
+    kinds.retweet_ratio = ratio(kinds.retweets);
+
+    // Days in a streak are consecutive, so each day minus its position in the list of days
+    // is the same for the whole streak.
+    let longest_streaks = query_rows::<PostingStreak>(
+        db,
+        statement(&format!(
+            ", days AS (SELECT DISTINCT date(created_at) AS day FROM user_tweets), \
+             streaks AS (SELECT day, julianday(day) - ROW_NUMBER() OVER (ORDER BY day) AS streak FROM days) \
+             SELECT MIN(day) AS first_day, MAX(day) AS last_day, COUNT(*) AS days FROM streaks \
+             GROUP BY streak ORDER BY days DESC, first_day LIMIT {LONGEST_STREAKS}"
+        )),
+    )
+    .await;
+
+    let threads = query_rows::<ThreadsRow>(
+        db,
+        statement(
+            "SELECT COUNT(*) AS threads, AVG(length) AS average_length FROM ( \
+             SELECT COUNT(*) AS length FROM user_tweets WHERE conversation_id IN \
+             (SELECT id FROM user_tweets WHERE id = conversation_id) \
+             GROUP BY conversation_id)",
+        ),
+    )
+    .await
+    .pop()
+    .unwrap_or(ThreadsRow {
+        threads: 0,
+        average_length: None,
+    });
+
+    UserActivity {
+        user_id,
+        tweets,
+        per_day,
+        per_week,
+        per_month,
+        per_hour,
+        weekday_hours,
+        kinds,
+        longest_streaks,
+        threads: threads.threads,
+        average_thread_length: threads.average_length.unwrap_or(0.0),
+    }
+}
+
+// Counts the replies and quotes between each pair of users, leaving out the ones users
+// make to themselves in threads and the ones to or from hidden tweets. With `user_id` only
+// the pairs that include that user are counted.
+pub async fn interaction_edges(
+    db: &State<DatabaseConnection>,
+    user_id: Option<i64>,
+) -> Vec<InteractionEdge> {
+    let db = db as &DatabaseConnection;
+    let (filter, values) = match user_id {
+        Some(user_id) => (
+            "AND (source.author_id = ?1 OR target.author_id = ?1)",
+            vec![user_id.into()],
+        ),
+        None => ("", Vec::new()),
+    };
+    query_rows(
+        db,
+        Statement::from_sql_and_values(
+            db.get_database_backend(),
+            &format!(
+                "SELECT source.author_id AS source_user_id, target.author_id AS target_user_id, \
+                 SUM(CASE WHEN tweet_references.reference_type = 'replied_to' THEN 1 ELSE 0 END) AS replies, \
+                 SUM(CASE WHEN tweet_references.reference_type = 'quoted' THEN 1 ELSE 0 END) AS quotes, \
+                 COUNT(*) AS weight, \
+                 strftime('%Y-%m-%dT%H:%M:%SZ', MIN(source.created_at)) AS first_at, \
+                 strftime('%Y-%m-%dT%H:%M:%SZ', MAX(source.created_at)) AS last_at \
+                 FROM tweet_references \
+                 JOIN tweets AS source ON source.id = tweet_references.source_tweet_id \
+                 JOIN tweets AS target ON target.id = tweet_references.referenced_tweet_id \
+                 WHERE tweet_references.reference_type IN ('replied_to', 'quoted') \
+                 AND source.author_id <> target.author_id \
+                 AND source.id NOT IN (SELECT tweet_id FROM hidden_tweets) \
+                 AND target.id NOT IN (SELECT tweet_id FROM hidden_tweets) {filter} \
+                 GROUP BY source.author_id, target.author_id \
+                 ORDER BY weight DESC, source_user_id, target_user_id"
+            ),
+            values,
+        ),
+    )
+    .await
+}
+
+async fn query_rows<T: FromQueryResult>(db: &DatabaseConnection, statement: Statement) -> Vec<T> {
+    T::find_by_statement(statement.clone())
+        .all(db)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to run {}. Error: {:?}", statement.sql, error))
+}
+
+// Checks every table for rows that point at missing rows, duplicate keys and dates that
+// can't be parsed. Only ids are selected so a malformed row can't break the checks.
+pub async fn verify(db: &State<DatabaseConnection>) -> VerifyReport {
+    let db = db as &DatabaseConnection;
+    let tweets_missing_author = ids(
+        db,
+        "tweets with missing authors",
+        Tweets::find()
+            .select_only()
+            .column(tweets::Column::Id)
+            .join(JoinType::LeftJoin, tweets::Relation::Users.def())
+            .filter(users::Column::Id.is_null()),
+    )
+    .await;
+    let tweets_missing_conversation = ids(
+        db,
+        "tweets with missing conversations",
+        Tweets::find()
+            .select_only()
+            .column(tweets::Column::Id)
+            .join(JoinType::LeftJoin, tweets::Relation::Conversations.def())
+            .filter(conversations::Column::Id.is_null()),
+    )
+    .await;
+    let references_missing_source_tweet = TweetReferences::find()
+        .join(JoinType::LeftJoin, tweet_references::Relation::Tweets1.def())
+        .filter(tweets::Column::Id.is_null())
+        .all(db)
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to find references from tweets that aren't archived. Error: {:?}",
+                error
+            )
+        });
+    let references_missing_tweet = TweetReferences::find()
+        .join(JoinType::LeftJoin, tweet_references::Relation::Tweets2.def())
+        .filter(tweets::Column::Id.is_null())
+        .all(db)
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to find references to tweets that aren't archived. Error: {:?}",
+                error
+            )
+        });
+    let conversations_without_tweets = ids(
+        db,
+        "conversations without tweets",
+        Conversations::find()
+            .select_only()
+            .column(conversations::Column::Id)
+            .join(JoinType::LeftJoin, conversations::Relation::Tweets.def())
+            .filter(tweets::Column::Id.is_null()),
+    )
+    .await;
+
+    let backend = db.get_database_backend();
+    let duplicates = |table: &str, column: &str| {
+        Statement::from_string(
+            backend,
+            format!(
+                "SELECT {column} AS id FROM {table} GROUP BY {column} HAVING COUNT(*) > 1 ORDER BY {column}"
+            ),
+        )
+    };
+    let duplicate_user_ids = raw_ids(db, duplicates("users", "id")).await;
+    let duplicate_conversation_ids = raw_ids(db, duplicates("conversations", "id")).await;
+    let duplicate_tweet_ids = raw_ids(db, duplicates("tweets", "id")).await;
+    let duplicate_reference_source_ids = raw_ids(
+        db,
+        Statement::from_string(
+            backend,
+            "SELECT source_tweet_id AS id FROM tweet_references \
+             GROUP BY source_tweet_id, referenced_tweet_id HAVING COUNT(*) > 1 \
+             ORDER BY source_tweet_id"
+                .to_owned(),
+        ),
     )
+    .await;
+    let duplicate_usernames = UsernameRow::find_by_statement(Statement::from_string(
+        backend,
+        "SELECT LOWER(username) AS username FROM users GROUP BY LOWER(username) \
+         HAVING COUNT(*) > 1 ORDER BY LOWER(username)"
+            .to_owned(),
+    ))
+    .all(db)
     .await
+    .unwrap_or_else(|error| panic!("Failed to find duplicate usernames. Error: {:?}", error))
+    .into_iter()
+    .map(|row| row.username)
+    .collect();
+
+    let malformed_dates = Tweets::find()
+        .select_only()
+        .column(tweets::Column::Id)
+        .column(tweets::Column::CreatedAt)
+        .order_by_asc(tweets::Column::Id)
+        .into_model::<DateRow>()
+        .all(db)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to read tweet dates. Error: {:?}", error))
+        .into_iter()
+        .filter(|row| !is_valid_date(&row.created_at))
+        .map(|row| MalformedDate {
+            tweet_id: row.id,
+            created_at: row.created_at,
+        })
+        .collect();
+
+    VerifyReport {
+        tweets_missing_author,
+        tweets_missing_conversation,
+        references_missing_source_tweet,
+        references_missing_tweet,
+        conversations_without_tweets,
+        duplicate_user_ids,
+        duplicate_conversation_ids,
+        duplicate_tweet_ids,
+        duplicate_reference_source_ids,
+        duplicate_usernames,
+        malformed_dates,
+    }
+}
+
+pub async fn authors_of_tweets(db: &State<DatabaseConnection>, tweet_ids: &[i64]) -> Vec<i64> {
+    tweet_column(db, tweet_ids, tweets::Column::AuthorId).await
+}
+
+pub async fn conversations_of_tweets(db: &State<DatabaseConnection>, tweet_ids: &[i64]) -> Vec<i64> {
+    tweet_column(db, tweet_ids, tweets::Column::ConversationId).await
+}
+
+// The distinct values of an id column across the given tweets, without reading whole rows.
+async fn tweet_column(
+    db: &State<DatabaseConnection>,
+    tweet_ids: &[i64],
+    column: tweets::Column,
+) -> Vec<i64> {
+    let mut values = Vec::new();
+    for chunk in tweet_ids.chunks(MAX_QUERY_PARAMETERS) {
+        values.extend(
+            ids(
+                db,
+                "columns of tweets",
+                Tweets::find()
+                    .select_only()
+                    .column_as(column, "id")
+                    .filter(tweets::Column::Id.is_in(chunk.to_vec())),
+            )
+            .await,
+        );
+    }
+    values.sort_unstable();
+    values.dedup();
+    values
+}
+
+#[derive(Debug, FromQueryResult)]
+struct IdRow {
+    id: i64,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct CollectionCountRow {
+    collection_id: i32,
+    tweets: i64,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct UsernameRow {
+    username: String,
+}
+
+#[derive(Debug, FromQueryResult)]
+pub struct TweetText {
+    pub content: String,
+    pub created_at: DateTime<FixedOffset>,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct WeekdayHourRow {
+    weekday: i64,
+    hour: i64,
+    tweets: i64,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct KindRow {
+    kind: String,
+    tweets: i64,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct ThreadsRow {
+    threads: i64,
+    average_length: Option<f64>,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct DateRow {
+    id: i64,
+    created_at: String,
+}
+
+async fn ids<E: EntityTrait>(db: &DatabaseConnection, description: &str, select: Select<E>) -> Vec<i64> {
+    select
+        .into_model::<IdRow>()
+        .all(db)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to find {description}. Error: {:?}", error))
+        .into_iter()
+        .map(|row| row.id)
+        .collect()
+}
+
+async fn raw_ids(db: &DatabaseConnection, statement: Statement) -> Vec<i64> {
+    IdRow::find_by_statement(statement.clone())
+        .all(db)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to run {}. Error: {:?}", statement.sql, error))
+        .into_iter()
+        .map(|row| row.id)
+        .collect()
+}
+
+// The formats sqlx reads back as a `DateTime<FixedOffset>`.
+fn is_valid_date(date: &str) -> bool {
+    chrono::DateTime::parse_from_rfc3339(date).is_ok()
+        || ["%F %T%.f%:z", "%FT%T%.f%:z", "%F %T%.f%#z"]
+            .iter()
+            .any(|format| chrono::DateTime::parse_from_str(date, format).is_ok())
+        || ["%F %T%.f", "%FT%T%.f"]
+            .iter()
+            .any(|format| chrono::NaiveDateTime::parse_from_str(date, format).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::data::setup;
+    use crate::app::data::write::{self, DumpedRows};
+    use chrono::DateTime;
+
+    fn tweet(id: i64, author_id: i64, conversation_id: i64, created_at: &str) -> tweets::Model {
+        tweets::Model {
+            id,
+            content: format!("Tweet {id}"),
+            author_id,
+            conversation_id,
+            created_at: DateTime::parse_from_rfc3339(created_at).unwrap(),
+        }
+    }
+
+    fn reference(source: i64, kind: &str, referenced: i64) -> tweet_references::Model {
+        tweet_references::Model {
+            source_tweet_id: source,
+            reference_type: kind.to_string(),
+            referenced_tweet_id: referenced,
+        }
+    }
+
+    // User 1 tweets every day from thursday 2020-12-31, which is in ISO week 53 of 2020,
+    // to monday 2021-01-04, then again on the sunday and monday after. Tweet 17 would make
+    // the first streak longer but is hidden, and tweet 20 is someone else's.
+    #[tokio::test]
+    async fn user_activity_counts_visible_tweets_by_week_hour_kind_and_streak() {
+        let db = setup::test_db("user-activity").await;
+        let state = State::from(&db);
+        write::dumped_rows(
+            state,
+            DumpedRows {
+                users: Vec::new(),
+                conversations: Vec::new(),
+                tweets: vec![
+                    tweet(10, 1, 10, "2020-12-31T09:00:00+00:00"),
+                    tweet(11, 1, 10, "2021-01-01T09:30:00+00:00"),
+                    tweet(12, 1, 99, "2021-01-02T14:00:00+00:00"),
+                    tweet(13, 1, 98, "2021-01-03T14:00:00+00:00"),
+                    tweet(14, 1, 97, "2021-01-04T23:00:00+00:00"),
+                    tweet(15, 1, 15, "2021-01-10T09:00:00+00:00"),
+                    tweet(16, 1, 16, "2021-01-11T09:00:00+00:00"),
+                    tweet(17, 1, 17, "2021-01-05T09:00:00+00:00"),
+                    tweet(20, 2, 20, "2021-01-05T09:00:00+00:00"),
+                ],
+                // The retweet also replies, but only counts as a retweet.
+                references: vec![
+                    reference(11, "replied_to", 10),
+                    reference(12, "quoted", 99),
+                    reference(13, "retweeted", 98),
+                    reference(13, "replied_to", 98),
+                    reference(14, "replied_to", 97),
+                ],
+            },
+        )
+        .await;
+        write::hidden_tweet(
+            state,
+            &hidden_tweets::Model {
+                tweet_id: 17,
+                reason: "Asked to".to_string(),
+                hidden_at: DateTime::parse_from_rfc3339("2021-02-01T00:00:00+00:00").unwrap(),
+            },
+        )
+        .await;
+
+        let activity = user_activity(state, 1).await;
+        assert_eq!(activity.tweets, 7);
+        let counts = |periods: &[PeriodCount]| -> Vec<(String, i64)> {
+            periods
+                .iter()
+                .map(|period| (period.period.clone(), period.tweets))
+                .collect()
+        };
+        assert_eq!(
+            counts(&activity.per_week),
+            [("2020-W53", 4), ("2021-W01", 2), ("2021-W02", 1)]
+                .map(|(week, tweets)| (week.to_string(), tweets))
+        );
+        let mut per_hour = vec![0; 24];
+        per_hour[9] = 4;
+        per_hour[14] = 2;
+        per_hour[23] = 1;
+        assert_eq!(activity.per_hour, per_hour);
+        assert_eq!(activity.weekday_hours[0].weekday, "Monday");
+        assert_eq!(activity.weekday_hours[0].hours[9], 1);
+        assert_eq!(activity.weekday_hours[0].hours[23], 1);
+        assert_eq!(activity.weekday_hours[3].hours[9], 1);
+
+        let kinds = &activity.kinds;
+        assert_eq!(
+            (kinds.original, kinds.replies, kinds.quotes, kinds.retweets),
+            (3, 2, 1, 1)
+        );
+        assert_eq!(kinds.original_ratio, 3.0 / 7.0);
+        assert_eq!(kinds.reply_ratio, 2.0 / 7.0);
+        assert_eq!(kinds.quote_ratio, 1.0 / 7.0);
+        assert_eq!(kinds.retweet_ratio, 1.0 / 7.0);
+
+        let streaks: Vec<(&str, &str, i64)> = activity
+            .longest_streaks
+            .iter()
+            .map(|streak| (streak.first_day.as_str(), streak.last_day.as_str(), streak.days))
+            .collect();
+        assert_eq!(
+            streaks,
+            [("2020-12-31", "2021-01-04", 5), ("2021-01-10", "2021-01-11", 2)]
+        );
+
+        // Conversations 10, 15 and 16 are threads user 1 started, with 2, 1 and 1 tweets.
+        assert_eq!(activity.threads, 3);
+        assert_eq!(activity.average_thread_length, 4.0 / 3.0);
+    }
 }
@@ -1,14 +1,17 @@
 // src/setup.rs
 
+use std::str::FromStr;
+
+use migration::{Migrator, MigratorTrait};
 use sea_orm::*;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::ConnectOptions as _;
 
 // Replace with your database URL
-const DATABASE_URL: &str = "sqlite:./tweets.db";
+const DATABASE_URL: &str = "sqlite:./tweets.db?mode=rwc";
 
 pub(crate) async fn set_up_db() -> Result<DatabaseConnection, DbErr> {
-    let mut opt = ConnectOptions::new(DATABASE_URL.to_owned());
-    opt.sqlx_logging(false);
-    let db = Database::connect(opt).await?;
+    let db = connect_sqlite(DATABASE_URL).await?;
 
     // Replace with your desired database name
     let db_name = "tweets_db";
@@ -44,4 +47,62 @@ pub(crate) async fn set_up_db() -> Result<DatabaseConnection, DbErr> {
     Ok(db)
 }
 
+// Opens a sqlite database other than the archive, such as a benchmark fixture, creating
+// the file if it doesn't exist.
+#[cfg(any(test, feature = "bench"))]
+pub(crate) async fn open_sqlite(path: &std::path::Path) -> Result<DatabaseConnection, DbErr> {
+    connect_sqlite(&format!("sqlite:{}?mode=rwc", path.display())).await
+}
+
+// Sqlx has sqlite check foreign keys, but the archive keeps references to tweets it hasn't
+// archived, and dumps can bring back tweets whose author isn't archived either, so the
+// checks are left off and `verify` reports whatever dangles.
+async fn connect_sqlite(url: &str) -> Result<DatabaseConnection, DbErr> {
+    let mut options = SqliteConnectOptions::from_str(url)
+        .map_err(|error| DbErr::Conn(error.to_string()))?
+        .foreign_keys(false);
+    options.disable_statement_logging();
+    // SeaORM gives sqlite a single connection by default, which would make every request
+    // wait on whichever query is running.
+    let pool = SqlitePoolOptions::new()
+        .max_connections(8)
+        .connect_with(options)
+        .await
+        .map_err(|error| DbErr::Conn(error.to_string()))?;
+    Ok(SqlxSqliteConnector::from_sqlx_sqlite_pool(pool))
+}
+
+// Runs any migrations the database hasn't had yet, recording them in seaql_migrations.
+// Tables that were already made are left as they are.
+pub(crate) async fn migrate(db: &DatabaseConnection) -> Result<(), DbErr> {
+    Migrator::up(db, None).await
+}
+
+// A freshly migrated database of its own for a test, in the temp dir.
+#[cfg(test)]
+pub(crate) async fn test_db(name: &str) -> DatabaseConnection {
+    let path = std::env::temp_dir().join(format!(
+        "better-twitter-archiver-{name}-{}.db",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let db = open_sqlite(&path)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to open {}. Error: {:?}", path.display(), error));
+    migrate(&db)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to migrate {}. Error: {:?}", path.display(), error));
+    db
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn migrating_again_finds_nothing_to_run() {
+        let db = test_db("migrate-again").await;
+        migrate(&db).await.unwrap();
+        assert!(Migrator::get_pending_migrations(&db).await.unwrap().is_empty());
+    }
+}
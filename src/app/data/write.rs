@@ -1,27 +1,529 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
 use super::entities::prelude::*;
 use super::entities::*;
-use crate::app::load_user_from_id;
-use crate::utils::{TweetData, UserData};
-use futures::StreamExt;
+use crate::app::api;
+use crate::utils::{i64_to_u64, RedactionReport, TweetData, UserData, MAX_QUERY_PARAMETERS};
+use chrono::{DateTime, FixedOffset};
 use rocket::State;
 
-use sea_orm::{ActiveValue, DatabaseConnection, EntityTrait};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection,
+    DatabaseTransaction, EntityTrait, Insert, QueryFilter, QueryTrait, TransactionTrait,
+};
+use sea_orm::sea_query::{Expr, Func, IntoIden, OnConflict};
+
+// Rows per insert statement, which keeps each one well under sqlite's parameter limit.
+const INSERT_BATCH_SIZE: usize = 500;
 
 pub async fn tweet(db: &State<DatabaseConnection>, tweet_data: &TweetData) {
-    let tweet = tweet_data.tweet.clone();
-    if let Some(tweet) = tweet {
-    load_user_from_id(db, tweet.author_id).await;
-    if !super::read::does_conversation_exist(db, tweet.conversation_id).await {
-        conversation(db, &tweet.conversation_id).await;
+    tweets(db, std::slice::from_ref(tweet_data)).await;
+}
+
+// Writes a batch of tweets in one transaction, along with the authors and conversations
+// they need. Rows that are already archived are left alone apart from tweets whose text
+// has changed, which get a new version. If any write fails the whole batch is rolled back
+// so no tweet or reference is written without the rest. Tweets by redacted users, and the
+// users themselves, are dropped, as are tweets whose author isn't archived and can't be
+// fetched, until a later write brings the author.
+pub async fn tweets(db: &State<DatabaseConnection>, tweets: &[TweetData]) {
+    let mut tweets: BTreeMap<i64, &TweetData> = tweets
+        .iter()
+        .filter_map(|tweet_data| Some((tweet_data.tweet.as_ref()?.id, tweet_data)))
+        .collect();
+    if tweets.is_empty() {
+        return;
+    }
+    let redactions = super::read::redacted_users(db).await;
+    let mut redacted_ids: HashSet<i64> = redactions
+        .iter()
+        .filter_map(|redaction| redaction.user_id)
+        .collect();
+    let author_ids: BTreeSet<i64> = tweets
+        .values()
+        .filter_map(|tweet_data| tweet_data.tweet.as_ref())
+        .map(|tweet| tweet.author_id)
+        .filter(|author_id| !redacted_ids.contains(author_id))
+        .collect();
+    // Authors come from the api, so they are fetched before the transaction starts rather
+    // than holding it open across network calls.
+    let stored_authors = existing_ids::<Users>(
+        db.inner(),
+        users::Column::Id,
+        &author_ids.iter().copied().collect::<Vec<i64>>(),
+        |user| user.id,
+    )
+    .await;
+    let mut authors = missing_authors(&author_ids, &stored_authors).await;
+    redacted_ids.extend(
+        authors
+            .iter()
+            .filter(|author| {
+                redactions.iter().any(|redaction| {
+                    redaction.username.as_deref() == Some(&*author.username.to_lowercase())
+                })
+            })
+            .map(|author| author.id),
+    );
+    authors.retain(|author| !redacted_ids.contains(&author.id));
+    let known_authors: HashSet<i64> = stored_authors
+        .iter()
+        .copied()
+        .chain(authors.iter().map(|author| author.id))
+        .collect();
+    tweets.retain(|_id, tweet_data| {
+        tweet_data
+            .tweet
+            .as_ref()
+            .is_some_and(|tweet| !redacted_ids.contains(&tweet.author_id))
+    });
+    let orphans = tweets.len();
+    tweets.retain(|_id, tweet_data| {
+        tweet_data
+            .tweet
+            .as_ref()
+            .is_some_and(|tweet| known_authors.contains(&tweet.author_id))
+    });
+    if tweets.len() < orphans {
+        println!(
+            "Skipped {} tweets whose authors couldn't be fetched",
+            orphans - tweets.len()
+        );
     }
+    if tweets.is_empty() {
+        return;
     }
 
-    tweet_data.write(db).await;
+    let txn = db
+        .begin()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to start a transaction. Error: {:?}", error));
+    let users_to_write: Vec<users::ActiveModel> = authors
+        .into_iter()
+        .map(|user| users::ActiveModel {
+            id: ActiveValue::set(user.id),
+            name: ActiveValue::set(user.name),
+            username: ActiveValue::set(user.username),
+            description: ActiveValue::set(user.description),
+        })
+        .collect();
+    insert_batches(&txn, "users", users_to_write, |batch| {
+        skip_stored(Users::insert_many(batch), [users::Column::Id])
+    })
+    .await;
+
+    let conversations_to_write: Vec<conversations::ActiveModel> = tweets
+        .values()
+        .filter_map(|tweet_data| tweet_data.tweet.as_ref())
+        .map(|tweet| tweet.conversation_id)
+        .collect::<BTreeSet<i64>>()
+        .into_iter()
+        .map(|id| conversations::ActiveModel {
+            id: ActiveValue::set(id),
+        })
+        .collect();
+    insert_batches(&txn, "conversations", conversations_to_write, |batch| {
+        skip_stored(Conversations::insert_many(batch), [conversations::Column::Id])
+    })
+    .await;
+
+    // An edit made on twitter has an id of its own but is stored as a version of the tweet
+    // it edits, so only the original gets a row. When the original isn't in the batch the
+    // oldest edit stands in for it, and later edits become versions of that.
+    let mut rows: BTreeMap<i64, tweets::Model> = BTreeMap::new();
+    for tweet_data in tweets.values() {
+        if let Some(tweet) = tweet_data.tweet.as_ref() {
+            let original_id = original_id(tweet_data, tweet);
+            if tweet.id == original_id {
+                rows.insert(original_id, tweet.clone());
+            } else {
+                rows.entry(original_id).or_insert_with(|| tweets::Model {
+                    id: original_id,
+                    ..tweet.clone()
+                });
+            }
+        }
+    }
+    // Only tweets that weren't archived yet are embedded below; changed ones are embedded
+    // with their versions.
+    #[cfg(feature = "semantic-search")]
+    let new_tweets: Vec<tweets::Model> = {
+        let tweet_ids: Vec<i64> = rows.keys().copied().collect();
+        let existing_tweets =
+            existing_ids::<Tweets>(&txn, tweets::Column::Id, &tweet_ids, |tweet| tweet.id).await;
+        rows.values()
+            .filter(|tweet| !existing_tweets.contains(&tweet.id))
+            .cloned()
+            .collect()
+    };
+    let tweets_to_write: Vec<tweets::ActiveModel> = rows
+        .into_values()
+        .map(|tweet| tweets::ActiveModel {
+            id: ActiveValue::set(tweet.id),
+            content: ActiveValue::set(tweet.content),
+            author_id: ActiveValue::set(tweet.author_id),
+            conversation_id: ActiveValue::set(tweet.conversation_id),
+            created_at: ActiveValue::set(tweet.created_at),
+        })
+        .collect();
+    insert_batches(&txn, "tweets", tweets_to_write, |batch| {
+        skip_stored(Tweets::insert_many(batch), [tweets::Column::Id])
+    })
+    .await;
+    #[cfg_attr(not(feature = "semantic-search"), allow(unused_variables))]
+    let changed_content = record_versions(&txn, &tweets).await;
+    #[cfg(feature = "semantic-search")]
+    {
+        let embeddings: Vec<embeddings::Model> = new_tweets
+            .iter()
+            .map(|tweet| (tweet.id, &tweet.content))
+            .chain(changed_content.iter().map(|(id, content)| (*id, content)))
+            .map(|(id, content)| crate::app::semantic::embed(id, content))
+            .collect();
+        replace_embeddings(&txn, embeddings).await;
+    }
+
+    // An edit's references belong to the tweet it edits, which is the one with a row. A
+    // tweet can reference several others, so references are told apart by both ends.
+    let references: BTreeMap<(i64, i64), tweet_references::Model> = tweets
+        .values()
+        .filter_map(|tweet_data| Some((tweet_data, tweet_data.tweet.as_ref()?)))
+        .flat_map(|(tweet_data, tweet)| {
+            let original_id = original_id(tweet_data, tweet);
+            tweet_data
+                .references
+                .iter()
+                .map(move |reference| tweet_references::Model {
+                    source_tweet_id: original_id,
+                    ..reference.clone()
+                })
+        })
+        .map(|reference| {
+            (
+                (reference.source_tweet_id, reference.referenced_tweet_id),
+                reference,
+            )
+        })
+        .collect();
+    let references_to_write: Vec<tweet_references::ActiveModel> = references
+        .into_values()
+        .map(|reference| tweet_references::ActiveModel {
+            source_tweet_id: ActiveValue::set(reference.source_tweet_id),
+            reference_type: ActiveValue::set(reference.reference_type),
+            referenced_tweet_id: ActiveValue::set(reference.referenced_tweet_id),
+        })
+        .collect();
+    insert_batches(&txn, "tweet references", references_to_write, |batch| {
+        skip_stored(
+            TweetReferences::insert_many(batch),
+            [
+                tweet_references::Column::SourceTweetId,
+                tweet_references::Column::ReferencedTweetId,
+            ],
+        )
+    })
+    .await;
+
+    txn.commit().await.unwrap_or_else(|error| {
+        panic!(
+            "Failed to commit a batch of {} tweets. Error: {:?}",
+            tweets.len(),
+            error
+        )
+    });
 }
 
-pub async fn tweets(db: &State<DatabaseConnection>, tweets: &[TweetData]) {
-    let tweet_stream = futures::stream::iter(tweets.iter());
-    tweet_stream.for_each(|t| tweet(db, t)).await;
+// Rows read back from a dump, as they were dumped.
+#[derive(Debug, Default)]
+pub struct DumpedRows {
+    pub users: Vec<users::Model>,
+    pub conversations: Vec<conversations::Model>,
+    pub tweets: Vec<tweets::Model>,
+    pub references: Vec<tweet_references::Model>,
+}
+
+impl DumpedRows {
+    pub fn len(&self) -> usize {
+        self.users.len() + self.conversations.len() + self.tweets.len() + self.references.len()
+    }
+}
+
+// Writes rows from a dump exactly as they were dumped, in one transaction. Unlike `tweets`
+// nothing is fetched from the api and nothing is left out, so a restore brings back tweets
+// whose author isn't archived and references to tweets that aren't either. Rows that are
+// already stored are left alone.
+pub async fn dumped_rows(db: &State<DatabaseConnection>, rows: DumpedRows) {
+    let txn = db
+        .begin()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to start a transaction. Error: {:?}", error));
+    let users_to_write: Vec<users::ActiveModel> = rows
+        .users
+        .into_iter()
+        .map(|user| users::ActiveModel {
+            id: ActiveValue::set(user.id),
+            name: ActiveValue::set(user.name),
+            username: ActiveValue::set(user.username),
+            description: ActiveValue::set(user.description),
+        })
+        .collect();
+    insert_batches(&txn, "users", users_to_write, |batch| {
+        skip_stored(Users::insert_many(batch), [users::Column::Id])
+    })
+    .await;
+    let conversations_to_write: Vec<conversations::ActiveModel> = rows
+        .conversations
+        .into_iter()
+        .map(|conversation| conversations::ActiveModel {
+            id: ActiveValue::set(conversation.id),
+        })
+        .collect();
+    insert_batches(&txn, "conversations", conversations_to_write, |batch| {
+        skip_stored(Conversations::insert_many(batch), [conversations::Column::Id])
+    })
+    .await;
+    let tweets_to_write: Vec<tweets::ActiveModel> = rows
+        .tweets
+        .into_iter()
+        .map(|tweet| tweets::ActiveModel {
+            id: ActiveValue::set(tweet.id),
+            content: ActiveValue::set(tweet.content),
+            author_id: ActiveValue::set(tweet.author_id),
+            conversation_id: ActiveValue::set(tweet.conversation_id),
+            created_at: ActiveValue::set(tweet.created_at),
+        })
+        .collect();
+    insert_batches(&txn, "tweets", tweets_to_write, |batch| {
+        skip_stored(Tweets::insert_many(batch), [tweets::Column::Id])
+    })
+    .await;
+    let references_to_write: Vec<tweet_references::ActiveModel> = rows
+        .references
+        .into_iter()
+        .map(|reference| tweet_references::ActiveModel {
+            source_tweet_id: ActiveValue::set(reference.source_tweet_id),
+            reference_type: ActiveValue::set(reference.reference_type),
+            referenced_tweet_id: ActiveValue::set(reference.referenced_tweet_id),
+        })
+        .collect();
+    insert_batches(&txn, "tweet references", references_to_write, |batch| {
+        skip_stored(
+            TweetReferences::insert_many(batch),
+            [
+                tweet_references::Column::SourceTweetId,
+                tweet_references::Column::ReferencedTweetId,
+            ],
+        )
+    })
+    .await;
+    txn.commit()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to commit dumped rows. Error: {:?}", error));
+}
+
+// Records a version whenever a tweet arrives with other text than what's archived for it,
+// either under its own id or, for an edit made on twitter, under the id of the tweet it
+// edits. The first time, the archived text is kept as version 0. The archived tweet is
+// then updated to the newest text. Edits of tweets that aren't archived are skipped, as
+// there is nothing to compare them with. Returns the new text of the tweets that changed.
+async fn record_versions(
+    txn: &DatabaseTransaction,
+    tweets: &BTreeMap<i64, &TweetData>,
+) -> BTreeMap<i64, String> {
+    // Tweet ids grow over time, so going through them in order replays edits in order.
+    let observations: Vec<(i64, &tweets::Model)> = tweets
+        .values()
+        .filter_map(|tweet_data| {
+            let tweet = tweet_data.tweet.as_ref()?;
+            Some((original_id(tweet_data, tweet), tweet))
+        })
+        .collect();
+    let original_ids: Vec<i64> = observations
+        .iter()
+        .map(|(original_id, _tweet)| *original_id)
+        .collect::<BTreeSet<i64>>()
+        .into_iter()
+        .collect();
+    let archived: HashMap<i64, tweets::Model> =
+        existing_rows::<Tweets>(txn, tweets::Column::Id, &original_ids)
+            .await
+            .into_iter()
+            .map(|tweet| (tweet.id, tweet))
+            .collect();
+    let mut versions: HashMap<i64, Vec<tweet_versions::Model>> = HashMap::new();
+    for version in
+        existing_rows::<TweetVersions>(txn, tweet_versions::Column::TweetId, &original_ids).await
+    {
+        versions.entry(version.tweet_id).or_default().push(version);
+    }
+
+    let recorded_at: DateTime<FixedOffset> = chrono::Utc::now().into();
+    let mut versions_to_write: Vec<tweet_versions::ActiveModel> = Vec::new();
+    let mut newest_content: BTreeMap<i64, String> = BTreeMap::new();
+    for (original_id, tweet) in observations {
+        let original = match archived.get(&original_id) {
+            Some(original) => original,
+            None => continue,
+        };
+        let known = versions.entry(original_id).or_default();
+        known.sort_by_key(|version| version.version);
+        let current = known
+            .last()
+            .map(|version| &version.content)
+            .unwrap_or(&original.content);
+        let seen = known.iter().any(|version| {
+            version.edit_tweet_id == tweet.id && version.content == tweet.content
+        });
+        if *current == tweet.content || seen {
+            continue;
+        }
+        let mut new_versions = Vec::new();
+        if known.is_empty() {
+            new_versions.push(tweet_versions::Model {
+                tweet_id: original_id,
+                version: 0,
+                edit_tweet_id: original_id,
+                content: original.content.clone(),
+                recorded_at: original.created_at,
+            });
+        }
+        new_versions.push(tweet_versions::Model {
+            tweet_id: original_id,
+            version: (known.len() + new_versions.len()) as i32,
+            edit_tweet_id: tweet.id,
+            content: tweet.content.clone(),
+            // An edit made on twitter is a tweet of its own with the time it was made.
+            recorded_at: if tweet.id == original_id {
+                recorded_at
+            } else {
+                tweet.created_at
+            },
+        });
+        versions_to_write.extend(new_versions.iter().map(version_to_active_model));
+        known.extend(new_versions);
+        newest_content.insert(original_id, tweet.content.clone());
+    }
+    insert_batches(txn, "tweet versions", versions_to_write, |batch| {
+        skip_stored(
+            TweetVersions::insert_many(batch),
+            [tweet_versions::Column::TweetId, tweet_versions::Column::Version],
+        )
+    })
+    .await;
+
+    for (id, content) in newest_content.iter() {
+        Tweets::update_many()
+            .col_expr(tweets::Column::Content, Expr::value(content.clone()))
+            .filter(tweets::Column::Id.eq(*id))
+            .exec(txn)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to update the text of tweet {id}. Error: {:?}", error)
+            });
+    }
+    newest_content
+}
+
+// The id of the first version of the tweet, which is its own id unless it's an edit.
+fn original_id(tweet_data: &TweetData, tweet: &tweets::Model) -> i64 {
+    tweet_data
+        .edit_history_tweet_ids
+        .first()
+        .copied()
+        .unwrap_or(tweet.id)
+}
+
+fn version_to_active_model(version: &tweet_versions::Model) -> tweet_versions::ActiveModel {
+    tweet_versions::ActiveModel {
+        tweet_id: ActiveValue::set(version.tweet_id),
+        version: ActiveValue::set(version.version),
+        edit_tweet_id: ActiveValue::set(version.edit_tweet_id),
+        content: ActiveValue::set(version.content.clone()),
+        recorded_at: ActiveValue::set(version.recorded_at),
+    }
+}
+
+// Fetches the authors that aren't archived yet from the api. In offline mode they are
+// left out, the same as when a single tweet is loaded.
+async fn missing_authors(
+    author_ids: &BTreeSet<i64>,
+    stored_authors: &HashSet<i64>,
+) -> Vec<users::Model> {
+    if api::is_offline() {
+        return Vec::new();
+    }
+    let mut authors = Vec::new();
+    for id in author_ids.iter().copied().filter(|id| !stored_authors.contains(id)) {
+        if let Some(user) = api::get_user_by_id(i64_to_u64(id)).await.user {
+            authors.push(user);
+        }
+    }
+    authors
+}
+
+async fn existing_ids<E: EntityTrait>(
+    db: &impl ConnectionTrait,
+    column: E::Column,
+    ids: &[i64],
+    id: fn(&E::Model) -> i64,
+) -> HashSet<i64> {
+    existing_rows::<E>(db, column, ids)
+        .await
+        .iter()
+        .map(id)
+        .collect()
+}
+
+async fn existing_rows<E: EntityTrait>(
+    db: &impl ConnectionTrait,
+    column: E::Column,
+    ids: &[i64],
+) -> Vec<E::Model> {
+    let mut existing = Vec::new();
+    for chunk in ids.chunks(MAX_QUERY_PARAMETERS) {
+        existing.extend(
+            E::find()
+                .filter(column.is_in(chunk.to_vec()))
+                .all(db)
+                .await
+                .unwrap_or_else(|error| {
+                    panic!("Failed to read existing rows from database. Error: {:?}", error)
+                }),
+        );
+    }
+    existing
+}
+
+// Makes the insert leave alone rows whose `columns` match a stored row rather than fail,
+// so that writers that overlap, like the scheduler and a sync asked for by a request, don't
+// trip over each other's rows.
+fn skip_stored<A, C, I>(mut insert: Insert<A>, columns: I) -> Insert<A>
+where
+    A: ActiveModelTrait,
+    C: IntoIden,
+    I: IntoIterator<Item = C>,
+{
+    insert
+        .query()
+        .on_conflict(OnConflict::columns(columns).do_nothing().to_owned());
+    insert
+}
+
+// Panicking drops the transaction, which rolls it back.
+async fn insert_batches<A: ActiveModelTrait>(
+    txn: &DatabaseTransaction,
+    table: &str,
+    rows: Vec<A>,
+    insert: impl Fn(Vec<A>) -> Insert<A>,
+) {
+    let mut rows = rows.into_iter().peekable();
+    while rows.peek().is_some() {
+        let batch: Vec<A> = rows.by_ref().take(INSERT_BATCH_SIZE).collect();
+        let count = batch.len();
+        insert(batch).exec(txn).await.unwrap_or_else(|error| {
+            panic!("Failed to insert {count} {table} into database. Error: {:?}", error)
+        });
+    }
 }
 
 pub async fn user(db: &State<DatabaseConnection>, user: &UserData) {
@@ -37,3 +539,935 @@ pub async fn conversation(db: &State<DatabaseConnection>, conversation_id: &i64)
         .await
         .expect("failed to insert conversation {conversation_id} into database");
 }
+
+pub async fn tweet_status(db: &State<DatabaseConnection>, status: &tweet_status::Model) {
+    tweet_statuses(db, std::slice::from_ref(status)).await;
+}
+
+// Replaces the stored statuses of the given tweets in one transaction.
+pub async fn tweet_statuses(db: &State<DatabaseConnection>, statuses: &[tweet_status::Model]) {
+    let txn = db
+        .begin()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to start a transaction. Error: {:?}", error));
+    let ids: Vec<i64> = statuses.iter().map(|status| status.tweet_id).collect();
+    for chunk in ids.chunks(MAX_QUERY_PARAMETERS) {
+        TweetStatus::delete_many()
+            .filter(tweet_status::Column::TweetId.is_in(chunk.to_vec()))
+            .exec(&txn)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to clear old tweet statuses. Error: {:?}", error)
+            });
+    }
+    let rows = statuses
+        .iter()
+        .map(|status| tweet_status::ActiveModel {
+            tweet_id: ActiveValue::set(status.tweet_id),
+            status: ActiveValue::set(status.status.clone()),
+            detail: ActiveValue::set(status.detail.clone()),
+            checked_at: ActiveValue::set(status.checked_at),
+        })
+        .collect();
+    insert_batches(&txn, "tweet statuses", rows, |batch| {
+        TweetStatus::insert_many(batch)
+    })
+    .await;
+    txn.commit()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to commit tweet statuses. Error: {:?}", error));
+}
+
+// Writes versions from a dump, skipping any the tweet already has.
+pub async fn tweet_versions(db: &State<DatabaseConnection>, versions: &[tweet_versions::Model]) {
+    let txn = db
+        .begin()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to start a transaction. Error: {:?}", error));
+    let rows = versions.iter().map(version_to_active_model).collect();
+    insert_batches(&txn, "tweet versions", rows, |batch| {
+        skip_stored(
+            TweetVersions::insert_many(batch),
+            [tweet_versions::Column::TweetId, tweet_versions::Column::Version],
+        )
+    })
+    .await;
+    txn.commit()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to commit tweet versions. Error: {:?}", error));
+}
+
+// Writes collections, the tweets in them and tags from a dump in one transaction, keeping
+// the ids collections were dumped with so their tweets stay in them. Rows that are already
+// stored are left alone.
+pub async fn dumped_collections(
+    db: &State<DatabaseConnection>,
+    collections: &[collections::Model],
+    entries: &[collection_tweets::Model],
+    tags: &[tweet_tags::Model],
+) {
+    let txn = db
+        .begin()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to start a transaction. Error: {:?}", error));
+    let collections_to_write = collections
+        .iter()
+        .map(|collection| collections::ActiveModel {
+            id: ActiveValue::set(collection.id),
+            name: ActiveValue::set(collection.name.clone()),
+            description: ActiveValue::set(collection.description.clone()),
+            created_at: ActiveValue::set(collection.created_at),
+            updated_at: ActiveValue::set(collection.updated_at),
+        })
+        .collect();
+    insert_batches(&txn, "collections", collections_to_write, |batch| {
+        skip_stored(Collections::insert_many(batch), [collections::Column::Id])
+    })
+    .await;
+    let entries_to_write = entries
+        .iter()
+        .map(|entry| collection_tweets::ActiveModel {
+            collection_id: ActiveValue::set(entry.collection_id),
+            tweet_id: ActiveValue::set(entry.tweet_id),
+            note: ActiveValue::set(entry.note.clone()),
+            added_at: ActiveValue::set(entry.added_at),
+        })
+        .collect();
+    insert_batches(&txn, "collection tweets", entries_to_write, |batch| {
+        skip_stored(
+            CollectionTweets::insert_many(batch),
+            [
+                collection_tweets::Column::CollectionId,
+                collection_tweets::Column::TweetId,
+            ],
+        )
+    })
+    .await;
+    let tags_to_write = tags
+        .iter()
+        .map(|tag| tweet_tags::ActiveModel {
+            tweet_id: ActiveValue::set(tag.tweet_id),
+            tag: ActiveValue::set(tag.tag.clone()),
+            tagged_at: ActiveValue::set(tag.tagged_at),
+        })
+        .collect();
+    insert_batches(&txn, "tweet tags", tags_to_write, |batch| {
+        skip_stored(
+            TweetTags::insert_many(batch),
+            [tweet_tags::Column::TweetId, tweet_tags::Column::Tag],
+        )
+    })
+    .await;
+    txn.commit()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to commit collections. Error: {:?}", error));
+}
+
+// Writes annotations from a dump with the ids they were dumped with, skipping any that are
+// already stored.
+pub async fn dumped_annotations(
+    db: &State<DatabaseConnection>,
+    annotations: &[annotations::Model],
+) {
+    let txn = db
+        .begin()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to start a transaction. Error: {:?}", error));
+    let rows = annotations
+        .iter()
+        .map(|annotation| annotations::ActiveModel {
+            id: ActiveValue::set(annotation.id),
+            author: ActiveValue::set(annotation.author.clone()),
+            tweet_id: ActiveValue::set(annotation.tweet_id),
+            conversation_id: ActiveValue::set(annotation.conversation_id),
+            body: ActiveValue::set(annotation.body.clone()),
+            created_at: ActiveValue::set(annotation.created_at),
+            updated_at: ActiveValue::set(annotation.updated_at),
+        })
+        .collect();
+    insert_batches(&txn, "annotations", rows, |batch| {
+        skip_stored(Annotations::insert_many(batch), [annotations::Column::Id])
+    })
+    .await;
+    txn.commit()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to commit annotations. Error: {:?}", error));
+}
+
+// Writes the embeddings in one transaction, replacing any the tweets already had.
+#[cfg(feature = "semantic-search")]
+pub async fn embeddings(db: &State<DatabaseConnection>, embeddings: &[embeddings::Model]) {
+    let txn = db
+        .begin()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to start a transaction. Error: {:?}", error));
+    replace_embeddings(&txn, embeddings.to_vec()).await;
+    txn.commit()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to commit embeddings. Error: {:?}", error));
+}
+
+#[cfg(feature = "semantic-search")]
+async fn replace_embeddings(txn: &DatabaseTransaction, embeddings: Vec<embeddings::Model>) {
+    let ids: Vec<i64> = embeddings.iter().map(|embedding| embedding.tweet_id).collect();
+    for chunk in ids.chunks(MAX_QUERY_PARAMETERS) {
+        Embeddings::delete_many()
+            .filter(embeddings::Column::TweetId.is_in(chunk.to_vec()))
+            .exec(txn)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to clear old embeddings. Error: {:?}", error)
+            });
+    }
+    let rows = embeddings
+        .into_iter()
+        .map(|embedding| embeddings::ActiveModel {
+            tweet_id: ActiveValue::set(embedding.tweet_id),
+            vectorizer: ActiveValue::set(embedding.vectorizer),
+            vector: ActiveValue::set(embedding.vector),
+            indexed_at: ActiveValue::set(embedding.indexed_at),
+        })
+        .collect();
+    insert_batches(txn, "embeddings", rows, |batch| {
+        Embeddings::insert_many(batch)
+    })
+    .await;
+}
+
+pub async fn watched_account(db: &State<DatabaseConnection>, account: &watched_accounts::Model) {
+    let to_write = watched_accounts::ActiveModel {
+        username: ActiveValue::set(account.username.clone()),
+        interval_seconds: ActiveValue::set(account.interval_seconds),
+        last_synced_at: ActiveValue::set(account.last_synced_at),
+        last_seen_tweet_id: ActiveValue::set(account.last_seen_tweet_id),
+        last_error: ActiveValue::set(account.last_error.clone()),
+    };
+    let res = if super::read::watched_account(db, &account.username)
+        .await
+        .is_some()
+    {
+        WatchedAccounts::update(to_write)
+            .exec(db.inner())
+            .await
+            .map(|_res| ())
+    } else {
+        WatchedAccounts::insert(to_write)
+            .exec(db.inner())
+            .await
+            .map(|_res| ())
+    };
+
+    match res {
+        Ok(()) => (),
+        Err(error) => println!(
+            "Failed to write watched account @{} to the database. Error: {:?}",
+            account.username, error
+        ),
+    }
+}
+
+pub async fn remove_watched_account(db: &State<DatabaseConnection>, twitter_handle: &str) -> bool {
+    delete_watched_account(db.inner(), twitter_handle).await
+}
+
+// Handles are matched in any case, as twitter matches them.
+async fn delete_watched_account(db: &impl ConnectionTrait, twitter_handle: &str) -> bool {
+    WatchedAccounts::delete_many()
+        .filter(
+            Expr::expr(Func::lower(Expr::col(watched_accounts::Column::Username)))
+                .eq(twitter_handle.to_lowercase()),
+        )
+        .exec(db)
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to remove watched account @{twitter_handle} from the database. Error: {:?}",
+                error
+            )
+        })
+        .rows_affected
+        > 0
+}
+
+// Adds a new key, returning it with the id it was given.
+pub async fn api_key(db: &State<DatabaseConnection>, key: &api_keys::Model) -> api_keys::Model {
+    let to_write = api_keys::ActiveModel {
+        id: ActiveValue::NotSet,
+        name: ActiveValue::set(key.name.clone()),
+        prefix: ActiveValue::set(key.prefix.clone()),
+        key_hash: ActiveValue::set(key.key_hash.clone()),
+        scopes: ActiveValue::set(key.scopes.clone()),
+        requests_per_minute: ActiveValue::set(key.requests_per_minute),
+        created_at: ActiveValue::set(key.created_at),
+        last_used_at: ActiveValue::set(key.last_used_at),
+        revoked_at: ActiveValue::set(key.revoked_at),
+    };
+    let id = ApiKeys::insert(to_write)
+        .exec(db.inner())
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to write api key {} to the database. Error: {:?}", key.name, error)
+        })
+        .last_insert_id;
+    api_keys::Model {
+        id,
+        ..key.clone()
+    }
+}
+
+pub async fn api_key_used(db: &State<DatabaseConnection>, id: i32, used_at: DateTime<FixedOffset>) {
+    let res = ApiKeys::update_many()
+        .col_expr(api_keys::Column::LastUsedAt, Expr::value(used_at))
+        .filter(api_keys::Column::Id.eq(id))
+        .exec(db.inner())
+        .await;
+    if let Err(error) = res {
+        println!("Failed to record when api key {id} was last used. Error: {:?}", error);
+    }
+}
+
+// Returns whether there was a key of that id that wasn't revoked already.
+pub async fn revoke_api_key(
+    db: &State<DatabaseConnection>,
+    id: i32,
+    revoked_at: DateTime<FixedOffset>,
+) -> bool {
+    ApiKeys::update_many()
+        .col_expr(api_keys::Column::RevokedAt, Expr::value(revoked_at))
+        .filter(api_keys::Column::Id.eq(id))
+        .filter(api_keys::Column::RevokedAt.is_null())
+        .exec(db.inner())
+        .await
+        .unwrap_or_else(|error| panic!("Failed to revoke api key {id}. Error: {:?}", error))
+        .rows_affected
+        == 1
+}
+
+pub async fn hidden_tweet(db: &State<DatabaseConnection>, hidden: &hidden_tweets::Model) {
+    write_hidden_tweet(db.inner(), hidden).await;
+}
+
+async fn write_hidden_tweet(db: &impl ConnectionTrait, hidden: &hidden_tweets::Model) {
+    HiddenTweets::delete_by_id(hidden.tweet_id)
+        .exec(db)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to clear how tweet {} was hidden. Error: {:?}", hidden.tweet_id, error)
+        });
+    let to_write = hidden_tweets::ActiveModel {
+        tweet_id: ActiveValue::set(hidden.tweet_id),
+        reason: ActiveValue::set(hidden.reason.clone()),
+        hidden_at: ActiveValue::set(hidden.hidden_at),
+    };
+    HiddenTweets::insert(to_write)
+        .exec(db)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to hide tweet {}. Error: {:?}", hidden.tweet_id, error)
+        });
+}
+
+// Returns whether the tweet was hidden.
+pub async fn remove_hidden_tweet(db: &State<DatabaseConnection>, tweet_id: i64) -> bool {
+    HiddenTweets::delete_by_id(tweet_id)
+        .exec(db.inner())
+        .await
+        .unwrap_or_else(|error| panic!("Failed to unhide tweet {tweet_id}. Error: {:?}", error))
+        .rows_affected
+        == 1
+}
+
+// Adds a redaction, returning it with the id it was given.
+pub async fn redacted_user(
+    db: &State<DatabaseConnection>,
+    redaction: &redacted_users::Model,
+) -> redacted_users::Model {
+    write_redacted_user(db.inner(), redaction).await
+}
+
+async fn write_redacted_user(
+    db: &impl ConnectionTrait,
+    redaction: &redacted_users::Model,
+) -> redacted_users::Model {
+    let to_write = redacted_users::ActiveModel {
+        id: ActiveValue::NotSet,
+        user_id: ActiveValue::set(redaction.user_id),
+        username: ActiveValue::set(redaction.username.clone()),
+        reason: ActiveValue::set(redaction.reason.clone()),
+        redacted_at: ActiveValue::set(redaction.redacted_at),
+    };
+    let id = RedactedUsers::insert(to_write)
+        .exec(db)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to write redaction {:?} to the database. Error: {:?}", redaction, error)
+        })
+        .last_insert_id;
+    redacted_users::Model {
+        id,
+        ..redaction.clone()
+    }
+}
+
+// Records the redaction, new when its id is 0, and removes the tweets, the user it names
+// and `username` from the watchlist, in one transaction along with the audit entry `audit`
+// makes of what was removed, if any.
+pub async fn redaction(
+    db: &State<DatabaseConnection>,
+    redaction: &redacted_users::Model,
+    username: Option<&str>,
+    tweet_ids: &[i64],
+    audit: impl FnOnce(&RedactionReport) -> Option<audit_log::Model>,
+) -> RedactionReport {
+    let txn = db
+        .begin()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to start a transaction. Error: {:?}", error));
+    let redaction = if redaction.id == 0 {
+        write_redacted_user(&txn, redaction).await
+    } else {
+        // A redaction made by handle learns the user's id once the user is known.
+        RedactedUsers::update_many()
+            .col_expr(redacted_users::Column::UserId, Expr::value(redaction.user_id))
+            .filter(redacted_users::Column::Id.eq(redaction.id))
+            .exec(&txn)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to record the user id of redaction {}. Error: {:?}", redaction.id, error)
+            });
+        redaction.clone()
+    };
+    delete_tweets(&txn, tweet_ids).await;
+    delete_tweet_statuses(&txn, tweet_ids).await;
+    let user_removed = match redaction.user_id {
+        Some(user_id) => {
+            Users::delete_by_id(user_id)
+                .exec(&txn)
+                .await
+                .unwrap_or_else(|error| {
+                    panic!("Failed to remove user {user_id}. Error: {:?}", error)
+                })
+                .rows_affected
+                == 1
+        }
+        None => false,
+    };
+    let unwatched = match username {
+        Some(username) => delete_watched_account(&txn, username).await,
+        None => false,
+    };
+    let report = RedactionReport {
+        redaction,
+        user_removed,
+        tweets_removed: tweet_ids.len(),
+        unwatched,
+    };
+    if let Some(entry) = audit(&report) {
+        write_audit_entry(&txn, &entry).await;
+    }
+    txn.commit().await.unwrap_or_else(|error| {
+        panic!("Failed to commit redaction {:?}. Error: {:?}", report.redaction, error)
+    });
+    report
+}
+
+pub async fn audit_entry(db: &State<DatabaseConnection>, entry: &audit_log::Model) {
+    write_audit_entry(db.inner(), entry).await;
+}
+
+async fn write_audit_entry(db: &impl ConnectionTrait, entry: &audit_log::Model) {
+    let to_write = audit_log::ActiveModel {
+        id: ActiveValue::NotSet,
+        action: ActiveValue::set(entry.action.clone()),
+        target: ActiveValue::set(entry.target.clone()),
+        reason: ActiveValue::set(entry.reason.clone()),
+        actor: ActiveValue::set(entry.actor.clone()),
+        detail: ActiveValue::set(entry.detail.clone()),
+        at: ActiveValue::set(entry.at),
+    };
+    AuditLog::insert(to_write)
+        .exec(db)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to write {:?} to the audit log. Error: {:?}", entry, error)
+        });
+}
+
+async fn delete_tweet_statuses(db: &impl ConnectionTrait, tweet_ids: &[i64]) {
+    for chunk in tweet_ids.chunks(MAX_QUERY_PARAMETERS) {
+        TweetStatus::delete_many()
+            .filter(tweet_status::Column::TweetId.is_in(chunk.to_vec()))
+            .exec(db)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to remove statuses of tweets {:?}. Error: {:?}", chunk, error)
+            });
+    }
+}
+
+// Adds a new collection, returning it with the id it was given.
+pub async fn collection(
+    db: &State<DatabaseConnection>,
+    collection: &collections::Model,
+) -> collections::Model {
+    let to_write = collections::ActiveModel {
+        id: ActiveValue::NotSet,
+        name: ActiveValue::set(collection.name.clone()),
+        description: ActiveValue::set(collection.description.clone()),
+        created_at: ActiveValue::set(collection.created_at),
+        updated_at: ActiveValue::set(collection.updated_at),
+    };
+    let id = Collections::insert(to_write)
+        .exec(db.inner())
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to write collection {} to the database. Error: {:?}",
+                collection.name, error
+            )
+        })
+        .last_insert_id;
+    collections::Model {
+        id,
+        ..collection.clone()
+    }
+}
+
+pub async fn update_collection(db: &State<DatabaseConnection>, collection: &collections::Model) {
+    let to_write = collections::ActiveModel {
+        id: ActiveValue::set(collection.id),
+        name: ActiveValue::set(collection.name.clone()),
+        description: ActiveValue::set(collection.description.clone()),
+        created_at: ActiveValue::set(collection.created_at),
+        updated_at: ActiveValue::set(collection.updated_at),
+    };
+    Collections::update(to_write)
+        .exec(db.inner())
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to update collection {}. Error: {:?}", collection.id, error)
+        });
+}
+
+pub async fn collection_updated(
+    db: &State<DatabaseConnection>,
+    id: i32,
+    updated_at: DateTime<FixedOffset>,
+) {
+    Collections::update_many()
+        .col_expr(collections::Column::UpdatedAt, Expr::value(updated_at))
+        .filter(collections::Column::Id.eq(id))
+        .exec(db.inner())
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to record when collection {id} was updated. Error: {:?}", error)
+        });
+}
+
+// Removes the collection and its list of tweets, but not the tweets. Returns whether there
+// was such a collection.
+pub async fn remove_collection(db: &State<DatabaseConnection>, id: i32) -> bool {
+    let txn = db
+        .begin()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to start a transaction. Error: {:?}", error));
+    CollectionTweets::delete_many()
+        .filter(collection_tweets::Column::CollectionId.eq(id))
+        .exec(&txn)
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to remove the tweets of collection {id}. Error: {:?}", error)
+        });
+    let removed = Collections::delete_by_id(id)
+        .exec(&txn)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to remove collection {id}. Error: {:?}", error))
+        .rows_affected
+        == 1;
+    txn.commit()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to commit removing collection {id}. Error: {:?}", error));
+    removed
+}
+
+// Adds the tweet to the collection, or replaces its note if it's already there.
+pub async fn collection_tweet(db: &State<DatabaseConnection>, entry: &collection_tweets::Model) {
+    CollectionTweets::delete_by_id((entry.collection_id, entry.tweet_id))
+        .exec(db.inner())
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to clear tweet {} from collection {}. Error: {:?}",
+                entry.tweet_id, entry.collection_id, error
+            )
+        });
+    let to_write = collection_tweets::ActiveModel {
+        collection_id: ActiveValue::set(entry.collection_id),
+        tweet_id: ActiveValue::set(entry.tweet_id),
+        note: ActiveValue::set(entry.note.clone()),
+        added_at: ActiveValue::set(entry.added_at),
+    };
+    CollectionTweets::insert(to_write)
+        .exec(db.inner())
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to add tweet {} to collection {}. Error: {:?}",
+                entry.tweet_id, entry.collection_id, error
+            )
+        });
+}
+
+// Returns whether the tweet was in the collection.
+pub async fn remove_collection_tweet(
+    db: &State<DatabaseConnection>,
+    collection_id: i32,
+    tweet_id: i64,
+) -> bool {
+    CollectionTweets::delete_by_id((collection_id, tweet_id))
+        .exec(db.inner())
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to remove tweet {tweet_id} from collection {collection_id}. Error: {:?}",
+                error
+            )
+        })
+        .rows_affected
+        == 1
+}
+
+// Returns whether the tweet didn't have the tag already.
+pub async fn tweet_tag(db: &State<DatabaseConnection>, tag: &tweet_tags::Model) -> bool {
+    let to_write = tweet_tags::ActiveModel {
+        tweet_id: ActiveValue::set(tag.tweet_id),
+        tag: ActiveValue::set(tag.tag.clone()),
+        tagged_at: ActiveValue::set(tag.tagged_at),
+    };
+    let exists = TweetTags::find_by_id((tag.tweet_id, tag.tag.clone()))
+        .one(db.inner())
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to read the tags of tweet {}. Error: {:?}", tag.tweet_id, error)
+        })
+        .is_some();
+    if !exists {
+        TweetTags::insert(to_write)
+            .exec(db.inner())
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to tag tweet {} with {}. Error: {:?}", tag.tweet_id, tag.tag, error)
+            });
+    }
+    !exists
+}
+
+// Returns whether the tweet had the tag.
+pub async fn remove_tweet_tag(db: &State<DatabaseConnection>, tweet_id: i64, tag: &str) -> bool {
+    TweetTags::delete_by_id((tweet_id, tag.to_owned()))
+        .exec(db.inner())
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to remove tag {tag} from tweet {tweet_id}. Error: {:?}", error)
+        })
+        .rows_affected
+        == 1
+}
+
+pub async fn annotation(
+    db: &State<DatabaseConnection>,
+    annotation: &annotations::Model,
+) -> annotations::Model {
+    let to_write = annotations::ActiveModel {
+        id: ActiveValue::NotSet,
+        author: ActiveValue::set(annotation.author.clone()),
+        tweet_id: ActiveValue::set(annotation.tweet_id),
+        conversation_id: ActiveValue::set(annotation.conversation_id),
+        body: ActiveValue::set(annotation.body.clone()),
+        created_at: ActiveValue::set(annotation.created_at),
+        updated_at: ActiveValue::set(annotation.updated_at),
+    };
+    let id = Annotations::insert(to_write)
+        .exec(db.inner())
+        .await
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to write an annotation by {} to the database. Error: {:?}",
+                annotation.author, error
+            )
+        })
+        .last_insert_id;
+    annotations::Model {
+        id,
+        ..annotation.clone()
+    }
+}
+
+pub async fn annotation_body(
+    db: &State<DatabaseConnection>,
+    id: i32,
+    body: &str,
+    updated_at: DateTime<FixedOffset>,
+) {
+    Annotations::update_many()
+        .col_expr(annotations::Column::Body, Expr::value(body))
+        .col_expr(annotations::Column::UpdatedAt, Expr::value(updated_at))
+        .filter(annotations::Column::Id.eq(id))
+        .exec(db.inner())
+        .await
+        .unwrap_or_else(|error| panic!("Failed to update annotation {id}. Error: {:?}", error));
+}
+
+// Returns whether there was such an annotation.
+pub async fn remove_annotation(db: &State<DatabaseConnection>, id: i32) -> bool {
+    Annotations::delete_by_id(id)
+        .exec(db.inner())
+        .await
+        .unwrap_or_else(|error| panic!("Failed to remove annotation {id}. Error: {:?}", error))
+        .rows_affected
+        == 1
+}
+
+// Deletes the tweets along with the references made from and to them, their versions,
+// their embeddings, their tags, their annotations and their places in collections, in one
+// transaction. Tweets that replied to or quoted them keep their other references.
+pub async fn remove_tweets(db: &State<DatabaseConnection>, ids: &[i64]) {
+    let txn = db
+        .begin()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to start a transaction. Error: {:?}", error));
+    delete_tweets(&txn, ids).await;
+    txn.commit()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to commit removing tweets. Error: {:?}", error));
+}
+
+// Removes the tweet as `remove_tweets` does, along with its status, hides it and records
+// who did it, all in one transaction so that it's never left gone but not hidden.
+pub async fn deleted_tweet(
+    db: &State<DatabaseConnection>,
+    hidden: &hidden_tweets::Model,
+    entry: &audit_log::Model,
+) {
+    let txn = db
+        .begin()
+        .await
+        .unwrap_or_else(|error| panic!("Failed to start a transaction. Error: {:?}", error));
+    let ids = [hidden.tweet_id];
+    delete_tweets(&txn, &ids).await;
+    delete_tweet_statuses(&txn, &ids).await;
+    write_hidden_tweet(&txn, hidden).await;
+    write_audit_entry(&txn, entry).await;
+    txn.commit().await.unwrap_or_else(|error| {
+        panic!("Failed to commit deleting tweet {}. Error: {:?}", hidden.tweet_id, error)
+    });
+}
+
+async fn delete_tweets(txn: &DatabaseTransaction, ids: &[i64]) {
+    for chunk in ids.chunks(MAX_QUERY_PARAMETERS) {
+        TweetReferences::delete_many()
+            .filter(tweet_references::Column::SourceTweetId.is_in(chunk.to_vec()))
+            .exec(txn)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to remove references from tweets {:?}. Error: {:?}", chunk, error)
+            });
+        TweetReferences::delete_many()
+            .filter(tweet_references::Column::ReferencedTweetId.is_in(chunk.to_vec()))
+            .exec(txn)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to remove references to tweets {:?}. Error: {:?}", chunk, error)
+            });
+        Embeddings::delete_many()
+            .filter(embeddings::Column::TweetId.is_in(chunk.to_vec()))
+            .exec(txn)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to remove embeddings of tweets {:?}. Error: {:?}", chunk, error)
+            });
+        TweetVersions::delete_many()
+            .filter(tweet_versions::Column::TweetId.is_in(chunk.to_vec()))
+            .exec(txn)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to remove versions of tweets {:?}. Error: {:?}", chunk, error)
+            });
+        TweetTags::delete_many()
+            .filter(tweet_tags::Column::TweetId.is_in(chunk.to_vec()))
+            .exec(txn)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to remove tags of tweets {:?}. Error: {:?}", chunk, error)
+            });
+        CollectionTweets::delete_many()
+            .filter(collection_tweets::Column::TweetId.is_in(chunk.to_vec()))
+            .exec(txn)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to remove tweets {:?} from collections. Error: {:?}", chunk, error)
+            });
+        Annotations::delete_many()
+            .filter(annotations::Column::TweetId.is_in(chunk.to_vec()))
+            .exec(txn)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to remove annotations of tweets {:?}. Error: {:?}", chunk, error)
+            });
+        Tweets::delete_many()
+            .filter(tweets::Column::Id.is_in(chunk.to_vec()))
+            .exec(txn)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to remove tweets {:?}. Error: {:?}", chunk, error)
+            });
+    }
+}
+
+pub async fn remove_tweet_references(db: &State<DatabaseConnection>, source_tweet_ids: &[i64]) {
+    for chunk in source_tweet_ids.chunks(MAX_QUERY_PARAMETERS) {
+        TweetReferences::delete_many()
+            .filter(tweet_references::Column::SourceTweetId.is_in(chunk.to_vec()))
+            .exec(db.inner())
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to remove references from tweets {:?}. Error: {:?}", chunk, error)
+            });
+    }
+}
+
+pub async fn remove_conversations(db: &State<DatabaseConnection>, ids: &[i64]) {
+    for chunk in ids.chunks(MAX_QUERY_PARAMETERS) {
+        Conversations::delete_many()
+            .filter(conversations::Column::Id.is_in(chunk.to_vec()))
+            .exec(db.inner())
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to remove conversations {:?}. Error: {:?}", chunk, error)
+            });
+    }
+}
+
+pub async fn tweet_created_at(
+    db: &State<DatabaseConnection>,
+    id: i64,
+    created_at: DateTime<FixedOffset>,
+) {
+    Tweets::update_many()
+        .col_expr(tweets::Column::CreatedAt, Expr::value(created_at))
+        .filter(tweets::Column::Id.eq(id))
+        .exec(db.inner())
+        .await
+        .unwrap_or_else(|error| {
+            panic!("Failed to update the date of tweet {id}. Error: {:?}", error)
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::data::setup;
+    use sea_orm::PaginatorTrait;
+
+    fn tweet(id: i64, replied_to: Option<i64>) -> TweetData {
+        let references = replied_to
+            .map(|replied_to| tweet_references::Model {
+                source_tweet_id: id,
+                reference_type: "replied_to".to_string(),
+                referenced_tweet_id: replied_to,
+            })
+            .into_iter()
+            .collect();
+        TweetData::new(
+            tweets::Model {
+                id,
+                content: format!("Tweet {id}"),
+                author_id: 1,
+                conversation_id: 1,
+                created_at: DateTime::parse_from_rfc3339("2020-01-01T00:00:00+00:00").unwrap(),
+            },
+            references,
+        )
+    }
+
+    async fn db_with_author(name: &str) -> DatabaseConnection {
+        let db = setup::test_db(name).await;
+        Users::insert(users::ActiveModel {
+            id: ActiveValue::set(1),
+            name: ActiveValue::set("Author".to_string()),
+            username: ActiveValue::set("author".to_string()),
+            description: ActiveValue::set(String::new()),
+        })
+        .exec(&db)
+        .await
+        .unwrap();
+        db
+    }
+
+    #[tokio::test]
+    async fn overlapping_writes_of_the_same_tweets_store_them_once() {
+        let db = db_with_author("overlapping-writes").await;
+        let batch = vec![tweet(1, None), tweet(2, Some(1))];
+        let state = State::from(&db);
+        futures::join!(tweets(state, &batch), tweets(state, &batch));
+        tweets(state, &batch).await;
+
+        assert_eq!(Tweets::find().count(&db).await.unwrap(), 2);
+        assert_eq!(Conversations::find().count(&db).await.unwrap(), 1);
+        assert_eq!(TweetReferences::find().count(&db).await.unwrap(), 1);
+        assert_eq!(TweetVersions::find().count(&db).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn replies_to_tweets_that_arent_archived_keep_their_reference() {
+        let db = db_with_author("unarchived-replies").await;
+        tweets(State::from(&db), &[tweet(2, Some(1))]).await;
+
+        let references = TweetReferences::find().all(&db).await.unwrap();
+        assert_eq!(references.len(), 1);
+        assert_eq!(references[0].referenced_tweet_id, 1);
+    }
+
+    #[tokio::test]
+    async fn deleting_a_tweet_removes_its_rows_hides_it_and_records_it_together() {
+        let db = db_with_author("deleted-tweet").await;
+        let state = State::from(&db);
+        tweets(state, &[tweet(1, None), tweet(2, Some(1))]).await;
+        let at = DateTime::parse_from_rfc3339("2020-01-02T00:00:00+00:00").unwrap();
+        tweet_status(
+            state,
+            &tweet_status::Model {
+                tweet_id: 1,
+                status: "deleted".to_string(),
+                detail: None,
+                checked_at: at,
+            },
+        )
+        .await;
+        deleted_tweet(
+            state,
+            &hidden_tweets::Model {
+                tweet_id: 1,
+                reason: "Asked to".to_string(),
+                hidden_at: at,
+            },
+            &audit_log::Model {
+                id: 0,
+                action: "delete_tweet".to_string(),
+                target: "tweet 1".to_string(),
+                reason: Some("Asked to".to_string()),
+                actor: "admin".to_string(),
+                detail: None,
+                at,
+            },
+        )
+        .await;
+
+        let remaining = Tweets::find().all(&db).await.unwrap();
+        assert_eq!(remaining.iter().map(|tweet| tweet.id).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(TweetReferences::find().count(&db).await.unwrap(), 0);
+        assert_eq!(TweetStatus::find().count(&db).await.unwrap(), 0);
+        assert_eq!(HiddenTweets::find().count(&db).await.unwrap(), 1);
+        assert_eq!(AuditLog::find().count(&db).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,263 @@
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+// The instants a query covers, `from` inclusive and `to` exclusive, along with the offset
+// its dates were read in so results can be grouped by day in the same one.
+#[derive(Debug, Clone)]
+pub struct DateWindow {
+    pub from: Option<DateTime<FixedOffset>>,
+    pub to: Option<DateTime<FixedOffset>>,
+    pub offset: FixedOffset,
+}
+
+impl DateWindow {
+    // Either bound can be an RFC3339 date and time, a date and time without an offset, a
+    // plain YYYY-MM-DD date, "now", "today", "yesterday", or a time ago like 30m, 12h, 7d
+    // or 2w. Anything without an offset of its own is read in `tz`, which defaults to
+    // UTC. A plain date as `to` takes in the whole of that day.
+    pub fn parse(from: Option<&str>, to: Option<&str>, tz: Option<&str>) -> Result<Self, String> {
+        let offset = parse_tz(tz)?;
+        Ok(Self {
+            from: from
+                .map(|from| parse_bound(from, offset, false))
+                .transpose()?,
+            to: to.map(|to| parse_bound(to, offset, true)).transpose()?,
+            offset,
+        })
+    }
+
+    pub fn is_unbounded(&self) -> bool {
+        self.from.is_none() && self.to.is_none()
+    }
+}
+
+// A fixed offset from UTC: "UTC", "Z", or a signed offset like +05:30, -0800 or +2. Named
+// zones like Europe/Paris aren't supported, as their offsets change through the year.
+pub fn parse_tz(tz: Option<&str>) -> Result<FixedOffset, String> {
+    let tz = match tz.map(str::trim) {
+        None | Some("") => return Ok(FixedOffset::east(0)),
+        Some(tz) => tz,
+    };
+    if tz.eq_ignore_ascii_case("utc") || tz.eq_ignore_ascii_case("z") {
+        return Ok(FixedOffset::east(0));
+    }
+    let error = || format!("Expected a UTC offset like +05:30 or -0800 for tz, got {tz}");
+    let (sign, offset) = match (tz.strip_prefix('+'), tz.strip_prefix('-')) {
+        (Some(offset), _) => (1, offset),
+        (_, Some(offset)) => (-1, offset),
+        _ => return Err(error()),
+    };
+    if !offset.chars().all(|c| c.is_ascii_digit() || c == ':') {
+        return Err(error());
+    }
+    let (hours, minutes) = match offset.split_once(':') {
+        Some((hours, minutes)) => (hours, minutes),
+        None if offset.len() == 4 => offset.split_at(2),
+        None => (offset, "0"),
+    };
+    let hours: i32 = hours.parse().map_err(|_| error())?;
+    let minutes: i32 = minutes.parse().map_err(|_| error())?;
+    if hours > 14 || minutes >= 60 {
+        return Err(error());
+    }
+    Ok(FixedOffset::east(sign * (hours * 3600 + minutes * 60)))
+}
+
+// A YYYY-MM-DD date.
+pub fn parse_day(day: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(day, "%Y-%m-%d")
+        .map_err(|_| format!("Expected a date like 2020-01-31, got {day}"))
+}
+
+// The start of `day` and of the day after it, in `offset`.
+pub fn day_bounds(
+    day: NaiveDate,
+    offset: FixedOffset,
+) -> (DateTime<FixedOffset>, DateTime<FixedOffset>) {
+    let start_of = |day: NaiveDate| {
+        offset
+            .from_local_datetime(&day.and_hms(0, 0, 0))
+            .single()
+            .unwrap_or_else(|| panic!("Failed to find the start of {day} in {offset}"))
+    };
+    (start_of(day), start_of(day + Duration::days(1)))
+}
+
+fn parse_bound(
+    bound: &str,
+    offset: FixedOffset,
+    is_end: bool,
+) -> Result<DateTime<FixedOffset>, String> {
+    let bound = bound.trim();
+    let now = Utc::now().with_timezone(&offset);
+    match bound {
+        "now" => return Ok(now),
+        "today" => return Ok(whole_day(now.naive_local().date(), offset, is_end)),
+        "yesterday" => {
+            return Ok(whole_day(
+                now.naive_local().date() - Duration::days(1),
+                offset,
+                is_end,
+            ))
+        }
+        _ => (),
+    }
+    if let Ok(date) = DateTime::parse_from_rfc3339(bound) {
+        return Ok(date);
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"] {
+        if let Ok(date) = NaiveDateTime::parse_from_str(bound, format) {
+            return offset
+                .from_local_datetime(&date)
+                .single()
+                .ok_or_else(|| format!("{bound} doesn't exist in {offset}"));
+        }
+    }
+    if let Ok(day) = NaiveDate::parse_from_str(bound, "%Y-%m-%d") {
+        return Ok(whole_day(day, offset, is_end));
+    }
+    // Times too far back to be a date at all are rejected like any other unreadable bound.
+    if let Some(date) = parse_ago(bound).and_then(|ago| now.checked_sub_signed(ago)) {
+        return Ok(date);
+    }
+    Err(format!(
+        "Expected a date like 2020-01-31, 2020-01-31T09:00:00Z, now, today, yesterday \
+         or a time ago like 7d, got {bound}"
+    ))
+}
+
+fn whole_day(day: NaiveDate, offset: FixedOffset, is_end: bool) -> DateTime<FixedOffset> {
+    let (start, end) = day_bounds(day, offset);
+    if is_end {
+        end
+    } else {
+        start
+    }
+}
+
+// 30m, 12h, 7d or 2w, or nothing if it's longer than a `Duration` can hold.
+fn parse_ago(ago: &str) -> Option<Duration> {
+    let unit = ago.chars().last()?;
+    let count: i64 = ago[..ago.len() - unit.len_utf8()].parse().ok()?;
+    let seconds_per_unit = match unit {
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let seconds = count.checked_mul(seconds_per_unit)?;
+    // `Duration::seconds` panics past what fits in milliseconds.
+    seconds.checked_mul(1000)?;
+    Some(Duration::seconds(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hours(hours: i32) -> FixedOffset {
+        FixedOffset::east(hours * 3600)
+    }
+
+    #[test]
+    fn tz_accepts_utc_and_signed_offsets() {
+        assert_eq!(parse_tz(None), Ok(hours(0)));
+        assert_eq!(parse_tz(Some(" ")), Ok(hours(0)));
+        assert_eq!(parse_tz(Some("utc")), Ok(hours(0)));
+        assert_eq!(parse_tz(Some("Z")), Ok(hours(0)));
+        assert_eq!(
+            parse_tz(Some("+05:30")),
+            Ok(FixedOffset::east(5 * 3600 + 30 * 60))
+        );
+        assert_eq!(parse_tz(Some("-0800")), Ok(hours(-8)));
+        assert_eq!(parse_tz(Some("+2")), Ok(hours(2)));
+    }
+
+    #[test]
+    fn tz_rejects_named_zones_and_impossible_offsets() {
+        for tz in ["Europe/Paris", "+", "+15", "+05:60", "+-5", "é", "05:00"] {
+            assert!(parse_tz(Some(tz)).is_err(), "{tz} should be rejected");
+        }
+    }
+
+    #[test]
+    fn day_is_year_month_day() {
+        assert_eq!(
+            parse_day("2020-01-31"),
+            Ok(NaiveDate::from_ymd(2020, 1, 31))
+        );
+        assert!(parse_day("31/01/2020").is_err());
+        assert!(parse_day("2020-02-30").is_err());
+    }
+
+    #[test]
+    fn day_bounds_run_midnight_to_midnight_in_the_offset() {
+        let (start, end) = day_bounds(NaiveDate::from_ymd(2020, 1, 31), hours(2));
+        assert_eq!(start.to_rfc3339(), "2020-01-31T00:00:00+02:00");
+        assert_eq!(end.to_rfc3339(), "2020-02-01T00:00:00+02:00");
+    }
+
+    #[test]
+    fn a_plain_date_as_to_takes_in_the_whole_day() {
+        let window =
+            DateWindow::parse(Some("2020-01-01"), Some("2020-01-31"), Some("-05:00")).unwrap();
+        assert_eq!(
+            window.from.unwrap().to_rfc3339(),
+            "2020-01-01T00:00:00-05:00"
+        );
+        assert_eq!(window.to.unwrap().to_rfc3339(), "2020-02-01T00:00:00-05:00");
+        assert_eq!(window.offset, hours(-5));
+    }
+
+    #[test]
+    fn bounds_with_their_own_offset_keep_it() {
+        let window = DateWindow::parse(Some("2020-01-31T09:00:00Z"), None, Some("+02:00")).unwrap();
+        assert_eq!(
+            window.from.unwrap().to_rfc3339(),
+            "2020-01-31T09:00:00+00:00"
+        );
+        assert!(window.to.is_none());
+    }
+
+    #[test]
+    fn times_without_an_offset_are_read_in_tz() {
+        let window = DateWindow::parse(Some("2020-01-31 09:30"), None, Some("+01:00")).unwrap();
+        assert_eq!(
+            window.from.unwrap().to_rfc3339(),
+            "2020-01-31T09:30:00+01:00"
+        );
+    }
+
+    #[test]
+    fn times_ago_count_back_from_now() {
+        // Each bound reads the clock, so they can be a moment more than 7 days apart.
+        let window = DateWindow::parse(Some("7d"), Some("now"), None).unwrap();
+        let between = window.to.unwrap() - window.from.unwrap();
+        assert!(between >= Duration::days(7) && between < Duration::days(7) + Duration::seconds(1));
+        assert_eq!(parse_ago("2w"), Some(Duration::weeks(2)));
+        assert_eq!(parse_ago("30m"), Some(Duration::minutes(30)));
+        assert_eq!(parse_ago("7y"), None);
+        assert_eq!(parse_ago(""), None);
+    }
+
+    #[test]
+    fn times_too_long_ago_are_rejected() {
+        assert_eq!(parse_ago(&format!("{}w", i64::MAX)), None);
+        assert_eq!(parse_ago(&format!("{}m", i64::MAX / 60)), None);
+        for bound in [
+            "9223372036854775807d",
+            "-9223372036854775807w",
+            "100000000000000d",
+            "100000000000d",
+        ] {
+            let error = DateWindow::parse(Some(bound), None, None).unwrap_err();
+            assert!(error.starts_with("Expected a date like"), "{error}");
+        }
+    }
+
+    #[test]
+    fn unreadable_bounds_are_rejected() {
+        assert!(DateWindow::parse(Some("last tuesday"), None, None).is_err());
+        assert!(DateWindow::parse(None, None, None).unwrap().is_unbounded());
+    }
+}
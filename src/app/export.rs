@@ -0,0 +1,6 @@
+pub mod collection;
+pub mod dump;
+pub mod feed;
+pub mod markdown;
+pub mod network;
+pub mod site;
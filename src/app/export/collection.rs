@@ -0,0 +1,149 @@
+use serde::Serialize;
+
+use super::dump::DumpFormat;
+use crate::utils::{CollectedTweet, CollectionData};
+
+// A row of a collection's csv export. Tags are joined with semicolons.
+#[derive(Debug, Serialize)]
+struct CsvRow<'a> {
+    tweet_id: i64,
+    author_id: i64,
+    created_at: String,
+    content: &'a str,
+    note: Option<&'a str>,
+    tags: String,
+    added_at: String,
+}
+
+pub fn to_format(collection: &CollectionData, format: DumpFormat) -> String {
+    match format {
+        DumpFormat::Ndjson => ndjson(collection),
+        DumpFormat::Csv => csv(collection),
+    }
+}
+
+// One collected tweet per line, in the order they were added.
+pub fn ndjson(collection: &CollectionData) -> String {
+    collection
+        .tweets
+        .iter()
+        .map(|collected| {
+            let line = serde_json::to_string(collected).unwrap_or_else(|error| {
+                panic!("Failed to write {:?}. Error: {:?}", collected, error)
+            });
+            line + "\n"
+        })
+        .collect()
+}
+
+pub fn csv(collection: &CollectionData) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in collection.tweets.iter().filter_map(csv_row) {
+        writer.serialize(&row).unwrap_or_else(|error| {
+            panic!("Failed to write {:?} as csv. Error: {:?}", row, error)
+        });
+    }
+    let bytes = writer
+        .into_inner()
+        .unwrap_or_else(|error| panic!("Failed to flush the csv. Error: {:?}", error));
+    String::from_utf8(bytes)
+        .unwrap_or_else(|error| panic!("Failed to read back the csv. Error: {:?}", error))
+}
+
+fn csv_row(collected: &CollectedTweet) -> Option<CsvRow<'_>> {
+    let tweet = collected.tweet.tweet.as_ref()?;
+    Some(CsvRow {
+        tweet_id: tweet.id,
+        author_id: tweet.author_id,
+        created_at: tweet.created_at.to_rfc3339(),
+        content: &tweet.content,
+        note: collected.note.as_deref(),
+        tags: collected.tags.join(";"),
+        added_at: collected.added_at.to_rfc3339(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+    use crate::app::data::entities::{collections, tweets};
+    use crate::utils::TweetData;
+
+    fn collected(id: i64, content: &str, note: Option<&str>, tags: &[&str]) -> CollectedTweet {
+        let date = DateTime::parse_from_rfc3339("2022-01-05T10:00:00Z").unwrap();
+        let mut tweet = TweetData::empty();
+        tweet.tweet = Some(tweets::Model {
+            id,
+            content: content.to_string(),
+            author_id: 7,
+            conversation_id: id,
+            created_at: date,
+        });
+        CollectedTweet {
+            tweet,
+            note: note.map(str::to_string),
+            added_at: date,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        }
+    }
+
+    fn collection(tweets: Vec<CollectedTweet>) -> CollectionData {
+        let date = DateTime::parse_from_rfc3339("2022-01-01T00:00:00Z").unwrap();
+        CollectionData {
+            collection: collections::Model {
+                id: 1,
+                name: "reading".to_string(),
+                description: None,
+                created_at: date,
+                updated_at: date,
+            },
+            tweets,
+        }
+    }
+
+    #[test]
+    fn csv_quotes_content_and_joins_tags() {
+        let csv = csv(&collection(vec![
+            collected(
+                1,
+                "commas, \"quotes\"\nand lines",
+                Some("keep"),
+                &["a", "b"],
+            ),
+            collected(2, "plain", None, &[]),
+        ]));
+        assert_eq!(
+            csv,
+            "tweet_id,author_id,created_at,content,note,tags,added_at\n\
+             1,7,2022-01-05T10:00:00+00:00,\"commas, \"\"quotes\"\"\nand lines\",keep,a;b,\
+             2022-01-05T10:00:00+00:00\n\
+             2,7,2022-01-05T10:00:00+00:00,plain,,,2022-01-05T10:00:00+00:00\n"
+        );
+    }
+
+    #[test]
+    fn csv_leaves_out_tweets_that_are_gone() {
+        let mut gone = collected(1, "gone", None, &[]);
+        gone.tweet = TweetData::empty();
+        let csv = csv(&collection(vec![gone, collected(2, "here", None, &[])]));
+        assert_eq!(csv.lines().count(), 2);
+        assert!(csv.contains("here"));
+    }
+
+    #[test]
+    fn ndjson_writes_one_tweet_per_line() {
+        let ndjson = ndjson(&collection(vec![
+            collected(1, "line\nbreak", None, &["a"]),
+            collected(2, "two", Some("note"), &[]),
+        ]));
+        let lines: Vec<serde_json::Value> = ndjson
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["tweet"]["tweet"]["content"], "line\nbreak");
+        assert_eq!(lines[1]["note"], "note");
+    }
+}
@@ -0,0 +1,769 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use futures::{Stream, StreamExt};
+use rocket::State;
+use sea_orm::sea_query::Query;
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, JoinType, QueryFilter,
+    QueryOrder, QuerySelect, RelationTrait,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::app::data;
+use crate::app::data::entities::prelude::*;
+use crate::app::data::entities::*;
+use crate::app::data::write::DumpedRows;
+use crate::utils::{TweetData, TweetStatusKind, UserData};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    Ndjson,
+    Csv,
+}
+
+impl FromStr for DumpFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "ndjson" => Ok(Self::Ndjson),
+            "csv" => Ok(Self::Csv),
+            _ => Err(format!("Unknown dump format {format}, expected ndjson or csv")),
+        }
+    }
+}
+
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+// One line of an ndjson dump. Tweets carry their references and tombstones so they round
+// trip through `TweetData`; references whose source tweet isn't archived, and statuses no
+// tweet carries, get a line of their own.
+// Hidden tweets are dumped like any other, along with what keeps them hidden.
+// Collections keep their ids, so the tweets in them and their notes restore into them.
+// Annotations keep theirs too, so links to them still work after a restore.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    User(UserData),
+    Conversation(conversations::Model),
+    Tweet(TweetData),
+    TweetReference(tweet_references::Model),
+    TweetVersion(tweet_versions::Model),
+    TweetStatus(tweet_status::Model),
+    HiddenTweet(hidden_tweets::Model),
+    RedactedUser(redacted_users::Model),
+    Collection(collections::Model),
+    CollectionTweet(collection_tweets::Model),
+    TweetTag(tweet_tags::Model),
+    Annotation(annotations::Model),
+}
+
+// Streams every row in the database as ndjson. Users and conversations come before tweets
+// so that a restore never writes a tweet before its author and conversation.
+pub async fn export_ndjson(db: &State<DatabaseConnection>, out: impl Write) -> usize {
+    let mut out = BufWriter::new(out);
+    let mut written = 0;
+
+    for redaction in data::read::redacted_users(db).await {
+        write_line(&mut out, &Record::RedactedUser(redaction));
+        written += 1;
+    }
+    for hidden in data::read::hidden_tweets(db).await {
+        write_line(&mut out, &Record::HiddenTweet(hidden));
+        written += 1;
+    }
+    let db = db as &DatabaseConnection;
+
+    let mut users = Box::pin(data::read::users_stream(db));
+    while let Some(user_data) = users.next().await {
+        write_line(&mut out, &Record::User(user_data));
+        written += 1;
+    }
+    let mut conversations = Box::pin(stream_or_panic(
+        Conversations::find().stream(db).await,
+        "conversations",
+    ));
+    while let Some(conversation) = conversations.next().await {
+        write_line(&mut out, &Record::Conversation(conversation));
+        written += 1;
+    }
+    let mut tweets = Box::pin(data::read::archived_tweets_stream(db));
+    while let Some(tweet_data) = tweets.next().await {
+        write_line(&mut out, &Record::Tweet(tweet_data));
+        written += 1;
+    }
+    let mut orphaned_references = Box::pin(stream_or_panic(
+        TweetReferences::find()
+            .join(JoinType::LeftJoin, tweet_references::Relation::Tweets1.def())
+            .filter(tweets::Column::Id.is_null())
+            .stream(db)
+            .await,
+        "tweet references",
+    ));
+    while let Some(reference) = orphaned_references.next().await {
+        write_line(&mut out, &Record::TweetReference(reference));
+        written += 1;
+    }
+    let mut versions = Box::pin(stream_or_panic(
+        TweetVersions::find()
+            .order_by_asc(tweet_versions::Column::TweetId)
+            .order_by_asc(tweet_versions::Column::Version)
+            .stream(db)
+            .await,
+        "tweet versions",
+    ));
+    while let Some(version) = versions.next().await {
+        write_line(&mut out, &Record::TweetVersion(version));
+        written += 1;
+    }
+    // Archived tweets only carry the statuses that say they are gone.
+    let mut loose_statuses = Box::pin(stream_or_panic(
+        TweetStatus::find()
+            .filter(
+                Condition::any()
+                    .add(tweet_status::Column::Status.eq(TweetStatusKind::Available.as_str()))
+                    .add(
+                        tweet_status::Column::TweetId.not_in_subquery(
+                            Query::select()
+                                .column(tweets::Column::Id)
+                                .from(Tweets)
+                                .to_owned(),
+                        ),
+                    ),
+            )
+            .order_by_asc(tweet_status::Column::TweetId)
+            .stream(db)
+            .await,
+        "tweet statuses",
+    ));
+    while let Some(status) = loose_statuses.next().await {
+        write_line(&mut out, &Record::TweetStatus(status));
+        written += 1;
+    }
+    let mut collections = Box::pin(stream_or_panic(
+        Collections::find()
+            .order_by_asc(collections::Column::Id)
+            .stream(db)
+            .await,
+        "collections",
+    ));
+    while let Some(collection) = collections.next().await {
+        write_line(&mut out, &Record::Collection(collection));
+        written += 1;
+    }
+    let mut entries = Box::pin(stream_or_panic(
+        CollectionTweets::find()
+            .order_by_asc(collection_tweets::Column::CollectionId)
+            .order_by_asc(collection_tweets::Column::TweetId)
+            .stream(db)
+            .await,
+        "collection tweets",
+    ));
+    while let Some(entry) = entries.next().await {
+        write_line(&mut out, &Record::CollectionTweet(entry));
+        written += 1;
+    }
+    let mut tags = Box::pin(stream_or_panic(
+        TweetTags::find()
+            .order_by_asc(tweet_tags::Column::TweetId)
+            .order_by_asc(tweet_tags::Column::Tag)
+            .stream(db)
+            .await,
+        "tweet tags",
+    ));
+    while let Some(tag) = tags.next().await {
+        write_line(&mut out, &Record::TweetTag(tag));
+        written += 1;
+    }
+    let mut annotations = Box::pin(stream_or_panic(
+        Annotations::find()
+            .order_by_asc(annotations::Column::Id)
+            .stream(db)
+            .await,
+        "annotations",
+    ));
+    while let Some(annotation) = annotations.next().await {
+        write_line(&mut out, &Record::Annotation(annotation));
+        written += 1;
+    }
+
+    out.flush()
+        .unwrap_or_else(|error| panic!("Failed to flush the dump. Error: {:?}", error));
+    written
+}
+
+// Writes users.csv, conversations.csv, tweets.csv, tweet_references.csv,
+// tweet_versions.csv, tweet_statuses.csv, hidden_tweets.csv, redacted_users.csv,
+// collections.csv, collection_tweets.csv, tweet_tags.csv and annotations.csv to `out_dir`.
+pub async fn export_csv(db: &State<DatabaseConnection>, out_dir: &str) -> usize {
+    let db = db as &DatabaseConnection;
+    let out_dir = Path::new(out_dir);
+    fs::create_dir_all(out_dir).unwrap_or_else(|error| {
+        panic!("Failed to create {}. Error: {:?}", out_dir.display(), error)
+    });
+    write_csv(out_dir, "users", Users::find().stream(db).await).await
+        + write_csv(
+            out_dir,
+            "conversations",
+            Conversations::find().stream(db).await,
+        )
+        .await
+        + write_csv(
+            out_dir,
+            "tweets",
+            Tweets::find()
+                .order_by_asc(tweets::Column::Id)
+                .stream(db)
+                .await,
+        )
+        .await
+        + write_csv(
+            out_dir,
+            "tweet_references",
+            TweetReferences::find()
+                .order_by_asc(tweet_references::Column::SourceTweetId)
+                .stream(db)
+                .await,
+        )
+        .await
+        + write_csv(
+            out_dir,
+            "tweet_versions",
+            TweetVersions::find()
+                .order_by_asc(tweet_versions::Column::TweetId)
+                .order_by_asc(tweet_versions::Column::Version)
+                .stream(db)
+                .await,
+        )
+        .await
+        + write_csv(
+            out_dir,
+            "tweet_statuses",
+            TweetStatus::find()
+                .order_by_asc(tweet_status::Column::TweetId)
+                .stream(db)
+                .await,
+        )
+        .await
+        + write_csv(
+            out_dir,
+            "hidden_tweets",
+            HiddenTweets::find()
+                .order_by_asc(hidden_tweets::Column::TweetId)
+                .stream(db)
+                .await,
+        )
+        .await
+        + write_csv(
+            out_dir,
+            "redacted_users",
+            RedactedUsers::find()
+                .order_by_asc(redacted_users::Column::Id)
+                .stream(db)
+                .await,
+        )
+        .await
+        + write_csv(
+            out_dir,
+            "collections",
+            Collections::find()
+                .order_by_asc(collections::Column::Id)
+                .stream(db)
+                .await,
+        )
+        .await
+        + write_csv(
+            out_dir,
+            "collection_tweets",
+            CollectionTweets::find()
+                .order_by_asc(collection_tweets::Column::CollectionId)
+                .order_by_asc(collection_tweets::Column::TweetId)
+                .stream(db)
+                .await,
+        )
+        .await
+        + write_csv(
+            out_dir,
+            "tweet_tags",
+            TweetTags::find()
+                .order_by_asc(tweet_tags::Column::TweetId)
+                .order_by_asc(tweet_tags::Column::Tag)
+                .stream(db)
+                .await,
+        )
+        .await
+        + write_csv(
+            out_dir,
+            "annotations",
+            Annotations::find()
+                .order_by_asc(annotations::Column::Id)
+                .stream(db)
+                .await,
+        )
+        .await
+}
+
+// Rows are buffered and written exactly as they were dumped, in batches that each get a
+// transaction of their own.
+pub async fn import_ndjson(db: &State<DatabaseConnection>, path: &str) -> usize {
+    let mut imported = 0;
+    let mut rows = DumpedRows::default();
+    let mut versions = Vec::new();
+    let mut statuses = Vec::new();
+    let mut collections = Vec::new();
+    let mut entries = Vec::new();
+    let mut tags = Vec::new();
+    let mut annotations = Vec::new();
+    for record in read_ndjson(path) {
+        match record {
+            Record::User(user_data) => rows.users.extend(user_data.user),
+            Record::Conversation(conversation) => rows.conversations.push(conversation),
+            Record::Tweet(tweet_data) => {
+                rows.tweets.extend(tweet_data.tweet);
+                rows.references.extend(tweet_data.references);
+                statuses.extend(tweet_data.status);
+            }
+            Record::TweetReference(reference) => rows.references.push(reference),
+            Record::TweetVersion(version) => versions.push(version),
+            Record::TweetStatus(status) => statuses.push(status),
+            Record::Collection(collection) => collections.push(collection),
+            Record::CollectionTweet(entry) => entries.push(entry),
+            Record::TweetTag(tag) => tags.push(tag),
+            Record::Annotation(annotation) => annotations.push(annotation),
+            record => import_record(db, record).await,
+        }
+        imported += 1;
+        write_if_full(db, &mut rows).await;
+    }
+    data::write::dumped_rows(db, rows).await;
+    data::write::tweet_versions(db, &versions).await;
+    data::write::tweet_statuses(db, &statuses).await;
+    data::write::dumped_collections(db, &collections, &entries, &tags).await;
+    data::write::dumped_annotations(db, &annotations).await;
+    imported
+}
+
+pub fn read_ndjson(path: &str) -> impl Iterator<Item = Record> + '_ {
+    let file = File::open(path)
+        .unwrap_or_else(|error| panic!("Failed to open {path}. Error: {:?}", error));
+    BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter_map(move |(i, line)| {
+            let line = line.unwrap_or_else(|error| {
+                panic!("Failed to read line {} of {path}. Error: {:?}", i + 1, error)
+            });
+            if line.trim().is_empty() {
+                return None;
+            }
+            Some(serde_json::from_str(&line).unwrap_or_else(|error| {
+                panic!("Failed to parse line {} of {path}. Error: {:?}", i + 1, error)
+            }))
+        })
+}
+
+pub async fn import_csv(db: &State<DatabaseConnection>, in_dir: &str) -> usize {
+    let in_dir = Path::new(in_dir);
+    let mut imported = 0;
+    // Dumps from before tweets could be hidden or users redacted don't have these.
+    if in_dir.join("redacted_users.csv").exists() {
+        for redaction in read_csv::<redacted_users::Model>(in_dir, "redacted_users") {
+            import_record(db, Record::RedactedUser(redaction)).await;
+            imported += 1;
+        }
+    }
+    if in_dir.join("hidden_tweets.csv").exists() {
+        for hidden in read_csv::<hidden_tweets::Model>(in_dir, "hidden_tweets") {
+            import_record(db, Record::HiddenTweet(hidden)).await;
+            imported += 1;
+        }
+    }
+    let mut rows = DumpedRows::default();
+    for user in read_csv::<users::Model>(in_dir, "users") {
+        rows.users.push(user);
+        imported += 1;
+        write_if_full(db, &mut rows).await;
+    }
+    for conversation in read_csv::<conversations::Model>(in_dir, "conversations") {
+        rows.conversations.push(conversation);
+        imported += 1;
+        write_if_full(db, &mut rows).await;
+    }
+    for tweet in read_csv::<tweets::Model>(in_dir, "tweets") {
+        rows.tweets.push(tweet);
+        imported += 1;
+        write_if_full(db, &mut rows).await;
+    }
+    for reference in read_csv::<tweet_references::Model>(in_dir, "tweet_references") {
+        rows.references.push(reference);
+        imported += 1;
+        write_if_full(db, &mut rows).await;
+    }
+    data::write::dumped_rows(db, rows).await;
+    // Dumps from before tweets were versioned don't have this file.
+    if in_dir.join("tweet_versions.csv").exists() {
+        let versions: Vec<tweet_versions::Model> = read_csv(in_dir, "tweet_versions").collect();
+        data::write::tweet_versions(db, &versions).await;
+        imported += versions.len();
+    }
+    // Nor do dumps from before statuses were exported.
+    if in_dir.join("tweet_statuses.csv").exists() {
+        let statuses: Vec<tweet_status::Model> = read_csv(in_dir, "tweet_statuses").collect();
+        data::write::tweet_statuses(db, &statuses).await;
+        imported += statuses.len();
+    }
+    // Nor do dumps from before tweets could be collected or tagged.
+    let collections: Vec<collections::Model> = read_optional_csv(in_dir, "collections");
+    let entries: Vec<collection_tweets::Model> = read_optional_csv(in_dir, "collection_tweets");
+    let tags: Vec<tweet_tags::Model> = read_optional_csv(in_dir, "tweet_tags");
+    data::write::dumped_collections(db, &collections, &entries, &tags).await;
+    imported += collections.len() + entries.len() + tags.len();
+    // Or annotated.
+    let annotations: Vec<annotations::Model> = read_optional_csv(in_dir, "annotations");
+    data::write::dumped_annotations(db, &annotations).await;
+    imported += annotations.len();
+    imported
+}
+
+async fn write_if_full(db: &State<DatabaseConnection>, rows: &mut DumpedRows) {
+    if rows.len() >= IMPORT_BATCH_SIZE {
+        data::write::dumped_rows(db, std::mem::take(rows)).await;
+    }
+}
+
+// Hidden tweets and redactions are few, so they are written one at a time.
+async fn import_record(db: &State<DatabaseConnection>, record: Record) {
+    match record {
+        Record::HiddenTweet(hidden) => data::write::hidden_tweet(db, &hidden).await,
+        Record::RedactedUser(redaction) => {
+            let username = redaction.username.as_deref();
+            if data::read::redaction(db, redaction.user_id, username).await.is_none() {
+                data::write::redacted_user(db, &redaction).await;
+            }
+        }
+        record => unreachable!("{:?} is written in batches", record),
+    }
+}
+
+fn stream_or_panic<T>(
+    stream: Result<impl Stream<Item = Result<T, DbErr>>, DbErr>,
+    table: &'static str,
+) -> impl Stream<Item = T> {
+    stream
+        .unwrap_or_else(|error| panic!("Failed to read {table} from database. Error: {:?}", error))
+        .map(move |row| {
+            row.unwrap_or_else(|error| {
+                panic!("Failed to read a row of {table} from database. Error: {:?}", error)
+            })
+        })
+}
+
+fn write_line(out: &mut impl Write, record: &Record) {
+    serde_json::to_writer(&mut *out, record)
+        .unwrap_or_else(|error| panic!("Failed to write {:?}. Error: {:?}", record, error));
+    out.write_all(b"\n")
+        .unwrap_or_else(|error| panic!("Failed to write to the dump. Error: {:?}", error));
+}
+
+async fn write_csv<T: Serialize>(
+    out_dir: &Path,
+    table: &'static str,
+    rows: Result<impl Stream<Item = Result<T, DbErr>>, DbErr>,
+) -> usize {
+    let path = out_dir.join(format!("{table}.csv"));
+    let mut writer = csv::Writer::from_path(&path).unwrap_or_else(|error| {
+        panic!("Failed to create {}. Error: {:?}", path.display(), error)
+    });
+    let mut rows = Box::pin(stream_or_panic(rows, table));
+    let mut written = 0;
+    while let Some(row) = rows.next().await {
+        writer.serialize(row).unwrap_or_else(|error| {
+            panic!("Failed to write to {}. Error: {:?}", path.display(), error)
+        });
+        written += 1;
+    }
+    writer
+        .flush()
+        .unwrap_or_else(|error| panic!("Failed to flush {}. Error: {:?}", path.display(), error));
+    written
+}
+
+fn read_csv<T: DeserializeOwned>(in_dir: &Path, table: &str) -> impl Iterator<Item = T> {
+    let path = in_dir.join(format!("{table}.csv"));
+    csv::Reader::from_path(&path)
+        .unwrap_or_else(|error| panic!("Failed to open {}. Error: {:?}", path.display(), error))
+        .into_deserialize()
+        .enumerate()
+        .map(move |(i, row)| {
+            row.unwrap_or_else(|error| {
+                panic!(
+                    "Failed to parse row {} of {}. Error: {:?}",
+                    i + 1,
+                    path.display(),
+                    error
+                )
+            })
+        })
+}
+
+fn read_optional_csv<T: DeserializeOwned>(in_dir: &Path, table: &str) -> Vec<T> {
+    if in_dir.join(format!("{table}.csv")).exists() {
+        read_csv(in_dir, table).collect()
+    } else {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::data::setup;
+    use chrono::DateTime;
+
+    type Snapshot = (
+        Vec<users::Model>,
+        Vec<conversations::Model>,
+        Vec<tweets::Model>,
+        Vec<tweet_references::Model>,
+        Vec<tweet_versions::Model>,
+        Vec<tweet_status::Model>,
+        Vec<hidden_tweets::Model>,
+        Vec<redacted_users::Model>,
+        Vec<collections::Model>,
+        Vec<collection_tweets::Model>,
+        Vec<tweet_tags::Model>,
+        Vec<annotations::Model>,
+    );
+
+    async fn snapshot(db: &DatabaseConnection) -> Snapshot {
+        (
+            Users::find().order_by_asc(users::Column::Id).all(db).await.unwrap(),
+            Conversations::find()
+                .order_by_asc(conversations::Column::Id)
+                .all(db)
+                .await
+                .unwrap(),
+            Tweets::find().order_by_asc(tweets::Column::Id).all(db).await.unwrap(),
+            TweetReferences::find()
+                .order_by_asc(tweet_references::Column::SourceTweetId)
+                .order_by_asc(tweet_references::Column::ReferencedTweetId)
+                .all(db)
+                .await
+                .unwrap(),
+            TweetVersions::find()
+                .order_by_asc(tweet_versions::Column::TweetId)
+                .order_by_asc(tweet_versions::Column::Version)
+                .all(db)
+                .await
+                .unwrap(),
+            TweetStatus::find()
+                .order_by_asc(tweet_status::Column::TweetId)
+                .all(db)
+                .await
+                .unwrap(),
+            HiddenTweets::find().all(db).await.unwrap(),
+            RedactedUsers::find().all(db).await.unwrap(),
+            Collections::find()
+                .order_by_asc(collections::Column::Id)
+                .all(db)
+                .await
+                .unwrap(),
+            CollectionTweets::find()
+                .order_by_asc(collection_tweets::Column::CollectionId)
+                .order_by_asc(collection_tweets::Column::TweetId)
+                .all(db)
+                .await
+                .unwrap(),
+            TweetTags::find()
+                .order_by_asc(tweet_tags::Column::TweetId)
+                .order_by_asc(tweet_tags::Column::Tag)
+                .all(db)
+                .await
+                .unwrap(),
+            Annotations::find()
+                .order_by_asc(annotations::Column::Id)
+                .all(db)
+                .await
+                .unwrap(),
+        )
+    }
+
+    fn reference(source: i64, kind: &str, referenced: i64) -> tweet_references::Model {
+        tweet_references::Model {
+            source_tweet_id: source,
+            reference_type: kind.to_string(),
+            referenced_tweet_id: referenced,
+        }
+    }
+
+    // An archive with what verify calls dangling: a tweet whose author isn't archived, a
+    // reference to a tweet that isn't archived and one from a tweet that isn't either.
+    async fn archive(name: &str) -> DatabaseConnection {
+        let db = setup::test_db(name).await;
+        let state = State::from(&db);
+        let created_at = DateTime::parse_from_rfc3339("2020-01-01T12:00:00+02:00").unwrap();
+        let tweet = |id: i64, author_id: i64| tweets::Model {
+            id,
+            content: format!("Tweet {id}, with a comma and \"quotes\""),
+            author_id,
+            conversation_id: 10,
+            created_at,
+        };
+        data::write::dumped_rows(
+            state,
+            data::write::DumpedRows {
+                users: vec![users::Model {
+                    id: 1,
+                    name: "Author".to_string(),
+                    username: "author".to_string(),
+                    description: "Writes\nthings".to_string(),
+                }],
+                conversations: vec![conversations::Model { id: 10 }],
+                tweets: vec![tweet(10, 1), tweet(11, 2)],
+                references: vec![
+                    reference(11, "replied_to", 10),
+                    reference(11, "quoted", 99),
+                    reference(98, "replied_to", 10),
+                ],
+            },
+        )
+        .await;
+        data::write::tweet_versions(
+            state,
+            &[0, 1].map(|version| tweet_versions::Model {
+                tweet_id: 10,
+                version,
+                edit_tweet_id: 10,
+                content: format!("Version {version}"),
+                recorded_at: created_at,
+            }),
+        )
+        .await;
+        // A tombstone archived tweet 10 carries, and statuses no tweet carries: one saying
+        // a tweet is still there and one for a tweet that was never archived.
+        data::write::tweet_statuses(
+            state,
+            &[(10, "deleted"), (11, "available"), (97, "protected")].map(|(tweet_id, status)| {
+                tweet_status::Model {
+                    tweet_id,
+                    status: status.to_string(),
+                    detail: (status == "deleted").then(|| "Deleted by its author".to_string()),
+                    checked_at: created_at,
+                }
+            }),
+        )
+        .await;
+        data::write::hidden_tweet(
+            state,
+            &hidden_tweets::Model {
+                tweet_id: 11,
+                reason: "Asked to".to_string(),
+                hidden_at: created_at,
+            },
+        )
+        .await;
+        data::write::redacted_user(
+            state,
+            &redacted_users::Model {
+                id: 0,
+                user_id: None,
+                username: Some("gone".to_string()),
+                reason: "Asked to".to_string(),
+                redacted_at: created_at,
+            },
+        )
+        .await;
+        // A removed collection leaves a gap in the ids, which a restore has to keep, and the
+        // first collection lists a tweet that isn't archived.
+        for name in ["Removed", "First, with a comma", "Second"] {
+            data::write::collection(
+                state,
+                &collections::Model {
+                    id: 0,
+                    name: name.to_string(),
+                    description: (name != "Second").then(|| "Described\nat length".to_string()),
+                    created_at,
+                    updated_at: created_at,
+                },
+            )
+            .await;
+        }
+        data::write::remove_collection(state, 1).await;
+        for (collection_id, tweet_id, note) in
+            [(2, 10, Some("A \"note\"")), (2, 96, None), (3, 11, None)]
+        {
+            data::write::collection_tweet(
+                state,
+                &collection_tweets::Model {
+                    collection_id,
+                    tweet_id,
+                    note: note.map(str::to_string),
+                    added_at: created_at,
+                },
+            )
+            .await;
+        }
+        for (tweet_id, tag) in [(10, "history"), (10, "maths"), (96, "history")] {
+            data::write::tweet_tag(
+                state,
+                &tweet_tags::Model {
+                    tweet_id,
+                    tag: tag.to_string(),
+                    tagged_at: created_at,
+                },
+            )
+            .await;
+        }
+        // Notes on a tweet and on a conversation, one of them removed so the ids have a gap.
+        for (tweet_id, conversation_id) in [(Some(10), None), (None, Some(10)), (Some(11), None)] {
+            data::write::annotation(
+                state,
+                &annotations::Model {
+                    id: 0,
+                    author: "researcher".to_string(),
+                    tweet_id,
+                    conversation_id,
+                    body: "# Context\n\nSee *this*, and \"that\".".to_string(),
+                    created_at,
+                    updated_at: created_at,
+                },
+            )
+            .await;
+        }
+        data::write::remove_annotation(state, 2).await;
+        db
+    }
+
+    #[tokio::test]
+    async fn ndjson_dumps_restore_exactly() {
+        let db = archive("ndjson-dump").await;
+        let path = std::env::temp_dir().join(format!(
+            "better-twitter-archiver-dump-{}.ndjson",
+            std::process::id()
+        ));
+        let written = export_ndjson(State::from(&db), File::create(&path).unwrap()).await;
+
+        let restored = setup::test_db("ndjson-restore").await;
+        let imported = import_ndjson(State::from(&restored), path.to_str().unwrap()).await;
+        assert_eq!(imported, written);
+        assert_eq!(snapshot(&restored).await, snapshot(&db).await);
+    }
+
+    #[tokio::test]
+    async fn csv_dumps_restore_exactly() {
+        let db = archive("csv-dump").await;
+        let dir = std::env::temp_dir().join(format!(
+            "better-twitter-archiver-dump-{}",
+            std::process::id()
+        ));
+        let written = export_csv(State::from(&db), dir.to_str().unwrap()).await;
+
+        let restored = setup::test_db("csv-restore").await;
+        let imported = import_csv(State::from(&restored), dir.to_str().unwrap()).await;
+        assert_eq!(imported, written);
+        assert_eq!(snapshot(&restored).await, snapshot(&db).await);
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, FixedOffset};
+use rocket::http::RawStr;
+use rocket::State;
+use sea_orm::DatabaseConnection;
+
+use super::site::escape_html as escape_xml;
+use crate::app::data;
+use crate::app::data::entities::{tweets, users};
+use crate::utils::TweetData;
+
+// Feed readers only need the recent history, not the whole archive.
+const FEED_LENGTH: usize = 50;
+
+// Both user feeds are None when the user isn't archived.
+pub async fn user_atom(db: &State<DatabaseConnection>, twitter_handle: &str) -> Option<String> {
+    let tweets = latest_users_tweets(db, twitter_handle).await?;
+    let users = authors(db, &tweets).await;
+    Some(atom(
+        &format!("tag:twitter.com,2006:user/{twitter_handle}"),
+        &format!("@{twitter_handle}'s archived tweets"),
+        &format!("https://twitter.com/{twitter_handle}"),
+        &tweets,
+        &users,
+    ))
+}
+
+pub async fn user_rss(db: &State<DatabaseConnection>, twitter_handle: &str) -> Option<String> {
+    let tweets = latest_users_tweets(db, twitter_handle).await?;
+    let users = authors(db, &tweets).await;
+    let items: String = latest(&tweets)
+        .map(|tweet| {
+            format!(
+                "<item><guid isPermaLink=\"false\">{id}</guid><link>{link}</link>\
+                 <title>{title}</title><description>{content}</description>\
+                 <pubDate>{date}</pubDate></item>",
+                id = entry_id(tweet),
+                link = escape_xml(&tweet_url(tweet, &users)),
+                title = escape_xml(&entry_title(tweet)),
+                content = escape_xml(&tweet.content),
+                date = tweet.created_at.to_rfc2822(),
+            )
+        })
+        .collect();
+    Some(format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<rss version=\"2.0\"><channel>\
+         <title>{title}</title><link>{link}</link><description>{title}</description>\
+         {items}</channel></rss>\n",
+        title = escape_xml(&format!("@{twitter_handle}'s archived tweets")),
+        link = escape_xml(&format!("https://twitter.com/{twitter_handle}")),
+    ))
+}
+
+pub async fn search_atom(db: &State<DatabaseConnection>, search_query: &str) -> String {
+    let tweets = data::read::latest_search_results(db, search_query, FEED_LENGTH as u64).await;
+    let users = authors(db, &tweets).await;
+    atom(
+        &format!("tag:twitter.com,2006:search/{search_query}"),
+        &format!("Archived tweets matching \"{search_query}\""),
+        &format!(
+            "https://twitter.com/search?q={}",
+            RawStr::new(search_query).percent_encode()
+        ),
+        &tweets,
+        &users,
+    )
+}
+
+fn atom(
+    feed_id: &str,
+    title: &str,
+    link: &str,
+    tweets: &[TweetData],
+    users: &HashMap<i64, users::Model>,
+) -> String {
+    let updated = latest(tweets)
+        .map(|tweet| tweet.created_at)
+        .max()
+        .map(|date| date.to_rfc3339())
+        .unwrap_or_else(|| DateTime::<FixedOffset>::from(chrono::Utc::now()).to_rfc3339());
+    let entries: String = latest(tweets)
+        .map(|tweet| {
+            format!(
+                "<entry><id>{id}</id><title>{title}</title><updated>{updated}</updated>\
+                 <published>{updated}</published><author><name>{author}</name></author>\
+                 <link href=\"{link}\"/><content type=\"text\">{content}</content></entry>",
+                id = entry_id(tweet),
+                title = escape_xml(&entry_title(tweet)),
+                updated = tweet.created_at.to_rfc3339(),
+                author = escape_xml(&username(tweet.author_id, users)),
+                link = escape_xml(&tweet_url(tweet, users)),
+                content = escape_xml(&tweet.content),
+            )
+        })
+        .collect();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\
+         <id>{feed_id}</id><title>{title}</title><updated>{updated}</updated>\
+         <link href=\"{link}\"/>{entries}</feed>\n",
+        feed_id = escape_xml(feed_id),
+        title = escape_xml(title),
+        link = escape_xml(link),
+    )
+}
+
+fn latest(tweets: &[TweetData]) -> impl Iterator<Item = &tweets::Model> {
+    tweets
+        .iter()
+        .filter_map(|tweet_data| tweet_data.tweet.as_ref())
+        .take(FEED_LENGTH)
+}
+
+// Snowflakes never change, so they make entry ids that stay stable across exports.
+fn entry_id(tweet: &tweets::Model) -> String {
+    format!("tag:twitter.com,2006:status/{}", tweet.id)
+}
+
+fn entry_title(tweet: &tweets::Model) -> String {
+    let title: String = tweet.content.chars().take(80).collect();
+    if title.len() < tweet.content.len() {
+        format!("{}…", title.trim_end())
+    } else {
+        title
+    }
+}
+
+fn tweet_url(tweet: &tweets::Model, users: &HashMap<i64, users::Model>) -> String {
+    format!(
+        "https://twitter.com/{}/status/{}",
+        username(tweet.author_id, users),
+        tweet.id
+    )
+}
+
+fn username(author_id: i64, users: &HashMap<i64, users::Model>) -> String {
+    users
+        .get(&author_id)
+        .map(|user| user.username.clone())
+        .unwrap_or_else(|| author_id.to_string())
+}
+
+async fn latest_users_tweets(
+    db: &State<DatabaseConnection>,
+    twitter_handle: &str,
+) -> Option<Vec<TweetData>> {
+    let user = data::read::user_by_twitter_handle(db, twitter_handle).await.user?;
+    Some(data::read::users_tweets_page(db, user.id, 0, FEED_LENGTH).await.tweets)
+}
+
+// Only the authors of the tweets in the feed are loaded.
+async fn authors(
+    db: &State<DatabaseConnection>,
+    tweets: &[TweetData],
+) -> HashMap<i64, users::Model> {
+    let author_ids: Vec<i64> = latest(tweets).map(|tweet| tweet.author_id).collect();
+    data::read::users_by_ids(db, &author_ids)
+        .await
+        .into_iter()
+        .map(|user| (user.id, user))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tweet_data(id: i64, content: &str) -> TweetData {
+        let mut tweet_data = TweetData::empty();
+        tweet_data.tweet = Some(tweets::Model {
+            id,
+            content: content.to_string(),
+            author_id: 7,
+            conversation_id: id,
+            created_at: DateTime::parse_from_rfc3339("2022-01-05T10:00:00Z").unwrap(),
+        });
+        tweet_data
+    }
+
+    fn users() -> HashMap<i64, users::Model> {
+        HashMap::from([(
+            7,
+            users::Model {
+                id: 7,
+                name: "Tom & Jerry".to_string(),
+                username: "tom".to_string(),
+                description: String::new(),
+            },
+        )])
+    }
+
+    #[test]
+    fn atom_escapes_what_it_takes_from_tweets() {
+        let feed = atom(
+            "tag:twitter.com,2006:user/tom",
+            "@tom's <archived> tweets",
+            "https://twitter.com/tom",
+            &[tweet_data(1, "1 < 2 & \"quotes\"")],
+            &users(),
+        );
+        assert!(feed.contains("<title>@tom&#39;s &lt;archived&gt; tweets</title>"));
+        assert!(feed.contains("<content type=\"text\">1 &lt; 2 &amp; &quot;quotes&quot;</content>"));
+        assert!(feed.contains("<link href=\"https://twitter.com/tom/status/1\"/>"));
+        assert!(feed.contains("<id>tag:twitter.com,2006:status/1</id>"));
+        assert!(feed.contains("<updated>2022-01-05T10:00:00+00:00</updated>"));
+    }
+
+    #[test]
+    fn long_tweets_are_cut_short_for_titles() {
+        let long = "word ".repeat(30);
+        let title = entry_title(&tweet_data(1, &long).tweet.unwrap());
+        assert!(title.ends_with("word…"));
+        assert!(title.chars().count() <= 81);
+        let short = entry_title(&tweet_data(1, "short").tweet.unwrap());
+        assert_eq!(short, "short");
+        // Titles are cut by characters, not bytes.
+        let emoji = entry_title(&tweet_data(1, &"🦀".repeat(80)).tweet.unwrap());
+        assert_eq!(emoji, "🦀".repeat(80));
+    }
+
+    #[test]
+    fn feeds_hold_the_latest_tweets_only() {
+        let mut tweets: Vec<TweetData> = (1..=60).map(|id| tweet_data(id, "tweet")).collect();
+        tweets.insert(0, TweetData::empty());
+        assert_eq!(latest(&tweets).count(), FEED_LENGTH);
+        assert_eq!(latest(&tweets).next().unwrap().id, 1);
+    }
+
+    // Tom has more tweets than a feed holds, and Jerry has one newer tweet that matches
+    // the same searches.
+    #[tokio::test]
+    async fn feeds_only_read_the_latest_tweets_and_their_authors() {
+        let db = crate::app::data::setup::test_db("feeds").await;
+        let state = State::from(&db);
+        let created_at = DateTime::parse_from_rfc3339("2022-01-05T10:00:00Z").unwrap();
+        let user = |id: i64, username: &str| users::Model {
+            id,
+            name: username.to_string(),
+            username: username.to_string(),
+            description: String::new(),
+        };
+        let tweet = |id: i64, author_id: i64| tweets::Model {
+            id,
+            content: format!("tweet {id}"),
+            author_id,
+            conversation_id: 1,
+            created_at: created_at + chrono::Duration::minutes(id),
+        };
+        data::write::dumped_rows(
+            state,
+            data::write::DumpedRows {
+                users: vec![user(7, "tom"), user(8, "jerry")],
+                conversations: vec![crate::app::data::entities::conversations::Model { id: 1 }],
+                tweets: (1..=60)
+                    .map(|id| tweet(id, 7))
+                    .chain([tweet(61, 8)])
+                    .collect(),
+                references: Vec::new(),
+            },
+        )
+        .await;
+
+        let feed = user_rss(state, "tom").await.unwrap();
+        assert_eq!(feed.matches("<item>").count(), FEED_LENGTH);
+        assert!(feed.contains("<title>tweet 60</title>"));
+        assert!(!feed.contains("<title>tweet 10</title>"));
+        assert!(user_atom(state, "nobody").await.is_none());
+
+        let feed = search_atom(state, "tweet").await;
+        assert_eq!(feed.matches("<entry>").count(), FEED_LENGTH);
+        assert!(feed.contains("<link href=\"https://twitter.com/jerry/status/61\"/>"));
+        assert!(feed.contains("<link href=\"https://twitter.com/tom/status/12\"/>"));
+        assert!(!feed.contains("status/11\""));
+    }
+
+    #[test]
+    fn authors_that_arent_archived_go_by_their_id() {
+        assert_eq!(username(7, &users()), "tom");
+        assert_eq!(username(8, &users()), "8");
+    }
+}
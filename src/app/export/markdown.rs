@@ -0,0 +1,288 @@
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+use rocket::State;
+use sea_orm::DatabaseConnection;
+
+use crate::app::data;
+use crate::app::data::entities::users;
+use crate::utils::{ConversationData, TweetData};
+
+// Writes one markdown note per conversation the user took part in, in a layout that
+// works as an Obsidian vault: front matter for the metadata, quoted tweets embedded as
+// blockquotes and wiki-links to the conversations that quote or reply to each other.
+pub async fn export(db: &State<DatabaseConnection>, twitter_handle: &str, out_dir: &str) {
+    let users_tweets = match data::read::users_tweets(db, twitter_handle).await {
+        Some(users_tweets) => users_tweets,
+        None => {
+            println!("@{twitter_handle} isn't archived");
+            return;
+        }
+    };
+    let out_dir = Path::new(out_dir);
+    fs::create_dir_all(out_dir).unwrap_or_else(|error| {
+        panic!("Failed to create {}. Error: {:?}", out_dir.display(), error)
+    });
+    let users: HashMap<i64, users::Model> = data::read::users(db)
+        .await
+        .into_iter()
+        .filter_map(|user_data| user_data.user)
+        .map(|user| (user.id, user))
+        .collect();
+
+    let mut conversations: BTreeMap<i64, ConversationData> = BTreeMap::new();
+    for tweet_data in users_tweets {
+        if let Some(tweet) = tweet_data.tweet {
+            if let Entry::Vacant(entry) = conversations.entry(tweet.conversation_id) {
+                entry.insert(data::read::conversation(db, tweet.conversation_id).await);
+            }
+        }
+    }
+
+    for conversation in conversations.values() {
+        let note = Note::load(db, conversation).await;
+        let path = out_dir.join(format!("{}.md", note_name(conversation.id)));
+        fs::write(&path, note.render(&users)).unwrap_or_else(|error| {
+            panic!("Failed to write {}. Error: {:?}", path.display(), error)
+        });
+    }
+    println!(
+        "Exported {} conversations to {}",
+        conversations.len(),
+        out_dir.display()
+    );
+}
+
+struct Note<'a> {
+    conversation: &'a ConversationData,
+    // Tweets referenced from this conversation, keyed by id, when they are archived.
+    referenced_tweets: HashMap<i64, TweetData>,
+    // Other conversations this one quotes or replies to, and ones that quote or reply to it.
+    links_to: BTreeSet<i64>,
+    linked_from: BTreeSet<i64>,
+}
+
+impl<'a> Note<'a> {
+    async fn load(db: &State<DatabaseConnection>, conversation: &'a ConversationData) -> Note<'a> {
+        let tweet_ids: Vec<i64> = conversation
+            .tweets
+            .iter()
+            .filter_map(|tweet_data| tweet_data.tweet.as_ref().map(|tweet| tweet.id))
+            .collect();
+
+        let mut referenced_tweets = HashMap::new();
+        let mut links_to = BTreeSet::new();
+        for reference in conversation
+            .tweets
+            .iter()
+            .flat_map(|tweet_data| tweet_data.references.iter())
+        {
+            let referenced =
+                data::read::visible_tweet_by_id(db, reference.referenced_tweet_id).await;
+            if let Some(tweet) = &referenced.tweet {
+                if tweet.conversation_id != conversation.id {
+                    links_to.insert(tweet.conversation_id);
+                }
+                referenced_tweets.insert(tweet.id, referenced);
+            }
+        }
+
+        let mut linked_from = BTreeSet::new();
+        for reference in data::read::references_to_tweets(db, &tweet_ids).await {
+            if let Some(tweet) = data::read::visible_tweet_by_id(db, reference.source_tweet_id)
+                .await
+                .tweet
+            {
+                if tweet.conversation_id != conversation.id {
+                    linked_from.insert(tweet.conversation_id);
+                }
+            }
+        }
+
+        Note {
+            conversation,
+            referenced_tweets,
+            links_to,
+            linked_from,
+        }
+    }
+
+    fn render(&self, users: &HashMap<i64, users::Model>) -> String {
+        let username = |author_id: i64| {
+            users
+                .get(&author_id)
+                .map(|user| user.username.clone())
+                .unwrap_or_else(|| author_id.to_string())
+        };
+        let tweets: Vec<_> = self
+            .conversation
+            .tweets
+            .iter()
+            .filter_map(|tweet_data| tweet_data.tweet.as_ref().map(|tweet| (tweet, tweet_data)))
+            .collect();
+        let authors: BTreeSet<String> = tweets
+            .iter()
+            .map(|(tweet, _tweet_data)| username(tweet.author_id))
+            .collect();
+        let hashtags: BTreeSet<String> = tweets
+            .iter()
+            .flat_map(|(tweet, _tweet_data)| hashtags(&tweet.content))
+            .collect();
+
+        let mut output = String::from("---\n");
+        output.push_str(&format!("conversation_id: \"{}\"\n", self.conversation.id));
+        output.push_str(&yaml_list(
+            "tweet_ids",
+            tweets
+                .iter()
+                .map(|(tweet, _tweet_data)| format!("\"{}\"", tweet.id)),
+        ));
+        output.push_str(&yaml_list(
+            "authors",
+            authors.iter().map(|author| yaml_string(author)),
+        ));
+        if let (Some((first, _)), Some((last, _))) = (tweets.first(), tweets.last()) {
+            output.push_str(&format!("created: {}\n", first.created_at.to_rfc3339()));
+            output.push_str(&format!("updated: {}\n", last.created_at.to_rfc3339()));
+        }
+        output.push_str(&yaml_list(
+            "hashtags",
+            hashtags.iter().map(|hashtag| yaml_string(hashtag)),
+        ));
+        output.push_str("---\n\n");
+        output.push_str(&format!("# Conversation {}\n", self.conversation.id));
+
+        for (tweet, tweet_data) in tweets.iter() {
+            output.push_str(&format!(
+                "\n## @{} · {}\n\n{}\n",
+                username(tweet.author_id),
+                tweet.created_at.format("%Y-%m-%d %H:%M"),
+                tweet.content
+            ));
+            for reference in tweet_data.references.iter() {
+                let referenced = self
+                    .referenced_tweets
+                    .get(&reference.referenced_tweet_id)
+                    .and_then(|tweet_data| tweet_data.tweet.as_ref());
+                match (reference.reference_type.as_str(), referenced) {
+                    ("quoted", Some(quoted)) => {
+                        output.push_str(&format!(
+                            "\n> **@{}** · {}\n",
+                            username(quoted.author_id),
+                            quoted.created_at.format("%Y-%m-%d %H:%M")
+                        ));
+                        for line in quoted.content.lines() {
+                            output.push_str(&format!("> {line}\n"));
+                        }
+                        if quoted.conversation_id != self.conversation.id {
+                            output.push_str(&format!(
+                                "> [[{}]]\n",
+                                note_name(quoted.conversation_id)
+                            ));
+                        }
+                    }
+                    (_, Some(referenced)) if referenced.conversation_id != self.conversation.id => {
+                        output.push_str(&format!(
+                            "\n*{} [[{}]]*\n",
+                            reference.reference_type.replace('_', " "),
+                            note_name(referenced.conversation_id)
+                        ));
+                    }
+                    (_, Some(_referenced)) => (),
+                    (_, None) => output.push_str(&format!(
+                        "\n*{} https://twitter.com/i/web/status/{} (not archived)*\n",
+                        reference.reference_type.replace('_', " "),
+                        reference.referenced_tweet_id
+                    )),
+                }
+            }
+        }
+
+        if !self.links_to.is_empty() || !self.linked_from.is_empty() {
+            output.push_str("\n## Links\n\n");
+            for conversation_id in self.links_to.iter() {
+                output.push_str(&format!(
+                    "- Refers to [[{}]]\n",
+                    note_name(*conversation_id)
+                ));
+            }
+            for conversation_id in self.linked_from.iter() {
+                output.push_str(&format!(
+                    "- Referred to by [[{}]]\n",
+                    note_name(*conversation_id)
+                ));
+            }
+        }
+        output
+    }
+}
+
+fn note_name(conversation_id: i64) -> String {
+    format!("conversation-{conversation_id}")
+}
+
+fn hashtags(content: &str) -> Vec<String> {
+    content
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix('#'))
+        .map(|tag| {
+            tag.chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .collect::<String>()
+        })
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+fn yaml_list(key: &str, items: impl Iterator<Item = String>) -> String {
+    let items: Vec<String> = items.collect();
+    if items.is_empty() {
+        format!("{key}: []\n")
+    } else {
+        format!(
+            "{key}:\n{}",
+            items
+                .iter()
+                .map(|item| format!("  - {item}\n"))
+                .collect::<String>()
+        )
+    }
+}
+
+fn yaml_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashtags_stop_at_punctuation() {
+        assert_eq!(
+            hashtags("#rust, #open_source! and # nothing #日本 x#no"),
+            vec!["rust", "open_source", "日本"]
+        );
+    }
+
+    #[test]
+    fn yaml_lists_are_empty_or_one_item_per_line() {
+        assert_eq!(yaml_list("tags", std::iter::empty()), "tags: []\n");
+        assert_eq!(
+            yaml_list("tags", ["a".to_string(), "b".to_string()].into_iter()),
+            "tags:\n  - a\n  - b\n"
+        );
+    }
+
+    #[test]
+    fn yaml_strings_escape_quotes_and_backslashes() {
+        assert_eq!(yaml_string(r#"say "hi" \o/"#), r#""say \"hi\" \\o/""#);
+    }
+
+    #[test]
+    fn notes_are_named_after_their_conversation() {
+        assert_eq!(note_name(42), "conversation-42");
+    }
+}
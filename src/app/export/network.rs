@@ -0,0 +1,131 @@
+use std::str::FromStr;
+
+use super::site::escape_html as escape_xml;
+use crate::utils::{GraphNode, InteractionGraph};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkFormat {
+    Graphml,
+    Gexf,
+}
+
+impl FromStr for NetworkFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "graphml" => Ok(Self::Graphml),
+            "gexf" => Ok(Self::Gexf),
+            _ => Err(format!(
+                "Unknown network format {format}, expected graphml or gexf"
+            )),
+        }
+    }
+}
+
+pub fn to_format(graph: &InteractionGraph, format: NetworkFormat) -> String {
+    match format {
+        NetworkFormat::Graphml => graphml(graph),
+        NetworkFormat::Gexf => gexf(graph),
+    }
+}
+
+pub fn graphml(graph: &InteractionGraph) -> String {
+    let nodes: String = graph
+        .nodes
+        .iter()
+        .map(|node| {
+            format!(
+                "<node id=\"{id}\"><data key=\"username\">{username}</data>\
+                 <data key=\"community\">{community}</data></node>\n",
+                id = node.user_id,
+                username = escape_xml(&label(node)),
+                community = node.community,
+            )
+        })
+        .collect();
+    let edges: String = graph
+        .edges
+        .iter()
+        .map(|edge| {
+            format!(
+                "<edge source=\"{source}\" target=\"{target}\">\
+                 <data key=\"weight\">{weight}</data><data key=\"replies\">{replies}</data>\
+                 <data key=\"quotes\">{quotes}</data><data key=\"first_at\">{first_at}</data>\
+                 <data key=\"last_at\">{last_at}</data></edge>\n",
+                source = edge.source_user_id,
+                target = edge.target_user_id,
+                weight = edge.weight,
+                replies = edge.replies,
+                quotes = edge.quotes,
+                first_at = escape_xml(&edge.first_at),
+                last_at = escape_xml(&edge.last_at),
+            )
+        })
+        .collect();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n\
+         <key id=\"username\" for=\"node\" attr.name=\"username\" attr.type=\"string\"/>\n\
+         <key id=\"community\" for=\"node\" attr.name=\"community\" attr.type=\"long\"/>\n\
+         <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"long\"/>\n\
+         <key id=\"replies\" for=\"edge\" attr.name=\"replies\" attr.type=\"long\"/>\n\
+         <key id=\"quotes\" for=\"edge\" attr.name=\"quotes\" attr.type=\"long\"/>\n\
+         <key id=\"first_at\" for=\"edge\" attr.name=\"first_at\" attr.type=\"string\"/>\n\
+         <key id=\"last_at\" for=\"edge\" attr.name=\"last_at\" attr.type=\"string\"/>\n\
+         <graph id=\"interactions\" edgedefault=\"directed\">\n{nodes}{edges}</graph>\n</graphml>\n"
+    )
+}
+
+// Edges carry their time range as a spell, so Gephi's timeline can replay the network.
+pub fn gexf(graph: &InteractionGraph) -> String {
+    let nodes: String = graph
+        .nodes
+        .iter()
+        .map(|node| {
+            format!(
+                "<node id=\"{id}\" label=\"{label}\"><attvalues>\
+                 <attvalue for=\"community\" value=\"{community}\"/></attvalues></node>\n",
+                id = node.user_id,
+                label = escape_xml(&label(node)),
+                community = node.community,
+            )
+        })
+        .collect();
+    let edges: String = graph
+        .edges
+        .iter()
+        .enumerate()
+        .map(|(i, edge)| {
+            format!(
+                "<edge id=\"{i}\" source=\"{source}\" target=\"{target}\" weight=\"{weight}\" \
+                 start=\"{first_at}\" end=\"{last_at}\"><attvalues>\
+                 <attvalue for=\"replies\" value=\"{replies}\"/>\
+                 <attvalue for=\"quotes\" value=\"{quotes}\"/></attvalues></edge>\n",
+                source = edge.source_user_id,
+                target = edge.target_user_id,
+                weight = edge.weight,
+                first_at = escape_xml(&edge.first_at),
+                last_at = escape_xml(&edge.last_at),
+                replies = edge.replies,
+                quotes = edge.quotes,
+            )
+        })
+        .collect();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <gexf xmlns=\"http://gexf.net/1.3\" version=\"1.3\">\n\
+         <graph mode=\"dynamic\" defaultedgetype=\"directed\" timeformat=\"dateTime\">\n\
+         <attributes class=\"node\"><attribute id=\"community\" title=\"community\" type=\"long\"/></attributes>\n\
+         <attributes class=\"edge\"><attribute id=\"replies\" title=\"replies\" type=\"long\"/>\
+         <attribute id=\"quotes\" title=\"quotes\" type=\"long\"/></attributes>\n\
+         <nodes>\n{nodes}</nodes>\n<edges>\n{edges}</edges>\n</graph>\n</gexf>\n"
+    )
+}
+
+fn label(node: &GraphNode) -> String {
+    match &node.username {
+        Some(username) => format!("@{username}"),
+        None => node.user_id.to_string(),
+    }
+}
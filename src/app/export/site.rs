@@ -0,0 +1,448 @@
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use rocket::State;
+use sea_orm::DatabaseConnection;
+use serde::Serialize;
+
+use crate::app::data;
+use crate::app::data::entities::users;
+use crate::utils::{ConversationData, TweetData};
+
+// Pages live either at the root of the site or one directory down, so links are built from
+// a prefix that points back at the root.
+const ROOT: &str = "";
+const NESTED: &str = "../";
+
+#[derive(Debug, Serialize)]
+struct SearchEntry {
+    // Snowflakes don't fit in a javascript number, so ids are written as strings.
+    id: String,
+    author: String,
+    created_at: String,
+    content: String,
+}
+
+// Writes a user's archived tweets to `out_dir` as a static site: an index per user and per
+// month, a page per conversation, a permalink page per tweet and a search index.
+pub async fn export(db: &State<DatabaseConnection>, twitter_handle: &str, out_dir: &str) {
+    let users_tweets = match data::read::users_tweets(db, twitter_handle).await {
+        Some(users_tweets) => users_tweets,
+        None => {
+            println!("@{twitter_handle} isn't archived");
+            return;
+        }
+    };
+    let out_dir = Path::new(out_dir);
+    let users: HashMap<i64, users::Model> = data::read::users(db)
+        .await
+        .into_iter()
+        .filter_map(|user_data| user_data.user)
+        .map(|user| (user.id, user))
+        .collect();
+
+    let mut conversations: BTreeMap<i64, ConversationData> = BTreeMap::new();
+    for tweet in users_tweets
+        .iter()
+        .filter_map(|tweet_data| tweet_data.tweet.as_ref())
+    {
+        if let Entry::Vacant(entry) = conversations.entry(tweet.conversation_id) {
+            entry.insert(data::read::conversation(db, tweet.conversation_id).await);
+        }
+    }
+    let archived_tweets: BTreeMap<i64, &TweetData> = conversations
+        .values()
+        .flat_map(|conversation| conversation.tweets.iter())
+        .chain(users_tweets.iter())
+        .filter_map(|tweet_data| {
+            tweet_data
+                .tweet
+                .as_ref()
+                .map(|tweet| (tweet.id, tweet_data))
+        })
+        .collect();
+    let site = Site {
+        users: &users,
+        archived_tweet_ids: archived_tweets.keys().copied().collect(),
+    };
+
+    let mut months: BTreeMap<String, Vec<&TweetData>> = BTreeMap::new();
+    for tweet_data in users_tweets.iter() {
+        if let Some(tweet) = &tweet_data.tweet {
+            months
+                .entry(tweet.created_at.format("%Y-%m").to_string())
+                .or_default()
+                .push(tweet_data);
+        }
+    }
+
+    let user_dir_name = dir_name(twitter_handle);
+    write_page(
+        &out_dir.join("index.html"),
+        &page(
+            "Archive",
+            ROOT,
+            &format!(
+                "<ul><li><a href=\"{dir}/index.html\">@{handle}</a></li></ul>",
+                dir = escape_html(&user_dir_name),
+                handle = escape_html(twitter_handle)
+            ),
+        ),
+    );
+    write_page(&out_dir.join("search.html"), &search_page());
+
+    let user_dir = out_dir.join(&user_dir_name);
+    let month_links: String = months
+        .iter()
+        .rev()
+        .map(|(month, tweets)| {
+            format!(
+                "<li><a href=\"{month}.html\">{month}</a> ({} tweets)</li>",
+                tweets.len()
+            )
+        })
+        .collect();
+    write_page(
+        &user_dir.join("index.html"),
+        &page(
+            &format!("@{twitter_handle}"),
+            NESTED,
+            &format!("<ul>{month_links}</ul>"),
+        ),
+    );
+    for (month, tweets) in months.iter() {
+        let body: String = tweets
+            .iter()
+            .map(|tweet_data| site.tweet(tweet_data, NESTED))
+            .collect();
+        write_page(
+            &user_dir.join(format!("{month}.html")),
+            &page(&format!("@{twitter_handle} in {month}"), NESTED, &body),
+        );
+    }
+
+    for conversation in conversations.values() {
+        write_page(
+            &out_dir
+                .join("conversation")
+                .join(format!("{}.html", conversation.id)),
+            &page(
+                &format!("Conversation {}", conversation.id),
+                NESTED,
+                &site.thread(conversation),
+            ),
+        );
+    }
+
+    for (id, tweet_data) in archived_tweets.iter() {
+        write_page(
+            &out_dir.join("tweet").join(format!("{id}.html")),
+            &page(
+                &format!("Tweet {id}"),
+                NESTED,
+                &site.tweet(tweet_data, NESTED),
+            ),
+        );
+    }
+
+    let search_index: Vec<SearchEntry> = archived_tweets
+        .values()
+        .filter_map(|tweet_data| tweet_data.tweet.as_ref())
+        .map(|tweet| SearchEntry {
+            id: tweet.id.to_string(),
+            author: site.username(tweet.author_id),
+            created_at: tweet.created_at.to_rfc3339(),
+            content: tweet.content.clone(),
+        })
+        .collect();
+    // A script rather than json, since browsers won't fetch files next to a page opened
+    // from disk.
+    write_page(
+        &out_dir.join("search-index.js"),
+        &format!(
+            "const INDEX = {};\n",
+            serde_json::to_string(&search_index).expect("Failed to serialize the search index")
+        ),
+    );
+    println!(
+        "Exported {} tweets in {} conversations to {}",
+        archived_tweets.len(),
+        conversations.len(),
+        out_dir.display()
+    );
+}
+
+struct Site<'a> {
+    users: &'a HashMap<i64, users::Model>,
+    archived_tweet_ids: HashSet<i64>,
+}
+
+impl Site<'_> {
+    fn username(&self, author_id: i64) -> String {
+        self.users
+            .get(&author_id)
+            .map(|user| user.username.clone())
+            .unwrap_or_else(|| author_id.to_string())
+    }
+
+    fn tweet_link(&self, id: i64, root: &str) -> String {
+        if self.archived_tweet_ids.contains(&id) {
+            format!("<a href=\"{root}tweet/{id}.html\">{id}</a>")
+        } else {
+            format!("<a href=\"https://twitter.com/i/web/status/{id}\">{id}</a> (not archived)")
+        }
+    }
+
+    fn tweet(&self, tweet_data: &TweetData, root: &str) -> String {
+        let tweet = match &tweet_data.tweet {
+            Some(tweet) => tweet,
+            None => return String::new(),
+        };
+        let references: String = tweet_data
+            .references
+            .iter()
+            .map(|reference| {
+                format!(
+                    "<div class=\"reference\">{} {}</div>",
+                    reference.reference_type.replace('_', " "),
+                    self.tweet_link(reference.referenced_tweet_id, root)
+                )
+            })
+            .collect();
+        format!(
+            "<article class=\"tweet\"><header><strong>@{author}</strong> \
+             <a href=\"{root}tweet/{id}.html\">{date}</a></header>{references}\
+             <p>{content}</p><footer><a href=\"{root}conversation/{conversation_id}.html\">\
+             conversation</a></footer></article>",
+            author = escape_html(&self.username(tweet.author_id)),
+            id = tweet.id,
+            date = tweet.created_at.format("%Y-%m-%d %H:%M"),
+            content = escape_html(&tweet.content).replace('\n', "<br>"),
+            conversation_id = tweet.conversation_id,
+        )
+    }
+
+    fn thread(&self, conversation: &ConversationData) -> String {
+        thread(conversation, |tweet_data| self.tweet(tweet_data, NESTED))
+    }
+}
+
+// Nests each reply under the tweet it replied to, rendering each tweet with `render`.
+// Tweets whose parent isn't in the conversation are shown at the top level.
+pub fn thread(conversation: &ConversationData, render: impl Fn(&TweetData) -> String) -> String {
+    let ids: HashSet<i64> = conversation
+        .tweets
+        .iter()
+        .filter_map(|tweet_data| tweet_data.tweet.as_ref().map(|tweet| tweet.id))
+        .collect();
+    let mut replies: HashMap<i64, Vec<&TweetData>> = HashMap::new();
+    let mut roots: Vec<&TweetData> = Vec::new();
+    for tweet_data in conversation.tweets.iter() {
+        match replied_to_id(tweet_data).filter(|parent_id| ids.contains(parent_id)) {
+            Some(parent_id) => replies.entry(parent_id).or_default().push(tweet_data),
+            None => roots.push(tweet_data),
+        }
+    }
+    roots
+        .into_iter()
+        .map(|tweet_data| thread_branch(tweet_data, &replies, &render))
+        .collect()
+}
+
+fn thread_branch(
+    tweet_data: &TweetData,
+    replies: &HashMap<i64, Vec<&TweetData>>,
+    render: &impl Fn(&TweetData) -> String,
+) -> String {
+    let children: String = tweet_data
+        .tweet
+        .as_ref()
+        .and_then(|tweet| replies.get(&tweet.id))
+        .map(|children| {
+            children
+                .iter()
+                .map(|child| thread_branch(child, replies, render))
+                .collect()
+        })
+        .unwrap_or_default();
+    format!(
+        "<div class=\"thread\">{}<div class=\"replies\">{children}</div></div>",
+        render(tweet_data)
+    )
+}
+
+pub fn replied_to_id(tweet_data: &TweetData) -> Option<i64> {
+    tweet_data
+        .references
+        .iter()
+        .find(|reference| reference.reference_type == "replied_to")
+        .map(|reference| reference.referenced_tweet_id)
+}
+
+fn page(title: &str, root: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\"><head><meta charset=\"utf-8\">\
+         <title>{title}</title><style>{STYLE}</style></head><body>\
+         <nav><a href=\"{root}index.html\">Archive</a> · <a href=\"{root}search.html\">Search</a></nav>\
+         <h1>{title}</h1>{body}</body></html>\n",
+        title = escape_html(title),
+    )
+}
+
+fn search_page() -> String {
+    page(
+        "Search",
+        ROOT,
+        "<input id=\"query\" type=\"search\" placeholder=\"Search tweets\" autofocus>\
+         <ul id=\"results\"></ul>\
+         <script src=\"search-index.js\"></script>\
+         <script>\
+         const query = document.getElementById('query');\
+         const results = document.getElementById('results');\
+         query.addEventListener('input', () => {\
+           const q = query.value.toLowerCase();\
+           results.replaceChildren(...(q ? INDEX.filter(t => t.content.toLowerCase().includes(q)) : [])\
+             .slice(0, 100).map(t => {\
+               const li = document.createElement('li');\
+               const a = document.createElement('a');\
+               a.href = 'tweet/' + t.id + '.html';\
+               a.textContent = '@' + t.author + ' ' + t.created_at.slice(0, 10);\
+               li.append(a, ' ' + t.content);\
+               return li;\
+             }));\
+         });\
+         </script>",
+    )
+}
+
+pub const STYLE: &str = "body{font-family:sans-serif;max-width:40em;margin:auto;padding:1em}\
+    .tweet{border:1px solid #ddd;border-radius:4px;padding:.5em;margin:.5em 0}\
+    .tweet header,.tweet footer,.reference{font-size:.85em;color:#555}\
+    .replies{margin-left:1.5em}";
+
+// Other file names are built from ids and dates, but the handle comes from the caller, so
+// anything a handle can't contain is replaced to keep the directory inside `out_dir`.
+fn dir_name(twitter_handle: &str) -> String {
+    let name: String = twitter_handle
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+    if name.is_empty() {
+        "user".to_string()
+    } else {
+        name
+    }
+}
+
+fn write_page(path: &Path, contents: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap_or_else(|error| {
+            panic!("Failed to create {}. Error: {:?}", parent.display(), error)
+        });
+    }
+    fs::write(path, contents)
+        .unwrap_or_else(|error| panic!("Failed to write {}. Error: {:?}", path.display(), error));
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::data::entities::{tweet_references, tweets};
+
+    fn tweet_data(id: i64, content: &str, replied_to: Option<i64>) -> TweetData {
+        let mut tweet_data = TweetData::empty();
+        tweet_data.tweet = Some(tweets::Model {
+            id,
+            content: content.to_string(),
+            author_id: 1,
+            conversation_id: 1,
+            created_at: chrono::DateTime::parse_from_rfc3339("2022-01-05T10:00:00Z").unwrap(),
+        });
+        tweet_data.references = replied_to
+            .into_iter()
+            .map(|referenced_tweet_id| tweet_references::Model {
+                source_tweet_id: id,
+                reference_type: "replied_to".to_string(),
+                referenced_tweet_id,
+            })
+            .collect();
+        tweet_data
+    }
+
+    #[test]
+    fn escape_html_escapes_markup_and_quotes() {
+        assert_eq!(
+            escape_html(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+        assert_eq!(escape_html("&amp;"), "&amp;amp;");
+    }
+
+    #[test]
+    fn dir_name_keeps_handles_inside_the_output_directory() {
+        assert_eq!(dir_name("jack_1"), "jack_1");
+        assert_eq!(dir_name("../../etc"), "______etc");
+        assert_eq!(dir_name("a/b\\c"), "a_b_c");
+        assert_eq!(dir_name(""), "user");
+    }
+
+    #[test]
+    fn page_titles_are_escaped() {
+        let page = page("<script>", ROOT, "");
+        assert!(page.contains("<title>&lt;script&gt;</title>"));
+        assert!(!page.contains("<title><script>"));
+    }
+
+    #[test]
+    fn tweets_are_rendered_escaped_with_line_breaks() {
+        let users = HashMap::new();
+        let site = Site {
+            users: &users,
+            archived_tweet_ids: HashSet::from([1]),
+        };
+        let html = site.tweet(&tweet_data(2, "<b>hi</b>\nthere", Some(1)), NESTED);
+        assert!(html.contains("<p>&lt;b&gt;hi&lt;/b&gt;<br>there</p>"));
+        assert!(html.contains("replied to <a href=\"../tweet/1.html\">1</a>"));
+        let html = site.tweet(&tweet_data(3, "hi", Some(9)), ROOT);
+        assert!(html.contains("https://twitter.com/i/web/status/9"));
+        assert!(html.contains("(not archived)"));
+    }
+
+    #[test]
+    fn replies_are_nested_under_the_tweet_they_reply_to() {
+        let users = HashMap::new();
+        let site = Site {
+            users: &users,
+            archived_tweet_ids: HashSet::from([1, 2, 3]),
+        };
+        let conversation = ConversationData {
+            id: 1,
+            tweets: vec![
+                tweet_data(1, "first", None),
+                tweet_data(2, "second", Some(1)),
+                tweet_data(3, "orphan", Some(99)),
+            ],
+            annotations: Vec::new(),
+        };
+        let html = site.thread(&conversation);
+        let first = html.find("first").unwrap();
+        let second = html.find("second").unwrap();
+        let orphan = html.find("orphan").unwrap();
+        assert!(first < second && second < orphan);
+        // The second tweet's thread closes inside the first's replies, and the orphan starts
+        // a thread of its own at the top level.
+        assert!(html[first..second].contains("<div class=\"replies\"><div class=\"thread\">"));
+        assert!(html[second..orphan]
+            .contains("<div class=\"replies\"></div></div></div></div><div class=\"thread\">"));
+    }
+}
@@ -0,0 +1,161 @@
+use rocket::State;
+use sea_orm::DatabaseConnection;
+
+use super::data;
+use crate::utils::{TextChange, TweetHistory, TweetVersionData};
+
+// The versions of a tweet with the changes between each of them. A tweet that has never
+// changed has the one version it was archived with. None when there is no such tweet or
+// it has been hidden.
+pub async fn tweet_history(db: &State<DatabaseConnection>, id: i64) -> Option<TweetHistory> {
+    let mut versions = data::read::tweet_versions(db, id).await;
+    if versions.is_empty() {
+        let tweet = data::read::tweet_by_id(db, id).await.tweet?;
+        versions.push(data::entities::tweet_versions::Model {
+            tweet_id: tweet.id,
+            version: 0,
+            edit_tweet_id: tweet.id,
+            content: tweet.content,
+            recorded_at: tweet.created_at,
+        });
+    }
+    let tweet_id = versions[0].tweet_id;
+    if data::read::hidden_tweet(db, tweet_id).await.is_some() {
+        return None;
+    }
+    let mut previous: Option<String> = None;
+    let versions = versions
+        .into_iter()
+        .map(|version| {
+            let changes = match &previous {
+                Some(previous) => diff_words(previous, &version.content),
+                None => Vec::new(),
+            };
+            previous = Some(version.content.clone());
+            TweetVersionData {
+                version: version.version,
+                edit_tweet_id: version.edit_tweet_id,
+                content: version.content,
+                recorded_at: version.recorded_at,
+                changes,
+            }
+        })
+        .collect();
+    Some(TweetHistory { tweet_id, versions })
+}
+
+// A word level diff from the longest common subsequence of the two texts' words, with runs
+// of the same kind of change joined back into one.
+fn diff_words(old: &str, new: &str) -> Vec<TextChange> {
+    let old: Vec<&str> = old.split_whitespace().collect();
+    let new: Vec<&str> = new.split_whitespace().collect();
+    // common[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes: Vec<TextChange> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        let change = if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+            TextChange::Kept(new[j - 1].to_string())
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            i += 1;
+            TextChange::Removed(old[i - 1].to_string())
+        } else {
+            j += 1;
+            TextChange::Added(new[j - 1].to_string())
+        };
+        push_change(&mut changes, change);
+    }
+    changes
+}
+
+fn push_change(changes: &mut Vec<TextChange>, change: TextChange) {
+    match (changes.last_mut(), change) {
+        (Some(TextChange::Kept(run)), TextChange::Kept(word))
+        | (Some(TextChange::Removed(run)), TextChange::Removed(word))
+        | (Some(TextChange::Added(run)), TextChange::Added(word)) => {
+            run.push(' ');
+            run.push_str(&word);
+        }
+        (_, change) => changes.push(change),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TextChange::{Added, Kept, Removed};
+
+    fn words(words: &str) -> String {
+        words.to_string()
+    }
+
+    #[test]
+    fn identical_texts_are_kept_whole() {
+        assert_eq!(
+            diff_words("the cat sat", "the  cat\nsat"),
+            vec![Kept(words("the cat sat"))]
+        );
+    }
+
+    #[test]
+    fn inserted_words_are_added() {
+        assert_eq!(
+            diff_words("the cat sat", "the black cat sat down"),
+            vec![
+                Kept(words("the")),
+                Added(words("black")),
+                Kept(words("cat sat")),
+                Added(words("down")),
+            ]
+        );
+    }
+
+    #[test]
+    fn deleted_words_are_removed() {
+        assert_eq!(
+            diff_words("the black cat sat down", "the cat sat"),
+            vec![
+                Kept(words("the")),
+                Removed(words("black")),
+                Kept(words("cat sat")),
+                Removed(words("down")),
+            ]
+        );
+    }
+
+    #[test]
+    fn replaced_words_are_removed_then_added() {
+        assert_eq!(
+            diff_words("the cat sat on the mat", "the dog lay on the mat"),
+            vec![
+                Kept(words("the")),
+                Removed(words("cat sat")),
+                Added(words("dog lay")),
+                Kept(words("on the mat")),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_texts_have_no_changes_or_only_one() {
+        assert_eq!(diff_words("", ""), vec![]);
+        assert_eq!(diff_words(" ", ""), vec![]);
+        assert_eq!(diff_words("", "new words"), vec![Added(words("new words"))]);
+        assert_eq!(
+            diff_words("old words", ""),
+            vec![Removed(words("old words"))]
+        );
+    }
+}
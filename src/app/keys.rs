@@ -0,0 +1,243 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{Duration, FixedOffset, Utc};
+use rand::RngCore;
+use rocket::State;
+use sea_orm::DatabaseConnection;
+use sha2::{Digest, Sha256};
+
+use super::data;
+use super::data::entities::api_keys;
+
+// Keys start with this so they're easy to spot in configs and logs.
+const KEY_PREFIX: &str = "bta_";
+// How many characters of a key, after KEY_PREFIX, are kept in the clear to tell keys apart.
+const SHOWN_CHARACTERS: usize = 8;
+// How stale a key's last use may get before it is written again, so a busy key doesn't
+// cost a write on every request.
+const LAST_USED_PRECISION_SECONDS: i64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    // Reading what is already archived, when the archive isn't public.
+    ReadArchive,
+    // Anything that may call the twitter api and spend its quota.
+    TriggerFetch,
+    // Making collections, tagging tweets and reading and writing annotations.
+    Curate,
+    // Everything, including changing what the server watches.
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ReadArchive => "read-archive",
+            Self::TriggerFetch => "trigger-fetch",
+            Self::Curate => "curate",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(scope: &str) -> Result<Self, Self::Err> {
+        match scope {
+            "read-archive" => Ok(Self::ReadArchive),
+            "trigger-fetch" => Ok(Self::TriggerFetch),
+            "curate" => Ok(Self::Curate),
+            "admin" => Ok(Self::Admin),
+            _ => Err(format!(
+                "Unknown scope {scope}, expected read-archive, trigger-fetch, curate or admin"
+            )),
+        }
+    }
+}
+
+// A comma separated list of scopes, like read-archive,trigger-fetch.
+pub fn parse_scopes(scopes: &str) -> Result<Vec<Scope>, String> {
+    let scopes: Vec<Scope> = scopes
+        .split(',')
+        .map(str::trim)
+        .filter(|scope| !scope.is_empty())
+        .map(str::parse)
+        .collect::<Result<_, _>>()?;
+    if scopes.is_empty() {
+        return Err("A key needs at least one scope".to_string());
+    }
+    Ok(scopes)
+}
+
+// Admin keys may do anything.
+pub fn has_scope(key: &api_keys::Model, scope: Scope) -> bool {
+    key.scopes
+        .split(',')
+        .any(|granted| granted == scope.as_str() || granted == Scope::Admin.as_str())
+}
+
+// Whether reads need a key with the read-archive scope. Otherwise only fetching from twitter
+// and admin routes do.
+pub fn reads_need_key() -> bool {
+    std::env::var("REQUIRE_API_KEY_FOR_READS")
+        .map(|value| value == "true" || value == "1")
+        .unwrap_or(false)
+}
+
+// Makes a new key and stores its hash. The key itself is only ever returned here. Names
+// are what annotations are put down to, so no two keys that aren't revoked share one.
+pub async fn create(
+    db: &State<DatabaseConnection>,
+    name: &str,
+    scopes: &[Scope],
+    requests_per_minute: Option<i32>,
+) -> Result<(String, api_keys::Model), String> {
+    if let Some(existing) = data::read::live_api_key_by_name(db, name).await {
+        return Err(format!(
+            "Key {} is already named {name}, revoke it first to replace it",
+            existing.id
+        ));
+    }
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let key = format!("{KEY_PREFIX}{}", hex::encode(bytes));
+    let mut scope_names: Vec<&str> = scopes.iter().map(Scope::as_str).collect();
+    scope_names.sort_unstable();
+    scope_names.dedup();
+    let model = data::write::api_key(
+        db,
+        &api_keys::Model {
+            id: 0,
+            name: name.to_owned(),
+            prefix: key[..KEY_PREFIX.len() + SHOWN_CHARACTERS].to_owned(),
+            key_hash: hash(&key),
+            scopes: scope_names.join(","),
+            requests_per_minute,
+            created_at: Utc::now().with_timezone(&FixedOffset::east(0)),
+            last_used_at: None,
+            revoked_at: None,
+        },
+    )
+    .await;
+    Ok((key, model))
+}
+
+// The stored key matching the one a client sent, unless it has been revoked. Marks it used,
+// to within LAST_USED_PRECISION_SECONDS.
+pub async fn find(db: &State<DatabaseConnection>, key: &str) -> Option<api_keys::Model> {
+    let model = data::read::api_key_by_hash(db, &hash(key))
+        .await
+        .filter(|model| model.revoked_at.is_none())?;
+    let now = Utc::now().with_timezone(&FixedOffset::east(0));
+    let stale = match model.last_used_at {
+        Some(last_used_at) => now - last_used_at >= Duration::seconds(LAST_USED_PRECISION_SECONDS),
+        None => true,
+    };
+    if stale {
+        data::write::api_key_used(db, model.id, now).await;
+    }
+    Some(model)
+}
+
+pub async fn list(db: &State<DatabaseConnection>) -> Vec<api_keys::Model> {
+    data::read::api_keys(db).await
+}
+
+pub async fn revoke(db: &State<DatabaseConnection>, id: i32) -> bool {
+    data::write::revoke_api_key(db, id, Utc::now().with_timezone(&FixedOffset::east(0))).await
+}
+
+fn hash(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_with_scopes(scopes: &str) -> api_keys::Model {
+        api_keys::Model {
+            id: 1,
+            name: "test".to_string(),
+            prefix: "bta_00000000".to_string(),
+            key_hash: hash("bta_0000000000"),
+            scopes: scopes.to_string(),
+            requests_per_minute: None,
+            created_at: Utc::now().with_timezone(&FixedOffset::east(0)),
+            last_used_at: None,
+            revoked_at: None,
+        }
+    }
+
+    #[test]
+    fn scopes_parse_from_a_comma_separated_list() {
+        assert_eq!(
+            parse_scopes("read-archive, trigger-fetch,,curate"),
+            Ok(vec![Scope::ReadArchive, Scope::TriggerFetch, Scope::Curate])
+        );
+        assert_eq!(parse_scopes("admin"), Ok(vec![Scope::Admin]));
+    }
+
+    #[test]
+    fn unknown_or_missing_scopes_are_rejected() {
+        assert!(parse_scopes("read-archive,write").is_err());
+        assert!(parse_scopes("Admin").is_err());
+        assert!(parse_scopes("").is_err());
+        assert!(parse_scopes(" , ").is_err());
+    }
+
+    #[test]
+    fn scope_names_round_trip() {
+        for scope in [
+            Scope::ReadArchive,
+            Scope::TriggerFetch,
+            Scope::Curate,
+            Scope::Admin,
+        ] {
+            assert_eq!(scope.to_string().parse(), Ok(scope));
+        }
+    }
+
+    #[test]
+    fn keys_have_only_their_scopes_unless_they_are_admin() {
+        let key = key_with_scopes("curate,read-archive");
+        assert!(has_scope(&key, Scope::ReadArchive));
+        assert!(has_scope(&key, Scope::Curate));
+        assert!(!has_scope(&key, Scope::TriggerFetch));
+        assert!(!has_scope(&key, Scope::Admin));
+        let admin = key_with_scopes("admin");
+        assert!(has_scope(&admin, Scope::TriggerFetch));
+    }
+
+    #[test]
+    fn keys_are_stored_as_their_hash() {
+        assert_eq!(hash("bta_a"), hash("bta_a"));
+        assert_ne!(hash("bta_a"), hash("bta_b"));
+        assert_eq!(hash("bta_a").len(), 64);
+    }
+
+    #[tokio::test]
+    async fn names_are_only_reused_once_the_key_is_revoked() {
+        let db = crate::app::data::setup::test_db("api-key-names").await;
+        let state = State::from(&db);
+        let (_key, first) = create(state, "alice", &[Scope::Curate], None)
+            .await
+            .unwrap();
+        assert!(create(state, "alice", &[Scope::Admin], None).await.is_err());
+        assert!(create(state, "bob", &[Scope::Curate], None).await.is_ok());
+
+        assert!(revoke(state, first.id).await);
+        let (_key, replacement) = create(state, "alice", &[Scope::Curate], None)
+            .await
+            .unwrap();
+        assert_ne!(replacement.id, first.id);
+    }
+}
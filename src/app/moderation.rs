@@ -0,0 +1,357 @@
+use std::fs;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use rocket::State;
+use sea_orm::DatabaseConnection;
+
+use super::data;
+use super::data::entities::{audit_log, hidden_tweets, redacted_users};
+use crate::utils::RedactionReport;
+
+// Who changes made by the redaction list are put down to in the audit log.
+pub const REDACTION_LIST_ACTOR: &str = "redaction list";
+
+// A user to redact, as given by an admin or the redaction list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RedactionTarget {
+    Id(i64),
+    Handle(String),
+}
+
+impl RedactionTarget {
+    // `@handle` or a numeric user id.
+    pub fn parse(target: &str) -> Result<Self, String> {
+        match target.strip_prefix('@') {
+            Some("") => Err("Expected a handle after @".to_string()),
+            Some(handle) => Ok(Self::Handle(handle.to_owned())),
+            None => target
+                .parse()
+                .map(Self::Id)
+                .map_err(|_| format!("Expected @handle or a user id, got {target}")),
+        }
+    }
+}
+
+pub async fn hidden_tweet(
+    db: &State<DatabaseConnection>,
+    id: i64,
+) -> Option<hidden_tweets::Model> {
+    data::read::hidden_tweet(db, id).await
+}
+
+pub async fn hidden_tweets(db: &State<DatabaseConnection>) -> Vec<hidden_tweets::Model> {
+    data::read::hidden_tweets(db).await
+}
+
+// Takes the tweet out of everything served to clients. It stays archived, and a tweet
+// that isn't archived yet is hidden as soon as it is.
+pub async fn hide_tweet(
+    db: &State<DatabaseConnection>,
+    id: i64,
+    reason: &str,
+    actor: &str,
+) -> hidden_tweets::Model {
+    let hidden = hidden_tweets::Model {
+        tweet_id: id,
+        reason: reason.to_owned(),
+        hidden_at: now(),
+    };
+    data::write::hidden_tweet(db, &hidden).await;
+    let detail = (!data::read::does_tweet_exist(db, id).await).then(|| "not archived".to_string());
+    audit(db, "hide_tweet", &format!("tweet {id}"), Some(reason), actor, detail).await;
+    hidden
+}
+
+// Returns whether the tweet was hidden.
+pub async fn unhide_tweet(db: &State<DatabaseConnection>, id: i64, actor: &str) -> bool {
+    let unhidden = data::write::remove_hidden_tweet(db, id).await;
+    if unhidden {
+        audit(db, "unhide_tweet", &format!("tweet {id}"), None, actor, None).await;
+    }
+    unhidden
+}
+
+// Deletes the tweet with its versions, its embedding, its status and every reference to
+// or from it. Replies and quotes of it stay, without their reference to it.
+// The tweet is also hidden, so it isn't served again if it's ever fetched again. Returns
+// whether it was archived.
+pub async fn delete_tweet(
+    db: &State<DatabaseConnection>,
+    id: i64,
+    reason: &str,
+    actor: &str,
+) -> bool {
+    let archived = data::read::does_tweet_exist(db, id).await;
+    let hidden = hidden_tweets::Model {
+        tweet_id: id,
+        reason: reason.to_owned(),
+        hidden_at: now(),
+    };
+    let detail = (!archived).then(|| "not archived".to_string());
+    let entry = audit_entry("delete_tweet", &format!("tweet {id}"), Some(reason), actor, detail);
+    data::write::deleted_tweet(db, &hidden, &entry).await;
+    archived
+}
+
+// Removes the user, every tweet they wrote and their place on the watchlist, and keeps
+// them from being archived again, all in one transaction. Handles are matched in any case.
+// A handle that isn't archived is redacted by name until the user turns up.
+pub async fn redact_user(
+    db: &State<DatabaseConnection>,
+    target: &RedactionTarget,
+    reason: &str,
+    actor: &str,
+) -> RedactionReport {
+    let user = match target {
+        RedactionTarget::Id(id) => data::read::user_by_id(db, *id).await.user,
+        RedactionTarget::Handle(handle) => data::read::user_by_handle_in_any_case(db, handle).await,
+    };
+    let (user_id, username) = match (&user, target) {
+        (Some(user), _target) => (Some(user.id), Some(user.username.to_lowercase())),
+        (None, RedactionTarget::Id(id)) => (Some(*id), None),
+        (None, RedactionTarget::Handle(handle)) => (None, Some(handle.to_lowercase())),
+    };
+    let existing = data::read::redaction(db, user_id, username.as_deref()).await;
+    let is_new = existing.is_none();
+    let redaction = match existing {
+        Some(redaction) => redacted_users::Model {
+            user_id: redaction.user_id.or(user_id),
+            ..redaction
+        },
+        None => redacted_users::Model {
+            id: 0,
+            user_id,
+            username: username.clone(),
+            reason: reason.to_owned(),
+            redacted_at: now(),
+        },
+    };
+    let tweet_ids = match user_id {
+        Some(user_id) => data::read::users_tweet_ids(db, user_id).await,
+        None => Vec::new(),
+    };
+
+    data::write::redaction(db, &redaction, username.as_deref(), &tweet_ids, |report| {
+        // Applying the list again on every start only shows up in the log when it does
+        // something.
+        if !is_new && !report.user_removed && report.tweets_removed == 0 && !report.unwatched {
+            return None;
+        }
+        let target = match (&username, user_id) {
+            (Some(username), Some(user_id)) => format!("user @{username} ({user_id})"),
+            (Some(username), None) => format!("user @{username}"),
+            (None, Some(user_id)) => format!("user {user_id}"),
+            (None, None) => unreachable!("A redaction always has a handle or an id"),
+        };
+        let detail = format!(
+            "removed {} tweets{}{}",
+            report.tweets_removed,
+            if report.user_removed { " and the user" } else { "" },
+            if report.unwatched { ", unwatched" } else { "" },
+        );
+        Some(audit_entry("redact_user", &target, Some(reason), actor, Some(detail)))
+    })
+    .await
+}
+
+// Redacts everyone on the list at `path`: one `@handle` or user id per line, optionally
+// followed by the reason. Blank lines and lines starting with # are skipped. Users that
+// are already redacted are checked again, which removes anything that has crept back in
+// through a restore. Returns how many lines were applied.
+pub async fn apply_redaction_list(db: &State<DatabaseConnection>, path: &str) -> usize {
+    let list = fs::read_to_string(path)
+        .unwrap_or_else(|error| panic!("Failed to read redaction list {path}. Error: {:?}", error));
+    let mut applied = 0;
+    for (i, line) in list.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (target, reason) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let target = RedactionTarget::parse(target).unwrap_or_else(|error| {
+            panic!("Failed to parse line {} of {path}. Error: {error}", i + 1)
+        });
+        let reason = match reason.trim() {
+            "" => "on the redaction list",
+            reason => reason,
+        };
+        let report = redact_user(db, &target, reason, REDACTION_LIST_ACTOR).await;
+        if report.user_removed || report.tweets_removed > 0 {
+            println!(
+                "Redacted {:?}, removing {} tweets",
+                target, report.tweets_removed
+            );
+        }
+        applied += 1;
+    }
+    applied
+}
+
+pub async fn audit_log(db: &State<DatabaseConnection>, limit: u64) -> Vec<audit_log::Model> {
+    data::read::audit_log(db, limit).await
+}
+
+async fn audit(
+    db: &State<DatabaseConnection>,
+    action: &str,
+    target: &str,
+    reason: Option<&str>,
+    actor: &str,
+    detail: Option<String>,
+) {
+    data::write::audit_entry(db, &audit_entry(action, target, reason, actor, detail)).await;
+}
+
+fn audit_entry(
+    action: &str,
+    target: &str,
+    reason: Option<&str>,
+    actor: &str,
+    detail: Option<String>,
+) -> audit_log::Model {
+    println!("Audit: {actor} did {action} on {target}");
+    audit_log::Model {
+        id: 0,
+        action: action.to_owned(),
+        target: target.to_owned(),
+        reason: reason.map(str::to_owned),
+        actor: actor.to_owned(),
+        detail,
+        at: now(),
+    }
+}
+
+fn now() -> DateTime<FixedOffset> {
+    Utc::now().with_timezone(&FixedOffset::east(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::data::entities::{conversations, tweets, users, watched_accounts};
+    use crate::app::data::setup;
+    use crate::app::data::write::DumpedRows;
+
+    // Yuda, as @YudaPearl on twitter but stored in lower case, wrote tweets 1 and 2 and is
+    // watched. Someone else wrote tweet 3.
+    async fn archive(name: &str) -> DatabaseConnection {
+        let db = setup::test_db(name).await;
+        let state = State::from(&db);
+        let user = |id: i64, username: &str| users::Model {
+            id,
+            name: username.to_owned(),
+            username: username.to_owned(),
+            description: String::new(),
+        };
+        let tweet = |id: i64, author_id: i64| tweets::Model {
+            id,
+            content: format!("Tweet {id}"),
+            author_id,
+            conversation_id: 1,
+            created_at: now(),
+        };
+        data::write::dumped_rows(
+            state,
+            DumpedRows {
+                users: vec![user(10, "yudapearl"), user(20, "someone")],
+                conversations: vec![conversations::Model { id: 1 }],
+                tweets: vec![tweet(1, 10), tweet(2, 10), tweet(3, 20)],
+                references: Vec::new(),
+            },
+        )
+        .await;
+        data::write::watched_account(
+            state,
+            &watched_accounts::Model {
+                username: "YudaPearl".to_string(),
+                interval_seconds: 3600,
+                last_synced_at: None,
+                last_seen_tweet_id: None,
+                last_error: None,
+            },
+        )
+        .await;
+        db
+    }
+
+    fn actions(entries: &[audit_log::Model]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.action.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn hidden_tweets_stay_archived_until_unhidden() {
+        let db = archive("hide-tweet").await;
+        let state = State::from(&db);
+        hide_tweet(state, 1, "Asked to", "admin").await;
+        assert!(hidden_tweet(state, 1).await.is_some());
+        assert!(data::read::does_tweet_exist(state, 1).await);
+        assert_eq!(data::read::visible_tweet_by_id(state, 1).await.tweet, None);
+
+        assert!(unhide_tweet(state, 1, "admin").await);
+        assert!(!unhide_tweet(state, 1, "admin").await);
+        assert!(hidden_tweet(state, 1).await.is_none());
+        assert!(data::read::visible_tweet_by_id(state, 1).await.tweet.is_some());
+        // Unhiding a tweet that wasn't hidden isn't logged.
+        let log = audit_log(state, 10).await;
+        let mut logged = actions(&log);
+        logged.sort_unstable();
+        assert_eq!(logged, vec!["hide_tweet", "unhide_tweet"]);
+    }
+
+    #[tokio::test]
+    async fn deleted_tweets_are_gone_and_stay_hidden() {
+        let db = archive("delete-tweet").await;
+        let state = State::from(&db);
+        assert!(delete_tweet(state, 1, "Asked to", "admin").await);
+        assert!(!data::read::does_tweet_exist(state, 1).await);
+        assert!(hidden_tweet(state, 1).await.is_some());
+        assert!(data::read::does_tweet_exist(state, 2).await);
+
+        // Deleting a tweet that was never archived still keeps it out.
+        assert!(!delete_tweet(state, 99, "Asked to", "admin").await);
+        assert!(hidden_tweet(state, 99).await.is_some());
+        let log = audit_log(state, 10).await;
+        assert_eq!(actions(&log), vec!["delete_tweet", "delete_tweet"]);
+        assert!(log
+            .iter()
+            .any(|entry| entry.detail.as_deref() == Some("not archived")));
+    }
+
+    #[tokio::test]
+    async fn redacting_a_handle_in_another_case_removes_everything() {
+        let db = archive("redact-user").await;
+        let state = State::from(&db);
+        let target = RedactionTarget::parse("@YudaPearl").unwrap();
+        let report = redact_user(state, &target, "Asked to", "admin").await;
+        assert_eq!(report.redaction.user_id, Some(10));
+        assert_eq!(report.redaction.username.as_deref(), Some("yudapearl"));
+        assert!(report.user_removed);
+        assert_eq!(report.tweets_removed, 2);
+        assert!(report.unwatched);
+
+        assert!(data::read::user_by_id(state, 10).await.user.is_none());
+        assert!(!data::read::does_tweet_exist(state, 1).await);
+        assert!(!data::read::does_tweet_exist(state, 2).await);
+        assert!(data::read::does_tweet_exist(state, 3).await);
+        assert!(data::read::watched_accounts(state).await.is_empty());
+        assert_eq!(actions(&audit_log(state, 10).await), vec!["redact_user"]);
+
+        // Applying it again finds nothing left and isn't logged.
+        let again = redact_user(state, &target, "Asked to", "admin").await;
+        assert_eq!(again.redaction.id, report.redaction.id);
+        assert!(!again.user_removed && again.tweets_removed == 0 && !again.unwatched);
+        assert_eq!(audit_log(state, 10).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn handles_that_arent_archived_are_redacted_by_name() {
+        let db = archive("redact-handle").await;
+        let state = State::from(&db);
+        let target = RedactionTarget::parse("@Nobody").unwrap();
+        let report = redact_user(state, &target, "Asked to", "admin").await;
+        assert_eq!(report.redaction.user_id, None);
+        assert_eq!(report.redaction.username.as_deref(), Some("nobody"));
+        assert!(!report.user_removed && report.tweets_removed == 0);
+        assert!(data::read::redaction(state, None, Some("NOBODY")).await.is_some());
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use rocket::State;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+
+use super::data;
+use super::data::entities::prelude::*;
+use super::data::entities::users;
+use crate::utils::{Community, GraphNode, InteractionEdge, InteractionGraph, Interlocutor};
+
+pub const DEFAULT_INTERLOCUTORS: usize = 20;
+
+// Label propagation settles in a handful of rounds on real graphs; this only stops it
+// from going back and forth forever on ties.
+const MAX_LABEL_ROUNDS: usize = 100;
+
+pub async fn interaction_graph(db: &State<DatabaseConnection>) -> InteractionGraph {
+    let edges = data::read::interaction_edges(db, None).await;
+    let user_ids: BTreeSet<i64> = edges
+        .iter()
+        .flat_map(|edge| [edge.source_user_id, edge.target_user_id])
+        .collect();
+    let usernames = usernames(db, &user_ids).await;
+    let communities = label_communities(&user_ids, &edges);
+    InteractionGraph {
+        nodes: user_ids
+            .iter()
+            .map(|user_id| GraphNode {
+                user_id: *user_id,
+                username: usernames.get(user_id).cloned(),
+                community: communities[user_id],
+            })
+            .collect(),
+        edges,
+    }
+}
+
+// The users `user_id` has replied to, quoted, or been replied to or quoted by, busiest
+// first.
+pub async fn top_interlocutors(
+    db: &State<DatabaseConnection>,
+    user_id: i64,
+    limit: usize,
+) -> Vec<Interlocutor> {
+    let mut interlocutors: HashMap<i64, Interlocutor> = HashMap::new();
+    for edge in data::read::interaction_edges(db, Some(user_id)).await {
+        let outgoing = edge.source_user_id == user_id;
+        let other_id = if outgoing {
+            edge.target_user_id
+        } else {
+            edge.source_user_id
+        };
+        let interlocutor = interlocutors
+            .entry(other_id)
+            .or_insert_with(|| Interlocutor {
+                user_id: other_id,
+                username: None,
+                replies_to: 0,
+                replies_from: 0,
+                quotes_to: 0,
+                quotes_from: 0,
+                total: 0,
+                first_at: edge.first_at.clone(),
+                last_at: edge.last_at.clone(),
+            });
+        if outgoing {
+            interlocutor.replies_to += edge.replies;
+            interlocutor.quotes_to += edge.quotes;
+        } else {
+            interlocutor.replies_from += edge.replies;
+            interlocutor.quotes_from += edge.quotes;
+        }
+        interlocutor.total += edge.weight;
+        interlocutor.first_at = interlocutor.first_at.clone().min(edge.first_at);
+        interlocutor.last_at = interlocutor.last_at.clone().max(edge.last_at);
+    }
+    let mut interlocutors: Vec<Interlocutor> = interlocutors.into_values().collect();
+    interlocutors.sort_by_key(|interlocutor| (-interlocutor.total, interlocutor.user_id));
+    interlocutors.truncate(limit);
+    let usernames = usernames(
+        db,
+        &interlocutors
+            .iter()
+            .map(|interlocutor| interlocutor.user_id)
+            .collect(),
+    )
+    .await;
+    for interlocutor in interlocutors.iter_mut() {
+        interlocutor.username = usernames.get(&interlocutor.user_id).cloned();
+    }
+    interlocutors
+}
+
+// Groups of users who mostly talk among themselves, largest first.
+pub async fn communities(db: &State<DatabaseConnection>) -> Vec<Community> {
+    let graph = interaction_graph(db).await;
+    let mut communities: BTreeMap<i64, Community> = BTreeMap::new();
+    for node in graph.nodes.iter() {
+        communities
+            .entry(node.community)
+            .or_insert_with(|| Community {
+                id: node.community,
+                members: Vec::new(),
+                internal_weight: 0,
+            })
+            .members
+            .push(node.clone());
+    }
+    let community_of: HashMap<i64, i64> = graph
+        .nodes
+        .iter()
+        .map(|node| (node.user_id, node.community))
+        .collect();
+    for edge in graph.edges.iter() {
+        let community = community_of[&edge.source_user_id];
+        if community == community_of[&edge.target_user_id] {
+            if let Some(community) = communities.get_mut(&community) {
+                community.internal_weight += edge.weight;
+            }
+        }
+    }
+    let mut communities: Vec<Community> = communities.into_values().collect();
+    communities.sort_by_key(|community| (-(community.members.len() as i64), community.id));
+    communities
+}
+
+// Weighted label propagation over the graph with directions ignored. Every user starts
+// in a community of their own and repeatedly joins the one they interact with most,
+// going through users in id order and breaking ties towards the lowest label so the
+// result is the same every time. Communities are labelled with a member's user id.
+fn label_communities(user_ids: &BTreeSet<i64>, edges: &[InteractionEdge]) -> HashMap<i64, i64> {
+    let mut neighbours: HashMap<i64, Vec<(i64, i64)>> = HashMap::new();
+    for edge in edges {
+        neighbours
+            .entry(edge.source_user_id)
+            .or_default()
+            .push((edge.target_user_id, edge.weight));
+        neighbours
+            .entry(edge.target_user_id)
+            .or_default()
+            .push((edge.source_user_id, edge.weight));
+    }
+    let mut labels: HashMap<i64, i64> = user_ids.iter().map(|id| (*id, *id)).collect();
+    for _round in 0..MAX_LABEL_ROUNDS {
+        let mut changed = false;
+        for user_id in user_ids {
+            let mut weights: BTreeMap<i64, i64> = BTreeMap::new();
+            for (neighbour, weight) in neighbours.get(user_id).into_iter().flatten() {
+                *weights.entry(labels[neighbour]).or_default() += weight;
+            }
+            let best = weights
+                .iter()
+                .max_by_key(|(label, weight)| (**weight, -**label))
+                .map(|(label, _weight)| *label);
+            if let Some(best) = best {
+                if best != labels[user_id] {
+                    labels.insert(*user_id, best);
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    labels
+}
+
+async fn usernames(
+    db: &State<DatabaseConnection>,
+    user_ids: &BTreeSet<i64>,
+) -> HashMap<i64, String> {
+    let ids: Vec<i64> = user_ids.iter().copied().collect();
+    let mut usernames = HashMap::new();
+    for chunk in ids.chunks(crate::utils::MAX_QUERY_PARAMETERS) {
+        let users: Vec<users::Model> = Users::find()
+            .filter(users::Column::Id.is_in(chunk.to_vec()))
+            .all(db as &DatabaseConnection)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to get users from database. Error: {:?}", error)
+            });
+        usernames.extend(users.into_iter().map(|user| (user.id, user.username)));
+    }
+    usernames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(source_user_id: i64, target_user_id: i64, weight: i64) -> InteractionEdge {
+        InteractionEdge {
+            source_user_id,
+            target_user_id,
+            replies: weight,
+            quotes: 0,
+            weight,
+            first_at: "2020-01-01T00:00:00+00:00".to_string(),
+            last_at: "2020-01-01T00:00:00+00:00".to_string(),
+        }
+    }
+
+    // Two close groups of three joined by one reply, and a user who talks to no one.
+    fn two_groups() -> (BTreeSet<i64>, Vec<InteractionEdge>) {
+        let user_ids = (1..=7).collect();
+        let edges = vec![
+            edge(1, 2, 5),
+            edge(2, 3, 5),
+            edge(3, 1, 5),
+            edge(4, 5, 5),
+            edge(5, 6, 5),
+            edge(6, 4, 5),
+            edge(3, 4, 1),
+        ];
+        (user_ids, edges)
+    }
+
+    #[test]
+    fn close_groups_share_a_label_and_loners_keep_their_own() {
+        let (user_ids, edges) = two_groups();
+        let labels = label_communities(&user_ids, &edges);
+        let label = |id: i64| labels[&id];
+        assert_eq!(labels.len(), 7);
+        assert!([1, 2, 3].iter().all(|id| label(*id) == label(1)));
+        assert!([4, 5, 6].iter().all(|id| label(*id) == label(4)));
+        assert_ne!(label(1), label(4));
+        assert_eq!(label(7), 7);
+        assert!([1, 2, 3].contains(&label(1)));
+        assert!([4, 5, 6].contains(&label(4)));
+    }
+
+    #[test]
+    fn labels_are_the_same_every_time() {
+        let (user_ids, edges) = two_groups();
+        let labels = label_communities(&user_ids, &edges);
+        let mut reversed = edges.clone();
+        reversed.reverse();
+        assert_eq!(label_communities(&user_ids, &reversed), labels);
+        assert_eq!(labels[&1], 2);
+        assert_eq!(labels[&4], 5);
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use chrono::{DateTime, FixedOffset};
+use rocket::State;
+use sea_orm::DatabaseConnection;
+
+use super::api;
+use super::data;
+use super::data::entities::{tweet_status, users};
+use super::export::dump::{self, Record};
+use crate::utils::{i64_to_u64, u64_to_i64, RepairReport, TweetData, TweetStatusKind, UserData};
+
+// Where repair looks for the rows that are missing from the archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepairSource {
+    // The twitter api, which is skipped in offline mode.
+    Api,
+    // An ndjson file written by the dump command, such as an older backup.
+    Dump(String),
+}
+
+// Fixes what `data::read::verify` finds: missing authors, conversations and referenced
+// tweets are fetched from the source, tweets with unreadable dates are fetched again, and
+// whatever can't be fixed that way is pruned. Duplicates are only reported, since they
+// can't be told apart automatically.
+pub async fn repair(db: &State<DatabaseConnection>, source: &RepairSource) -> RepairReport {
+    let before = data::read::verify(db).await;
+    let source = Source::load(source);
+
+    // A reference is stored against the tweet it comes from, so without it there's nothing
+    // left to repair.
+    let pruned_references: Vec<i64> = before
+        .references_missing_source_tweet
+        .iter()
+        .map(|reference| reference.source_tweet_id)
+        .collect();
+    data::write::remove_tweet_references(db, &pruned_references).await;
+
+    let mut redated_tweets = Vec::new();
+    let mut pruned_tweets = Vec::new();
+    for malformed in before.malformed_dates.iter() {
+        match source.tweet(malformed.tweet_id).await.tweet {
+            Some(tweet) => {
+                data::write::tweet_created_at(db, tweet.id, tweet.created_at).await;
+                redated_tweets.push(tweet.id);
+            }
+            None => pruned_tweets.push(malformed.tweet_id),
+        }
+    }
+    data::write::remove_tweets(db, &pruned_tweets).await;
+
+    // Tweets pruned above no longer need an author or a conversation.
+    let remaining = |tweet_ids: &[i64]| -> Vec<i64> {
+        tweet_ids
+            .iter()
+            .copied()
+            .filter(|id| !pruned_tweets.contains(id))
+            .collect()
+    };
+    let mut fetched_users = Vec::new();
+    for author_id in
+        data::read::authors_of_tweets(db, &remaining(&before.tweets_missing_author)).await
+    {
+        if let Some(user) = source.user(author_id).await {
+            UserData { user: Some(user) }.write(db).await;
+            fetched_users.push(author_id);
+        }
+    }
+
+    let created_conversations =
+        data::read::conversations_of_tweets(db, &remaining(&before.tweets_missing_conversation))
+            .await;
+    for conversation_id in created_conversations.iter() {
+        data::write::conversation(db, conversation_id).await;
+    }
+
+    let missing_tweet_ids: BTreeSet<i64> = before
+        .references_missing_tweet
+        .iter()
+        .map(|reference| reference.referenced_tweet_id)
+        .collect();
+    let fetched = source.tweets(db, &missing_tweet_ids).await;
+    for tweet in fetched.iter().filter_map(|tweet_data| tweet_data.tweet.as_ref()) {
+        let author_is_archived = data::read::user_by_id(db, tweet.author_id)
+            .await
+            .user
+            .is_some();
+        if let Some(author) = source
+            .archived_user(tweet.author_id)
+            .filter(|_author| !author_is_archived)
+        {
+            UserData { user: Some(author) }.write(db).await;
+        }
+    }
+    data::write::tweets(db, &fetched).await;
+    let fetched_tweets = fetched
+        .iter()
+        .filter_map(|tweet_data| tweet_data.tweet.as_ref().map(|tweet| tweet.id))
+        .collect();
+
+    // Pruning tweets can leave conversations empty, so these are found last.
+    let pruned_conversations = data::read::verify(db).await.conversations_without_tweets;
+    data::write::remove_conversations(db, &pruned_conversations).await;
+
+    RepairReport {
+        before,
+        fetched_users,
+        fetched_tweets,
+        created_conversations,
+        redated_tweets,
+        pruned_tweets,
+        pruned_references,
+        pruned_conversations,
+        after: data::read::verify(db).await,
+    }
+}
+
+enum Source {
+    Api,
+    Dump {
+        users: HashMap<i64, users::Model>,
+        tweets: HashMap<i64, TweetData>,
+    },
+}
+
+impl Source {
+    fn load(source: &RepairSource) -> Self {
+        match source {
+            RepairSource::Api => Self::Api,
+            RepairSource::Dump(path) => {
+                let mut users = HashMap::new();
+                let mut tweets = HashMap::new();
+                for record in dump::read_ndjson(path) {
+                    match record {
+                        Record::User(UserData { user: Some(user) }) => {
+                            users.insert(user.id, user);
+                        }
+                        Record::Tweet(tweet_data) => {
+                            if let Some(tweet) = &tweet_data.tweet {
+                                tweets.insert(tweet.id, tweet_data);
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+                Self::Dump { users, tweets }
+            }
+        }
+    }
+
+    async fn user(&self, id: i64) -> Option<users::Model> {
+        match self {
+            Self::Api if api::is_offline() => None,
+            Self::Api => api::get_user_by_id(i64_to_u64(id)).await.user,
+            Self::Dump { .. } => self.archived_user(id),
+        }
+    }
+
+    async fn tweet(&self, id: i64) -> TweetData {
+        match self {
+            Self::Api if api::is_offline() => TweetData::empty(),
+            Self::Api => api::get_tweet_by_id(i64_to_u64(id)).await,
+            Self::Dump { tweets, .. } => tweets.get(&id).cloned().unwrap_or_else(TweetData::empty),
+        }
+    }
+
+    // The tweets the source has out of `ids`. The api is asked 100 at a time, skipping
+    // tweets already known to be gone, and the ones it doesn't return get a tombstone so
+    // the next repair doesn't ask for them again.
+    async fn tweets(&self, db: &State<DatabaseConnection>, ids: &BTreeSet<i64>) -> Vec<TweetData> {
+        match self {
+            Self::Api if api::is_offline() => Vec::new(),
+            Self::Api => {
+                let mut unknown = Vec::new();
+                for id in ids.iter().copied() {
+                    if super::load_known_unavailable_status(db, id).await.is_none() {
+                        unknown.push(i64_to_u64(id));
+                    }
+                }
+                let mut fetched = Vec::new();
+                for chunk in unknown.chunks(100) {
+                    let (available, errors) = api::get_tweets_by_ids_with_errors(chunk).await;
+                    let available_ids: HashSet<u64> = available
+                        .iter()
+                        .filter_map(|tweet_data| tweet_data.tweet.as_ref())
+                        .map(|tweet| i64_to_u64(tweet.id))
+                        .collect();
+                    let checked_at: DateTime<FixedOffset> = chrono::Utc::now().into();
+                    let statuses: Vec<tweet_status::Model> = chunk
+                        .iter()
+                        .map(|id| {
+                            let (kind, detail) = if available_ids.contains(id) {
+                                (TweetStatusKind::Available, None)
+                            } else {
+                                api::tweet_status_from_errors(*id, &errors, false)
+                            };
+                            tweet_status::Model {
+                                tweet_id: u64_to_i64(*id),
+                                status: kind.as_str().to_string(),
+                                detail,
+                                checked_at,
+                            }
+                        })
+                        .collect();
+                    data::write::tweet_statuses(db, &statuses).await;
+                    fetched.extend(available);
+                }
+                fetched
+            }
+            Self::Dump { tweets, .. } => {
+                ids.iter().filter_map(|id| tweets.get(id).cloned()).collect()
+            }
+        }
+    }
+
+    // Users that come with the source itself. The api has none, since writing a tweet
+    // fetches its author anyway.
+    fn archived_user(&self, id: i64) -> Option<users::Model> {
+        match self {
+            Self::Api => None,
+            Self::Dump { users, .. } => users.get(&id).cloned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::data::entities::prelude::*;
+    use crate::app::data::entities::{conversations, tweet_references, tweets};
+    use crate::app::data::setup;
+    use crate::app::data::write::DumpedRows;
+    use chrono::DateTime;
+    use sea_orm::{ConnectionTrait, EntityTrait, Statement};
+    use std::io::Write;
+
+    fn user(id: i64) -> users::Model {
+        users::Model {
+            id,
+            name: format!("User {id}"),
+            username: format!("user{id}"),
+            description: String::new(),
+        }
+    }
+
+    fn tweet(id: i64, author_id: i64, conversation_id: i64) -> tweets::Model {
+        tweets::Model {
+            id,
+            content: format!("Tweet {id}"),
+            author_id,
+            conversation_id,
+            created_at: DateTime::parse_from_rfc3339("2020-01-01T00:00:00+00:00").unwrap(),
+        }
+    }
+
+    fn reference(source: i64, referenced: i64) -> tweet_references::Model {
+        tweet_references::Model {
+            source_tweet_id: source,
+            reference_type: "replied_to".to_string(),
+            referenced_tweet_id: referenced,
+        }
+    }
+
+    // Tweet 11's author and conversation aren't archived and neither is the tweet it
+    // replies to, 98 replies to 10 but isn't archived itself, nothing is in conversation 30
+    // and 12 and 13 have dates that can't be read.
+    async fn broken_archive() -> DatabaseConnection {
+        let db = setup::test_db("repair").await;
+        data::write::dumped_rows(
+            State::from(&db),
+            DumpedRows {
+                users: vec![user(1)],
+                conversations: vec![
+                    conversations::Model { id: 10 },
+                    conversations::Model { id: 30 },
+                ],
+                tweets: vec![
+                    tweet(10, 1, 10),
+                    tweet(11, 2, 20),
+                    tweet(12, 1, 10),
+                    tweet(13, 1, 10),
+                ],
+                references: vec![reference(11, 99), reference(98, 10)],
+            },
+        )
+        .await;
+        db.execute(Statement::from_string(
+            db.get_database_backend(),
+            "UPDATE tweets SET created_at = 'last tuesday' WHERE id IN (12, 13)".to_owned(),
+        ))
+        .await
+        .unwrap();
+        db
+    }
+
+    // An older backup with tweet 11's author, the tweet it replies to and its author, and
+    // tweet 12 as it was before its date was mangled.
+    fn backup() -> String {
+        let path = std::env::temp_dir().join(format!(
+            "better-twitter-archiver-repair-{}.ndjson",
+            std::process::id()
+        ));
+        let mut file = std::fs::File::create(&path).unwrap();
+        for record in [
+            Record::User(UserData {
+                user: Some(user(2)),
+            }),
+            Record::User(UserData {
+                user: Some(user(3)),
+            }),
+            Record::Tweet(TweetData::new(tweet(99, 3, 10), Vec::new())),
+            Record::Tweet(TweetData::new(tweet(12, 1, 10), Vec::new())),
+        ] {
+            writeln!(file, "{}", serde_json::to_string(&record).unwrap()).unwrap();
+        }
+        path.to_str().unwrap().to_owned()
+    }
+
+    #[tokio::test]
+    async fn repair_fixes_what_verify_finds_from_a_dump_and_prunes_the_rest() {
+        let db = broken_archive().await;
+        let state = State::from(&db);
+        let before = data::read::verify(state).await;
+        assert_eq!(before.tweets_missing_author, vec![11]);
+        assert_eq!(before.tweets_missing_conversation, vec![11]);
+        assert_eq!(
+            before.references_missing_source_tweet,
+            vec![reference(98, 10)]
+        );
+        assert_eq!(before.references_missing_tweet, vec![reference(11, 99)]);
+        assert_eq!(before.conversations_without_tweets, vec![30]);
+        let malformed: Vec<i64> = before
+            .malformed_dates
+            .iter()
+            .map(|date| date.tweet_id)
+            .collect();
+        assert_eq!(malformed, vec![12, 13]);
+        assert!(!before.is_ok());
+
+        let report = repair(state, &RepairSource::Dump(backup())).await;
+        assert_eq!(report.pruned_references, vec![98]);
+        assert_eq!(report.redated_tweets, vec![12]);
+        assert_eq!(report.pruned_tweets, vec![13]);
+        assert_eq!(report.fetched_users, vec![2]);
+        assert_eq!(report.created_conversations, vec![20]);
+        assert_eq!(report.fetched_tweets, vec![99]);
+        assert_eq!(report.pruned_conversations, vec![30]);
+        assert!(report.after.is_ok(), "{:?}", report.after);
+
+        let redated = Tweets::find_by_id(12).one(&db).await.unwrap().unwrap();
+        assert_eq!(redated.created_at, tweet(12, 1, 10).created_at);
+        assert!(Users::find_by_id(3).one(&db).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::{HashMap, HashSet};
+
+use rocket::State;
+use sea_orm::DatabaseConnection;
+
+use super::data;
+use super::data::entities::embeddings;
+use super::vocabulary;
+use crate::utils::{SimilarTweet, TweetData};
+
+// Changing how vectors are made means bumping this, after which `index-embeddings`
+// indexes every tweet again.
+pub const VECTORIZER: &str = "hashed-tfidf-v1";
+
+pub const DEFAULT_RESULTS: usize = 20;
+
+// Words and word pairs are hashed into this many buckets, so there's no vocabulary to keep
+// and new words need nothing but their hash. Vectors are sparse, so a large space costs
+// nothing and keeps collisions rare.
+const BUCKETS: u64 = 1 << 18;
+
+const INDEX_BATCH_SIZE: u64 = 1000;
+
+// The vector of a tweet's text, ready to be written. Only term frequencies are stored:
+// inverse document frequencies change with every tweet archived, so they're worked out
+// from the whole index at search time.
+pub fn embed(tweet_id: i64, content: &str) -> embeddings::Model {
+    embeddings::Model {
+        tweet_id,
+        vectorizer: VECTORIZER.to_string(),
+        vector: encode(&vectorize(content)),
+        indexed_at: chrono::Utc::now().into(),
+    }
+}
+
+// Indexes the tweets that have no vector, or one made by an older vectorizer, and returns
+// how many it indexed.
+pub async fn index_missing(db: &State<DatabaseConnection>) -> usize {
+    let mut indexed = 0;
+    loop {
+        let tweets = data::read::tweets_without_embedding(db, VECTORIZER, INDEX_BATCH_SIZE).await;
+        if tweets.is_empty() {
+            return indexed;
+        }
+        let rows: Vec<embeddings::Model> = tweets
+            .iter()
+            .map(|tweet| embed(tweet.id, &tweet.content))
+            .collect();
+        data::write::embeddings(db, &rows).await;
+        indexed += rows.len();
+        println!("Indexed {indexed} tweets");
+    }
+}
+
+pub async fn search(
+    db: &State<DatabaseConnection>,
+    query: &str,
+    limit: usize,
+) -> Vec<SimilarTweet> {
+    nearest(db, &vectorize(query), None, limit).await
+}
+
+// The tweets closest to the one with `id`, or None if it isn't archived or is hidden.
+pub async fn similar(
+    db: &State<DatabaseConnection>,
+    id: i64,
+    limit: usize,
+) -> Option<Vec<SimilarTweet>> {
+    let tweet = data::read::visible_tweet_by_id(db, id).await.tweet?;
+    let vector = match data::read::embedding(db, id).await {
+        Some(embedding) if embedding.vectorizer == VECTORIZER => decode(&embedding.vector),
+        _ => vectorize(&tweet.content),
+    };
+    Some(nearest(db, &vector, Some(id), limit).await)
+}
+
+// Brute force cosine similarity against every indexed tweet, weighting both sides by
+// inverse document frequency. The index is small next to the tweets themselves, a few
+// dozen bytes a tweet, so this stays quick for archives of a few hundred thousand tweets.
+async fn nearest(
+    db: &State<DatabaseConnection>,
+    query: &[(u32, f32)],
+    exclude: Option<i64>,
+    limit: usize,
+) -> Vec<SimilarTweet> {
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let vectors: Vec<(i64, Vec<(u32, f32)>)> = data::read::embeddings(db, VECTORIZER)
+        .await
+        .into_iter()
+        .map(|embedding| (embedding.tweet_id, decode(&embedding.vector)))
+        .collect();
+    let mut document_frequencies: HashMap<u32, usize> = HashMap::new();
+    for (_tweet_id, vector) in vectors.iter() {
+        for (bucket, _weight) in vector {
+            *document_frequencies.entry(*bucket).or_default() += 1;
+        }
+    }
+    let documents = vectors.len() as f64;
+    let idf = |bucket: &u32| {
+        let frequency = document_frequencies.get(bucket).copied().unwrap_or(0) as f64;
+        ((documents + 1.0) / (frequency + 1.0)).ln() + 1.0
+    };
+
+    let query: HashMap<u32, f64> = query
+        .iter()
+        .map(|(bucket, weight)| (*bucket, *weight as f64 * idf(bucket)))
+        .collect();
+    let query_norm = query.values().map(|weight| weight * weight).sum::<f64>().sqrt();
+    let mut scores: Vec<(f64, i64)> = vectors
+        .iter()
+        .filter(|(tweet_id, _vector)| Some(*tweet_id) != exclude)
+        .filter_map(|(tweet_id, vector)| {
+            let mut dot = 0.0;
+            let mut norm = 0.0;
+            for (bucket, weight) in vector {
+                let weight = *weight as f64 * idf(bucket);
+                norm += weight * weight;
+                dot += weight * query.get(bucket).copied().unwrap_or(0.0);
+            }
+            (dot > 0.0).then(|| (dot / (query_norm * norm.sqrt()), *tweet_id))
+        })
+        .collect();
+    scores.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+    let hidden: HashSet<i64> = data::read::hidden_tweet_ids(db, &ids_of(&scores))
+        .await
+        .into_iter()
+        .collect();
+    scores.retain(|(_score, tweet_id)| !hidden.contains(tweet_id));
+    scores.truncate(limit);
+
+    let tweets = TweetData::read_many(db, &ids_of(&scores)).await;
+    scores
+        .into_iter()
+        .zip(tweets)
+        .map(|((score, _tweet_id), tweet)| SimilarTweet { score, tweet })
+        .collect()
+}
+
+fn ids_of(scores: &[(f64, i64)]) -> Vec<i64> {
+    scores.iter().map(|(_score, tweet_id)| *tweet_id).collect()
+}
+
+// Sublinear term frequencies of the tweet's words and adjacent word pairs, by bucket.
+fn vectorize(text: &str) -> Vec<(u32, f32)> {
+    let tokens = vocabulary::tokenize(text);
+    let mut counts: HashMap<u32, u32> = HashMap::new();
+    let bigrams = tokens.windows(2).map(|pair| format!("{} {}", pair[0], pair[1]));
+    for feature in tokens.iter().cloned().chain(bigrams) {
+        *counts.entry((fnv1a(&feature) % BUCKETS) as u32).or_default() += 1;
+    }
+    let mut vector: Vec<(u32, f32)> = counts
+        .into_iter()
+        .map(|(bucket, count)| (bucket, 1.0 + (count as f32).ln()))
+        .collect();
+    vector.sort_by_key(|(bucket, _weight)| *bucket);
+    vector
+}
+
+// A hash that stays the same across builds, unlike std's, since vectors are stored.
+fn fnv1a(feature: &str) -> u64 {
+    feature.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn encode(vector: &[(u32, f32)]) -> Vec<u8> {
+    vector
+        .iter()
+        .flat_map(|(bucket, weight)| {
+            bucket
+                .to_le_bytes()
+                .into_iter()
+                .chain(weight.to_le_bytes())
+        })
+        .collect()
+}
+
+fn decode(bytes: &[u8]) -> Vec<(u32, f32)> {
+    bytes
+        .chunks_exact(8)
+        .map(|pair| {
+            let (bucket, weight) = pair.split_at(4);
+            (
+                u32::from_le_bytes(bucket.try_into().expect("chunks are 8 bytes")),
+                f32::from_le_bytes(weight.try_into().expect("chunks are 8 bytes")),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_matches_the_published_values() {
+        assert_eq!(fnv1a(""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a("a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a("foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn vectors_weigh_words_and_pairs_of_words_by_how_often_they_come_up() {
+        let bucket = |feature: &str| (fnv1a(feature) % BUCKETS) as u32;
+        let mut expected = vec![
+            (bucket("rust"), 1.0 + 3f32.ln()),
+            (bucket("compiler"), 1.0),
+            (bucket("rust rust"), 1.0 + 2f32.ln()),
+            (bucket("rust compiler"), 1.0),
+        ];
+        expected.sort_by_key(|(bucket, _weight)| *bucket);
+        assert_eq!(vectorize("Rust rust, RUST compiler"), expected);
+        assert_eq!(vectorize(""), vec![]);
+    }
+
+    #[test]
+    fn vectors_round_trip_through_their_bytes() {
+        let vector = vectorize("Tweets about the rust compiler and the borrow checker");
+        let bytes = encode(&vector);
+        assert_eq!(bytes.len(), vector.len() * 8);
+        assert_eq!(decode(&bytes), vector);
+        let extremes = vec![(0, 0.5), (u32::MAX, -1.25)];
+        assert_eq!(decode(&encode(&extremes)), extremes);
+        assert_eq!(decode(&[]), vec![]);
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, FixedOffset};
+use rocket::State;
+use sea_orm::DatabaseConnection;
+
+use super::data;
+use super::dates::DateWindow;
+use super::data::read::TweetText;
+use crate::utils::{EmergingTerm, EmergingTerms, PeriodTerms, TermCount, TermReport};
+
+pub const DEFAULT_TERM_LIMIT: usize = 25;
+
+// A term has to be used at least this often in a period to count as emerging, so that a
+// word used once or twice doesn't top the list just for being new.
+const MIN_EMERGING_COUNT: usize = 3;
+
+// Words too common to say anything about the author.
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "am", "amp", "an", "and", "any", "are", "as", "at",
+    "be", "because", "been", "but", "by", "can", "could", "did", "do", "does", "don't", "for",
+    "from", "get", "got", "had", "has", "have", "he", "her", "him", "his", "how", "i", "i'm",
+    "if", "in", "into", "is", "it", "it's", "its", "just", "like", "me", "more", "my", "no",
+    "not", "now", "of", "on", "one", "only", "or", "our", "out", "rt", "she", "so", "some",
+    "than", "that", "that's", "the", "their", "them", "then", "there", "they", "this", "to",
+    "too", "up", "us", "very", "was", "we", "were", "what", "when", "which", "who", "why",
+    "will", "with", "would", "you", "your",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TermPeriod {
+    Year,
+    Month,
+}
+
+impl FromStr for TermPeriod {
+    type Err = String;
+
+    fn from_str(period: &str) -> Result<Self, Self::Err> {
+        match period {
+            "year" => Ok(Self::Year),
+            "month" => Ok(Self::Month),
+            _ => Err(format!("Unknown period {period}, expected year or month")),
+        }
+    }
+}
+
+impl TermPeriod {
+    fn of(&self, date: &DateTime<FixedOffset>, offset: &FixedOffset) -> String {
+        let date = date.with_timezone(offset);
+        match self {
+            Self::Year => format!("{}", date.year()),
+            Self::Month => format!("{}-{:02}", date.year(), date.month()),
+        }
+    }
+}
+
+// Lowercase words from the tweet with links, mentions and punctuation taken out and stop
+// words dropped. Hashtags keep their #.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .filter(|word| !is_link(word) && !word.starts_with('@'))
+        .flat_map(|word| {
+            word.split(|c: char| !(c.is_alphanumeric() || c == '#' || c == '\'' || c == '’'))
+                .map(|part| part.replace('’', "'"))
+                .collect::<Vec<String>>()
+        })
+        .map(|word| {
+            word.trim_matches('\'')
+                .trim_end_matches('#')
+                .to_lowercase()
+        })
+        .filter(|word| {
+            word.chars().count() > 1
+                && !word.chars().all(|c| c.is_numeric() || c == '#')
+                && !STOP_WORDS.contains(&word.as_str())
+        })
+        .collect()
+}
+
+pub async fn user_terms(
+    db: &State<DatabaseConnection>,
+    user_id: i64,
+    window: &DateWindow,
+    period: TermPeriod,
+    limit: usize,
+) -> TermReport {
+    let texts = data::read::users_tweet_texts(db, user_id, window.from, window.to).await;
+    let mut overall = Counts::default();
+    let mut periods: BTreeMap<String, Counts> = BTreeMap::new();
+    for text in texts.iter() {
+        let tokens = tokenize(&text.content);
+        overall.add(&tokens);
+        periods.entry(period.of(&text.created_at, &window.offset)).or_default().add(&tokens);
+    }
+    TermReport {
+        user_id,
+        from: window.from.map(|from| from.to_rfc3339()),
+        to: window.to.map(|to| to.to_rfc3339()),
+        tweets: texts.len(),
+        top_terms: top(&overall.terms, limit),
+        top_bigrams: top(&overall.bigrams, limit),
+        periods: periods
+            .into_iter()
+            .map(|(period, counts)| PeriodTerms {
+                period,
+                tweets: counts.tweets,
+                top_terms: top(&counts.terms, limit),
+                top_bigrams: top(&counts.bigrams, limit),
+            })
+            .collect(),
+    }
+}
+
+// Compares each period that has tweets with the one before it that has tweets too.
+pub async fn emerging_terms(
+    db: &State<DatabaseConnection>,
+    user_id: i64,
+    window: &DateWindow,
+    period: TermPeriod,
+    limit: usize,
+) -> Vec<EmergingTerms> {
+    let texts = data::read::users_tweet_texts(db, user_id, window.from, window.to).await;
+    emerging(&by_period(&texts, period, &window.offset), limit)
+}
+
+// The tweets' terms counted per period, with periods read in `offset`.
+fn by_period(
+    texts: &[TweetText],
+    period: TermPeriod,
+    offset: &FixedOffset,
+) -> BTreeMap<String, Counts> {
+    let mut periods: BTreeMap<String, Counts> = BTreeMap::new();
+    for text in texts.iter() {
+        periods
+            .entry(period.of(&text.created_at, offset))
+            .or_default()
+            .add(&tokenize(&text.content));
+    }
+    periods
+}
+
+// A term's growth is its share of the period's words over its share of the previous
+// period's, where terms the previous period didn't use count as used once.
+fn emerging(periods: &BTreeMap<String, Counts>, limit: usize) -> Vec<EmergingTerms> {
+    let periods: Vec<(&String, &Counts)> = periods.iter().collect();
+    periods
+        .windows(2)
+        .map(|pair| {
+            let (previous_period, previous) = pair[0];
+            let (period, current) = pair[1];
+            let mut terms: Vec<EmergingTerm> = current
+                .terms
+                .iter()
+                .filter(|(_term, count)| **count >= MIN_EMERGING_COUNT)
+                .map(|(term, count)| {
+                    let previous_count = previous.terms.get(term).copied().unwrap_or(0);
+                    let rate = *count as f64 / current.words as f64;
+                    let previous_rate =
+                        previous_count.max(1) as f64 / previous.words.max(1) as f64;
+                    EmergingTerm {
+                        term: term.clone(),
+                        count: *count,
+                        previous_count,
+                        growth: rate / previous_rate,
+                    }
+                })
+                .filter(|term| term.growth > 1.0)
+                .collect();
+            terms.sort_by(|a, b| {
+                b.growth
+                    .total_cmp(&a.growth)
+                    .then(b.count.cmp(&a.count))
+                    .then(a.term.cmp(&b.term))
+            });
+            terms.truncate(limit);
+            EmergingTerms {
+                period: period.clone(),
+                previous_period: previous_period.clone(),
+                terms,
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    tweets: usize,
+    words: usize,
+    terms: HashMap<String, usize>,
+    bigrams: HashMap<String, usize>,
+}
+
+impl Counts {
+    fn add(&mut self, tokens: &[String]) {
+        self.tweets += 1;
+        self.words += tokens.len();
+        for token in tokens {
+            *self.terms.entry(token.clone()).or_default() += 1;
+        }
+        // Stop words are already gone, so pairs can span them.
+        for pair in tokens.windows(2) {
+            *self
+                .bigrams
+                .entry(format!("{} {}", pair[0], pair[1]))
+                .or_default() += 1;
+        }
+    }
+}
+
+// Most used first, ties in alphabetical order.
+fn top(counts: &HashMap<String, usize>, limit: usize) -> Vec<TermCount> {
+    let mut counts: Vec<TermCount> = counts
+        .iter()
+        .map(|(term, count)| TermCount {
+            term: term.clone(),
+            count: *count,
+        })
+        .collect();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then(a.term.cmp(&b.term)));
+    counts.truncate(limit);
+    counts
+}
+
+fn is_link(word: &str) -> bool {
+    let word = word.to_lowercase();
+    word.starts_with("http://") || word.starts_with("https://") || word.starts_with("www.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(content: &str, created_at: &str) -> TweetText {
+        TweetText {
+            content: content.to_string(),
+            created_at: DateTime::parse_from_rfc3339(created_at).unwrap(),
+        }
+    }
+
+    #[test]
+    fn tokenize_drops_links_mentions_stop_words_and_numbers() {
+        assert_eq!(
+            tokenize("@bob I'm reading THE #Rust book’s 2nd edition: https://example.com 2022 x"),
+            vec!["reading", "#rust", "book's", "2nd", "edition"]
+        );
+    }
+
+    #[test]
+    fn periods_are_read_in_the_offset() {
+        let date = DateTime::parse_from_rfc3339("2021-12-31T23:30:00Z").unwrap();
+        let utc = FixedOffset::east(0);
+        let paris = FixedOffset::east(3600);
+        assert_eq!(TermPeriod::Month.of(&date, &utc), "2021-12");
+        assert_eq!(TermPeriod::Month.of(&date, &paris), "2022-01");
+        assert_eq!(TermPeriod::Year.of(&date, &paris), "2022");
+    }
+
+    #[test]
+    fn tweets_are_counted_in_their_period() {
+        let texts = [
+            text("rust rust", "2022-01-05T10:00:00Z"),
+            text("rust crabs", "2022-01-20T10:00:00Z"),
+            text("crabs", "2022-03-01T10:00:00Z"),
+        ];
+        let periods = by_period(&texts, TermPeriod::Month, &FixedOffset::east(0));
+        assert_eq!(periods.keys().collect::<Vec<_>>(), ["2022-01", "2022-03"]);
+        let january = &periods["2022-01"];
+        assert_eq!(january.tweets, 2);
+        assert_eq!(january.words, 4);
+        assert_eq!(january.terms["rust"], 3);
+        assert_eq!(january.bigrams["rust rust"], 1);
+        assert_eq!(january.bigrams["rust crabs"], 1);
+    }
+
+    #[test]
+    fn bigrams_stay_within_a_tweet() {
+        let mut counts = Counts::default();
+        counts.add(&tokenize("rust crabs"));
+        counts.add(&tokenize("ferris"));
+        assert_eq!(counts.bigrams.len(), 1);
+        assert!(!counts.bigrams.contains_key("crabs ferris"));
+    }
+
+    #[test]
+    fn emerging_terms_compare_each_period_with_the_one_before() {
+        let texts = [
+            text("rust crabs ferris", "2022-01-05T10:00:00Z"),
+            text("crabs crabs crabs ferris", "2022-02-05T10:00:00Z"),
+            text("ferris ferris ferris crabs", "2022-04-05T10:00:00Z"),
+        ];
+        let periods = by_period(&texts, TermPeriod::Month, &FixedOffset::east(0));
+        let emerging = emerging(&periods, DEFAULT_TERM_LIMIT);
+        assert_eq!(emerging.len(), 2);
+        assert_eq!(emerging[0].previous_period, "2022-01");
+        assert_eq!(emerging[0].period, "2022-02");
+        assert_eq!(emerging[0].terms.len(), 1);
+        let crabs = &emerging[0].terms[0];
+        assert_eq!(crabs.term, "crabs");
+        assert_eq!((crabs.count, crabs.previous_count), (3, 1));
+        assert!((crabs.growth - 2.25).abs() < 1e-9);
+        // Periods without tweets are skipped over.
+        assert_eq!(emerging[1].previous_period, "2022-02");
+        assert_eq!(emerging[1].terms[0].term, "ferris");
+    }
+
+    #[test]
+    fn rare_terms_never_emerge() {
+        let texts = [
+            text("rust", "2022-01-05T10:00:00Z"),
+            text("crabs crabs", "2022-02-05T10:00:00Z"),
+        ];
+        let periods = by_period(&texts, TermPeriod::Month, &FixedOffset::east(0));
+        assert!(emerging(&periods, DEFAULT_TERM_LIMIT)[0].terms.is_empty());
+    }
+
+    #[test]
+    fn top_terms_are_most_used_first_then_alphabetical() {
+        let mut counts = Counts::default();
+        counts.add(&tokenize("crabs rust ferris rust"));
+        let top = top(&counts.terms, 2);
+        assert_eq!(
+            top,
+            vec![
+                TermCount {
+                    term: "rust".to_string(),
+                    count: 2
+                },
+                TermCount {
+                    term: "crabs".to_string(),
+                    count: 1
+                },
+            ]
+        );
+    }
+}
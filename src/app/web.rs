@@ -0,0 +1,587 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+use rocket::http::RawStr;
+use rocket::State;
+use sea_orm::DatabaseConnection;
+
+use super::data;
+use super::data::entities::users;
+use super::export::site::{self, escape_html, replied_to_id, STYLE};
+use crate::utils::{ConversationData, TweetData, TweetPage};
+
+pub const TWEETS_PER_PAGE: usize = 50;
+
+// What the search page was asked for. Empty fields of its form count as not given, and
+// `page` counts from 0.
+#[derive(Debug, Clone, Default)]
+pub struct SearchForm {
+    pub query: String,
+    pub collection: Option<i32>,
+    pub tag: Option<String>,
+    pub page: usize,
+}
+
+impl SearchForm {
+    pub fn parse(
+        query: Option<&str>,
+        collection: Option<&str>,
+        tag: Option<&str>,
+        page: Option<usize>,
+    ) -> Self {
+        SearchForm {
+            query: given(query).unwrap_or_default().to_owned(),
+            collection: given(collection).and_then(|collection| collection.parse().ok()),
+            tag: given(tag).map(str::to_owned),
+            page: page_index(page),
+        }
+    }
+
+    fn link(&self, page: usize) -> String {
+        let mut link = format!("/ui/search?q={}", encode(&self.query));
+        if let Some(collection) = self.collection {
+            link += &format!("&collection={collection}");
+        }
+        if let Some(tag) = &self.tag {
+            link += &format!("&tag={}", encode(tag));
+        }
+        link + &format!("&page={}", page + 1)
+    }
+}
+
+fn given(field: Option<&str>) -> Option<&str> {
+    field.map(str::trim).filter(|field| !field.is_empty())
+}
+
+// Pages are numbered from 1 in links.
+pub fn page_index(page: Option<usize>) -> usize {
+    page.unwrap_or(1).max(1) - 1
+}
+
+// The archived users, the search form and what the archive has been sorted into.
+pub async fn home(db: &State<DatabaseConnection>) -> String {
+    let mut users: Vec<users::Model> = data::read::users(db)
+        .await
+        .into_iter()
+        .filter_map(|user_data| user_data.user)
+        .collect();
+    users.sort_by_key(|user| user.username.to_lowercase());
+    let user_links: String = users
+        .iter()
+        .map(|user| {
+            format!(
+                "<li><a href=\"/ui/user/{handle}\">@{handle}</a> {name}</li>",
+                handle = escape_html(&user.username),
+                name = escape_html(&user.name),
+            )
+        })
+        .collect();
+    let collection_links: String = super::collections::list(db)
+        .await
+        .iter()
+        .map(|summary| {
+            format!(
+                "<li><a href=\"/ui/search?collection={}\">{}</a> ({} tweets)</li>",
+                summary.collection.id,
+                escape_html(&summary.collection.name),
+                summary.tweets
+            )
+        })
+        .collect();
+    let tag_links: String = super::collections::tags(db)
+        .await
+        .iter()
+        .map(|tag| {
+            format!(
+                "<li><a href=\"/ui/search?tag={}\">{}</a> ({})</li>",
+                encode(&tag.tag),
+                escape_html(&tag.tag),
+                tag.tweets
+            )
+        })
+        .collect();
+    let mut body = search_form(db, &SearchForm::default()).await;
+    body += &format!("<h2>Users</h2><ul>{user_links}</ul>");
+    if !collection_links.is_empty() {
+        body += &format!("<h2>Collections</h2><ul>{collection_links}</ul>");
+    }
+    if !tag_links.is_empty() {
+        body += &format!("<h2>Tags</h2><ul class=\"tags\">{tag_links}</ul>");
+    }
+    page("Archive", &body)
+}
+
+// The user's profile and a page of their timeline, or None if they aren't archived.
+pub async fn user(
+    db: &State<DatabaseConnection>,
+    twitter_handle: &str,
+    page_index: usize,
+) -> Option<String> {
+    let user = data::read::user_by_twitter_handle(db, twitter_handle)
+        .await
+        .user?;
+    let timeline = data::read::users_tweets_page(db, user.id, page_index, TWEETS_PER_PAGE).await;
+    let renderer = Renderer::load(db, &timeline.tweets).await;
+    let tweets: String = timeline
+        .tweets
+        .iter()
+        .map(|tweet_data| renderer.tweet(tweet_data))
+        .collect();
+    let handle = escape_html(&user.username);
+    let body = format!(
+        "<p><strong>{name}</strong> · <a href=\"https://twitter.com/{handle}\">twitter</a></p>\
+         <p>{description}</p>{tweets}{pager}",
+        name = escape_html(&user.name),
+        description = escape_html(&user.description),
+        pager = pager(&timeline, |page| format!(
+            "/ui/user/{handle}?page={}",
+            page + 1
+        )),
+    );
+    Some(page(&format!("@{}", user.username), &body))
+}
+
+// Every archived tweet of the conversation, each reply under the tweet it replied to.
+pub async fn conversation(db: &State<DatabaseConnection>, id: i64) -> Option<String> {
+    let conversation = data::read::conversation(db, id).await;
+    if conversation.tweets.is_empty() {
+        return None;
+    }
+    let renderer = Renderer::load(db, &conversation.tweets).await;
+    Some(page(
+        &format!("Conversation {id}"),
+        &renderer.thread(&conversation),
+    ))
+}
+
+// The tweet with what it replied to above it and the replies and quotes of it below.
+pub async fn tweet(db: &State<DatabaseConnection>, id: i64) -> Option<String> {
+    let tweet_data = data::read::visible_tweet_by_id(db, id).await;
+    let tweet = tweet_data.tweet.clone()?;
+    let parent = match replied_to_id(&tweet_data) {
+        Some(parent_id) => Some(data::read::visible_tweet_by_id(db, parent_id).await),
+        None => None,
+    };
+    let mut replies = Vec::new();
+    let mut quotes = Vec::new();
+    for reference in data::read::references_to_tweets(db, &[id]).await {
+        let source = data::read::visible_tweet_by_id(db, reference.source_tweet_id).await;
+        if source.tweet.is_none() {
+            continue;
+        }
+        match reference.reference_type.as_str() {
+            "replied_to" => replies.push(source),
+            "quoted" => quotes.push(source),
+            _other => {}
+        }
+    }
+    let by_date = |tweet_data: &TweetData| tweet_data.tweet.as_ref().map(|tweet| tweet.created_at);
+    replies.sort_by_key(by_date);
+    quotes.sort_by_key(by_date);
+
+    let shown: Vec<TweetData> = parent
+        .iter()
+        .chain([&tweet_data])
+        .chain(replies.iter())
+        .chain(quotes.iter())
+        .cloned()
+        .collect();
+    let renderer = Renderer::load(db, &shown).await;
+    let mut body = String::new();
+    match &parent {
+        Some(parent) if parent.tweet.is_some() => {
+            body += &format!("<div class=\"parent\">{}</div>", renderer.tweet(parent));
+        }
+        _not_archived => {}
+    }
+    body += &format!("<div class=\"focus\">{}</div>", renderer.tweet(&tweet_data));
+    body += &section("Replies", &replies, &renderer);
+    body += &section("Quoted by", &quotes, &renderer);
+    body += &format!(
+        "<p><a href=\"/ui/conversation/{}\">Whole conversation</a></p>",
+        tweet.conversation_id
+    );
+    Some(page(&format!("Tweet {id}"), &body))
+}
+
+// The search form, with a page of results when there is something to search for.
+pub async fn search(db: &State<DatabaseConnection>, form: &SearchForm) -> String {
+    let mut body = search_form(db, form).await;
+    let filtering = form.collection.is_some() || form.tag.is_some();
+    if form.query.is_empty() && !filtering {
+        return page("Search", &body);
+    }
+    let tag = match form.tag.as_deref().map(super::collections::normalize_tag) {
+        Some(Err(error)) => {
+            body += &format!("<p class=\"error\">{}</p>", escape_html(&error.to_string()));
+            return page("Search", &body);
+        }
+        Some(Ok(tag)) => Some(tag),
+        None => None,
+    };
+    let filter = data::read::SearchFilter {
+        collection_id: form.collection,
+        tag,
+    };
+    let results = TweetPage::of(
+        super::search_tweets_in_db(db, &form.query, &filter).await,
+        form.page,
+        TWEETS_PER_PAGE,
+    );
+    let renderer = Renderer::load(db, &results.tweets).await;
+    if results.tweets.is_empty() {
+        body += "<p>No tweets found.</p>";
+    }
+    for tweet_data in results.tweets.iter() {
+        body += &renderer.tweet(tweet_data);
+    }
+    body += &pager(&results, |page| form.link(page));
+    page("Search", &body)
+}
+
+pub fn not_found(description: &str) -> String {
+    page(
+        "Not found",
+        &format!(
+            "<p>There is no archived {}.</p><p><a href=\"/\">Back to the archive</a></p>",
+            escape_html(description)
+        ),
+    )
+}
+
+async fn search_form(db: &State<DatabaseConnection>, form: &SearchForm) -> String {
+    let selected = |is_selected: bool| if is_selected { " selected" } else { "" };
+    let collection_options: String = super::collections::list(db)
+        .await
+        .iter()
+        .map(|summary| {
+            format!(
+                "<option value=\"{id}\"{selected}>{name}</option>",
+                id = summary.collection.id,
+                selected = selected(form.collection == Some(summary.collection.id)),
+                name = escape_html(&summary.collection.name),
+            )
+        })
+        .collect();
+    let tag_options: String = super::collections::tags(db)
+        .await
+        .iter()
+        .map(|tag| {
+            format!(
+                "<option value=\"{tag}\"{selected}>{tag}</option>",
+                tag = escape_html(&tag.tag),
+                selected = selected(form.tag.as_deref() == Some(tag.tag.as_str())),
+            )
+        })
+        .collect();
+    format!(
+        "<form action=\"/ui/search\" method=\"get\" class=\"search\">\
+         <input type=\"search\" name=\"q\" value=\"{query}\" placeholder=\"Search tweets\">\
+         <select name=\"collection\"><option value=\"\">Any collection</option>{collection_options}</select>\
+         <select name=\"tag\"><option value=\"\">Any tag</option>{tag_options}</select>\
+         <button type=\"submit\">Search</button></form>",
+        query = escape_html(&form.query),
+    )
+}
+
+fn section(title: &str, tweets: &[TweetData], renderer: &Renderer) -> String {
+    if tweets.is_empty() {
+        return String::new();
+    }
+    let tweets: String = tweets
+        .iter()
+        .map(|tweet_data| renderer.tweet(tweet_data))
+        .collect();
+    format!("<h2>{title}</h2>{tweets}")
+}
+
+fn pager(tweets: &TweetPage, link: impl Fn(usize) -> String) -> String {
+    if tweets.pages <= 1 {
+        return String::new();
+    }
+    let newer = match tweets.page {
+        0 => String::new(),
+        page => format!("<a href=\"{}\">Newer</a> ", escape_html(&link(page - 1))),
+    };
+    let older = if tweets.page + 1 < tweets.pages {
+        format!(
+            " <a href=\"{}\">Older</a>",
+            escape_html(&link(tweets.page + 1))
+        )
+    } else {
+        String::new()
+    };
+    format!(
+        "<nav class=\"pager\">{newer}Page {} of {}{older}</nav>",
+        tweets.page + 1,
+        tweets.pages
+    )
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\"><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+         <title>{title}</title><style>{STYLE}{UI_STYLE}</style></head><body>\
+         <nav><a href=\"/\">Archive</a> · <a href=\"/ui/search\">Search</a></nav>\
+         <h1>{title}</h1>{body}</body></html>\n",
+        title = escape_html(title),
+    )
+}
+
+const UI_STYLE: &str = ".focus .tweet{border-color:#888}\
+    .quote{border-left:3px solid #ddd;margin:.5em 0;padding:0 .5em}\
+    .search select,.search input{margin-right:.5em}\
+    .pager{margin:1em 0}.error{color:#a00}";
+
+fn encode(text: &str) -> String {
+    RawStr::new(text).percent_encode().to_string()
+}
+
+// Knows the authors of the tweets on a page and the tweets they quote, so quotes can be
+// shown inline.
+struct Renderer {
+    authors: HashMap<i64, users::Model>,
+    quoted: HashMap<i64, TweetData>,
+}
+
+impl Renderer {
+    async fn load(db: &State<DatabaseConnection>, tweets: &[TweetData]) -> Self {
+        let mut quoted = HashMap::new();
+        for reference in tweets
+            .iter()
+            .flat_map(|tweet_data| tweet_data.references.iter())
+            .filter(|reference| reference.reference_type == "quoted")
+        {
+            let id = reference.referenced_tweet_id;
+            if let Entry::Vacant(entry) = quoted.entry(id) {
+                let quoted_tweet = data::read::visible_tweet_by_id(db, id).await;
+                if quoted_tweet.tweet.is_some() {
+                    entry.insert(quoted_tweet);
+                }
+            }
+        }
+        let author_ids: HashSet<i64> = tweets
+            .iter()
+            .chain(quoted.values())
+            .filter_map(|tweet_data| tweet_data.tweet.as_ref().map(|tweet| tweet.author_id))
+            .collect();
+        let mut authors = HashMap::new();
+        for author_id in author_ids {
+            if let Some(user) = data::read::user_by_id(db, author_id).await.user {
+                authors.insert(author_id, user);
+            }
+        }
+        Renderer { authors, quoted }
+    }
+
+    fn author(&self, author_id: i64) -> String {
+        match self.authors.get(&author_id) {
+            Some(user) => format!(
+                "<a href=\"/ui/user/{handle}\"><strong>@{handle}</strong></a>",
+                handle = escape_html(&user.username)
+            ),
+            None => format!("<strong>user {author_id}</strong>"),
+        }
+    }
+
+    fn tweet(&self, tweet_data: &TweetData) -> String {
+        let tweet = match &tweet_data.tweet {
+            Some(tweet) => tweet,
+            None => return String::new(),
+        };
+        let replying_to: String = replied_to_id(tweet_data)
+            .map(|parent_id| {
+                format!(
+                    "<div class=\"reference\">replying to <a href=\"/ui/tweet/{parent_id}\">\
+                     {parent_id}</a></div>"
+                )
+            })
+            .unwrap_or_default();
+        let quotes: String = tweet_data
+            .references
+            .iter()
+            .filter(|reference| reference.reference_type == "quoted")
+            .map(|reference| self.quote(reference.referenced_tweet_id))
+            .collect();
+        format!(
+            "<article class=\"tweet\"><header>{author} <a href=\"/ui/tweet/{id}\">{date}</a>\
+             </header>{replying_to}<p>{content}</p>{quotes}<footer>\
+             <a href=\"/ui/conversation/{conversation_id}\">conversation</a></footer></article>",
+            author = self.author(tweet.author_id),
+            id = tweet.id,
+            date = tweet.created_at.format("%Y-%m-%d %H:%M"),
+            content = escape_html(&tweet.content).replace('\n', "<br>"),
+            conversation_id = tweet.conversation_id,
+        )
+    }
+
+    // Quoted tweets are shown without their own quotes, so a chain of quotes stays short.
+    fn quote(&self, id: i64) -> String {
+        match self.quoted.get(&id).and_then(|quoted| quoted.tweet.as_ref()) {
+            Some(tweet) => format!(
+                "<blockquote class=\"quote\"><header>{author} <a href=\"/ui/tweet/{id}\">{date}</a>\
+                 </header><p>{content}</p></blockquote>",
+                author = self.author(tweet.author_id),
+                date = tweet.created_at.format("%Y-%m-%d %H:%M"),
+                content = escape_html(&tweet.content).replace('\n', "<br>"),
+            ),
+            None => format!(
+                "<div class=\"reference\">quoting <a href=\"https://twitter.com/i/web/status/{id}\">\
+                 {id}</a> (not archived)</div>"
+            ),
+        }
+    }
+
+    // Nests each reply under the tweet it replied to, like the static site does.
+    fn thread(&self, conversation: &ConversationData) -> String {
+        site::thread(conversation, |tweet_data| self.tweet(tweet_data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::data::entities::{conversations, hidden_tweets, tweet_references, tweets};
+    use crate::app::data::setup;
+    use chrono::DateTime;
+
+    #[test]
+    fn pagers_link_to_the_pages_either_side() {
+        let pages = |page: usize, pages: usize| TweetPage {
+            tweets: Vec::new(),
+            page,
+            pages,
+        };
+        let link = |page: usize| format!("/ui/user/tom?page={}", page + 1);
+        assert_eq!(pager(&pages(0, 1), link), "");
+        assert_eq!(
+            pager(&pages(0, 3), link),
+            "<nav class=\"pager\">Page 1 of 3 <a href=\"/ui/user/tom?page=2\">Older</a></nav>"
+        );
+        assert_eq!(
+            pager(&pages(1, 3), link),
+            "<nav class=\"pager\"><a href=\"/ui/user/tom?page=1\">Newer</a> Page 2 of 3 \
+             <a href=\"/ui/user/tom?page=3\">Older</a></nav>"
+        );
+        assert_eq!(
+            pager(&pages(2, 3), link),
+            "<nav class=\"pager\"><a href=\"/ui/user/tom?page=2\">Newer</a> Page 3 of 3</nav>"
+        );
+    }
+
+    #[test]
+    fn search_links_keep_the_form_and_are_escaped() {
+        let form = SearchForm::parse(Some(" cats & dogs "), Some("2"), Some("pets"), Some(3));
+        assert_eq!(form.page, 2);
+        assert_eq!(
+            form.link(form.page + 1),
+            "/ui/search?q=cats%20%26%20dogs&collection=2&tag=pets&page=4"
+        );
+        let results = TweetPage {
+            tweets: Vec::new(),
+            page: 2,
+            pages: 4,
+        };
+        assert!(pager(&results, |page| form.link(page)).contains(
+            "<a href=\"/ui/search?q=cats%20%26%20dogs&amp;collection=2&amp;tag=pets&amp;page=2\">\
+             Newer</a>"
+        ));
+        // Fields left empty count as not given.
+        let form = SearchForm::parse(Some(""), Some(" "), None, None);
+        assert_eq!(form.link(0), "/ui/search?q=&page=1");
+    }
+
+    // Tom's first tweet has markup in it and is replied to by the second, which is hidden
+    // along with everything else it would show up in. Tom has a page more than fits on one.
+    async fn archive(name: &str) -> DatabaseConnection {
+        let db = setup::test_db(name).await;
+        let state = State::from(&db);
+        let created_at = DateTime::parse_from_rfc3339("2022-01-05T10:00:00Z").unwrap();
+        let tweet = |id: i64, content: &str| tweets::Model {
+            id,
+            content: content.to_string(),
+            author_id: 7,
+            conversation_id: 1,
+            created_at: created_at - chrono::Duration::minutes(id),
+        };
+        data::write::dumped_rows(
+            state,
+            data::write::DumpedRows {
+                users: vec![users::Model {
+                    id: 7,
+                    name: "Tom & <Jerry>".to_string(),
+                    username: "tom".to_string(),
+                    description: "\"Quoted\"".to_string(),
+                }],
+                conversations: vec![conversations::Model { id: 1 }],
+                tweets: [
+                    tweet(1, "<script>alert(1)</script>\nnext line"),
+                    tweet(2, "hidden reply"),
+                ]
+                .into_iter()
+                .chain((3..=TWEETS_PER_PAGE as i64 + 2).map(|id| tweet(id, "filler")))
+                .collect(),
+                references: vec![tweet_references::Model {
+                    source_tweet_id: 2,
+                    reference_type: "replied_to".to_string(),
+                    referenced_tweet_id: 1,
+                }],
+            },
+        )
+        .await;
+        data::write::hidden_tweet(
+            state,
+            &hidden_tweets::Model {
+                tweet_id: 2,
+                reason: "Asked to".to_string(),
+                hidden_at: created_at,
+            },
+        )
+        .await;
+        db
+    }
+
+    #[tokio::test]
+    async fn pages_escape_what_they_take_from_the_archive() {
+        let db = archive("web-escaping").await;
+        let state = State::from(&db);
+        let html = user(state, "tom", 0).await.unwrap();
+        assert!(html.contains("<strong>Tom &amp; &lt;Jerry&gt;</strong>"));
+        assert!(html.contains("<p>&quot;Quoted&quot;</p>"));
+        assert!(html.contains("<p>&lt;script&gt;alert(1)&lt;/script&gt;<br>next line</p>"));
+        assert!(!html.contains("<script>"));
+        let html = search(state, &SearchForm::parse(Some("<script>"), None, None, None)).await;
+        assert!(html.contains("value=\"&lt;script&gt;\""));
+        assert!(html.contains("<p>&lt;script&gt;alert(1)&lt;/script&gt;<br>next line</p>"));
+        assert!(!html.contains("<script>"));
+        assert!(not_found("tweet <1>").contains("There is no archived tweet &lt;1&gt;."));
+    }
+
+    #[tokio::test]
+    async fn user_timelines_are_paged() {
+        let db = archive("web-pages").await;
+        let state = State::from(&db);
+        let first = user(state, "tom", 0).await.unwrap();
+        assert!(first.contains("Page 1 of 2 <a href=\"/ui/user/tom?page=2\">Older</a>"));
+        assert!(first.contains("/ui/tweet/1\""));
+        let last = user(state, "tom", 1).await.unwrap();
+        assert!(last.contains("<a href=\"/ui/user/tom?page=1\">Newer</a> Page 2 of 2</nav>"));
+        assert_eq!(last.matches("<article class=\"tweet\">").count(), 1);
+        assert!(user(state, "nobody", 0).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn hidden_tweets_are_left_out_everywhere() {
+        let db = archive("web-hidden").await;
+        let state = State::from(&db);
+        assert!(!user(state, "tom", 0).await.unwrap().contains("hidden reply"));
+        assert!(!conversation(state, 1).await.unwrap().contains("hidden reply"));
+        assert!(tweet(state, 2).await.is_none());
+        let html = tweet(state, 1).await.unwrap();
+        assert!(!html.contains("hidden reply"));
+        assert!(!html.contains("<h2>Replies</h2>"));
+        let html = search(state, &SearchForm::parse(Some("hidden"), None, None, None)).await;
+        assert!(html.contains("<p>No tweets found.</p>"));
+    }
+}
@@ -0,0 +1,277 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rocket::http::{Header, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::{catch, catchers, Catcher, Request, Responder, State};
+use sea_orm::DatabaseConnection;
+
+use crate::app;
+use crate::app::data::entities::api_keys;
+use crate::app::keys::{self, Scope};
+use crate::limits::{self, Client, Limits};
+
+// Who made a request: the key they sent, if any. Getting one fails with 401 for keys that
+// are unknown or revoked, or for no key when reads need one, and with 429 when the client
+// is over its request rate limit.
+pub struct Caller {
+    pub key: Option<api_keys::Model>,
+    pub client: Client,
+    limits: Limits,
+}
+
+// Why a caller can't make a request that may fetch from the api.
+pub enum FetchRefused {
+    MissingScope(String),
+    OverBudget(TooManyRequests),
+}
+
+impl Caller {
+    // The caller's key, if it has the scope.
+    pub fn require(&self, scope: Scope) -> Result<&api_keys::Model, String> {
+        match &self.key {
+            Some(key) if keys::has_scope(key, scope) => Ok(key),
+            Some(key) => Err(format!(
+                "Api key {} doesn't have the {scope} scope",
+                key.prefix
+            )),
+            None => Err(format!("This needs an api key with the {scope} scope")),
+        }
+    }
+
+    // Fetching needs the trigger-fetch scope and some upstream budget left. Nothing is
+    // fetched in offline mode, so there is no quota to protect.
+    pub fn require_fetch(&self) -> Result<(), FetchRefused> {
+        if app::api::is_offline() {
+            return Ok(());
+        }
+        self.require(Scope::TriggerFetch)
+            .map_err(FetchRefused::MissingScope)?;
+        self.limits
+            .check_upstream(&self.client)
+            .map_err(|retry_after| {
+                FetchRefused::OverBudget(TooManyRequests::new(
+                    format!("{} has used up its api calls for now", self.client),
+                    retry_after,
+                ))
+            })
+    }
+
+    // Runs work that may call the api, charging the calls it makes to the caller.
+    pub async fn fetching<F: Future>(&self, work: F) -> F::Output {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let _charge = Charge {
+            caller: self,
+            calls: calls.clone(),
+        };
+        app::api::attributed(calls, work).await
+    }
+}
+
+// Charges the calls when dropped, so calls made before the api layer panics still count.
+struct Charge<'a> {
+    caller: &'a Caller,
+    calls: Arc<AtomicUsize>,
+}
+
+impl Drop for Charge<'_> {
+    fn drop(&mut self) {
+        self.caller
+            .limits
+            .charge_upstream(&self.caller.client, self.calls.load(Ordering::Relaxed));
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Caller {
+    type Error = String;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let limits = request
+            .rocket()
+            .state::<Limits>()
+            .expect("Callers need the limits to be managed")
+            .clone();
+        let key = match sent_key(request).await {
+            SentKey::Known(key) => Some(key.clone()),
+            SentKey::Unknown => {
+                return fail(request, Status::Unauthorized, "Unknown or revoked api key")
+            }
+            SentKey::None => None,
+        };
+        let client = match &key {
+            Some(key) => Client::Key(key.id),
+            None => limits::client_address(request),
+        };
+        if let Throttled(Some(_retry_after)) = request.local_cache(|| Throttled(None)) {
+            return fail(
+                request,
+                Status::TooManyRequests,
+                &format!("Too many requests from {client}"),
+            );
+        }
+        let caller = Caller {
+            key,
+            client,
+            limits,
+        };
+        if keys::reads_need_key() {
+            if let Err(message) = caller.require(Scope::ReadArchive) {
+                return fail(request, Status::Unauthorized, &message);
+            }
+        }
+        Outcome::Success(caller)
+    }
+}
+
+// The key of a caller with the admin scope, or 403.
+pub struct Admin(pub api_keys::Model);
+
+impl Admin {
+    // How the admin is named in the audit log.
+    pub fn actor(&self) -> String {
+        format!("api key {} ({})", self.0.name, self.0.prefix)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = String;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        scoped(request, Scope::Admin)
+            .await
+            .map(|(_caller, key)| Admin(key))
+    }
+}
+
+// A caller whose key may curate collections, tags and annotations, or 403.
+pub struct Curator(pub Caller);
+
+impl Curator {
+    // Who annotations are written by: the key's name, so a curator keeps their annotations
+    // when their key is replaced. Only one key that isn't revoked has a given name.
+    pub fn author(&self) -> String {
+        match &self.0.key {
+            Some(key) => key.name.clone(),
+            None => "unknown curator".to_string(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Curator {
+    type Error = String;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        scoped(request, Scope::Curate)
+            .await
+            .map(|(caller, _key)| Curator(caller))
+    }
+}
+
+// The caller and their key, if it has the scope.
+async fn scoped(request: &Request<'_>, scope: Scope) -> Outcome<(Caller, api_keys::Model), String> {
+    let caller = match request.guard::<Caller>().await {
+        Outcome::Success(caller) => caller,
+        Outcome::Failure(failure) => return Outcome::Failure(failure),
+        Outcome::Forward(forward) => return Outcome::Forward(forward),
+    };
+    match caller.require(scope).cloned() {
+        Ok(key) => Outcome::Success((caller, key)),
+        Err(message) => fail(request, Status::Forbidden, &message),
+    }
+}
+
+pub enum SentKey {
+    None,
+    Unknown,
+    Known(api_keys::Model),
+}
+
+// The key the request was sent with, as `Authorization: Bearer <key>` or `X-Api-Key: <key>`,
+// looked up once per request and marked used.
+pub async fn sent_key<'r>(request: &'r Request<'_>) -> &'r SentKey {
+    request
+        .local_cache_async(async {
+            let sent = request
+                .headers()
+                .get_one("Authorization")
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .or_else(|| request.headers().get_one("X-Api-Key"))
+                .map(str::trim);
+            let db = match request.guard::<&State<DatabaseConnection>>().await {
+                Outcome::Success(db) => db,
+                _ => panic!("Looking up api keys needs a managed database connection"),
+            };
+            match sent {
+                None => SentKey::None,
+                Some(sent) => match keys::find(db, sent).await {
+                    Some(key) => SentKey::Known(key),
+                    None => SentKey::Unknown,
+                },
+            }
+        })
+        .await
+}
+
+// Keeps the reason for the catchers to show, as a failed guard can't respond itself.
+fn fail<T>(request: &Request<'_>, status: Status, message: &str) -> Outcome<T, String> {
+    let message = message.to_owned();
+    request.local_cache(|| Refusal(message.clone()));
+    Outcome::Failure((status, message))
+}
+
+struct Refusal(String);
+
+// Set by the rate limiting fairing when a request's client has no request tokens left.
+pub struct Throttled(pub Option<Duration>);
+
+pub fn catchers() -> Vec<Catcher> {
+    catchers![unauthorized, forbidden, too_many_requests]
+}
+
+#[catch(401)]
+fn unauthorized(request: &Request<'_>) -> String {
+    refusal(request, "This needs an api key")
+}
+
+#[catch(403)]
+fn forbidden(request: &Request<'_>) -> String {
+    refusal(request, "This api key isn't allowed to do that")
+}
+
+#[derive(Responder)]
+#[response(status = 429)]
+pub struct TooManyRequests {
+    message: String,
+    retry_after: Header<'static>,
+}
+
+impl TooManyRequests {
+    fn new(message: String, retry_after: Duration) -> Self {
+        Self {
+            message,
+            // Rounded up, so a client that waits that long finds a token.
+            retry_after: Header::new("Retry-After", (retry_after.as_secs() + 1).to_string()),
+        }
+    }
+}
+
+#[catch(429)]
+fn too_many_requests(request: &Request<'_>) -> TooManyRequests {
+    let retry_after = request
+        .local_cache(|| Throttled(None))
+        .0
+        .unwrap_or(Duration::from_secs(60));
+    TooManyRequests::new(refusal(request, "Too many requests"), retry_after)
+}
+
+fn refusal(request: &Request<'_>, fallback: &str) -> String {
+    request
+        .local_cache(|| Refusal(fallback.to_owned()))
+        .0
+        .clone()
+}
@@ -0,0 +1,172 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use futures::future::join_all;
+use rocket::State;
+use sea_orm::{ActiveValue, DatabaseConnection, EntityTrait, QueryOrder};
+
+use crate::app::data::entities::prelude::*;
+use crate::app::data::entities::*;
+use crate::app::data::setup;
+use crate::utils::TweetData;
+
+pub const DEFAULT_TWEET_COUNT: usize = 10_000;
+const RUNS: usize = 5;
+const FIXTURE_BATCH_SIZE: usize = 500;
+
+// Compares reading every tweet with its references one query per tweet, as the list
+// routes used to, against loading the references for the whole list at once. Runs
+// against a fixture database in the temp directory so the archive isn't touched.
+pub async fn reads(tweet_count: usize) {
+    let path = std::env::temp_dir().join(format!(
+        "better-twitter-archiver-bench-{}.db",
+        std::process::id()
+    ));
+    let connection = setup::open_sqlite(&path)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to open {}. Error: {:?}", path.display(), error));
+    setup::migrate(&connection)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to run migrations. Error: {:?}", error));
+    let db = State::from(&connection);
+    println!("Seeding {tweet_count} tweets into {}", path.display());
+    fixture(db, tweet_count).await;
+
+    let (per_tweet, per_tweet_references) = time(|| async {
+        join_all(
+            all_tweet_models(db)
+                .await
+                .into_iter()
+                .map(|tweet_model| TweetData::read_from_data_model(db, tweet_model)),
+        )
+        .await
+    })
+    .await;
+    let (batched, batched_references) = time(|| async {
+        TweetData::read_many_from_data_models(db, all_tweet_models(db).await).await
+    })
+    .await;
+
+    if per_tweet_references != batched_references {
+        eprintln!(
+            "The reads loaded different references: {per_tweet_references} one query per tweet, \
+             {batched_references} batched"
+        );
+        std::process::exit(1);
+    }
+    println!(
+        "One query per tweet: {} queries, median {:?}",
+        tweet_count + 1,
+        per_tweet
+    );
+    println!("Batched: 2 queries, median {:?}", batched);
+    println!(
+        "Speedup: {:.1}x",
+        per_tweet.as_secs_f64() / batched.as_secs_f64()
+    );
+
+    // Dropping the connection first lets sqlite clean up its wal files.
+    drop(connection);
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+}
+
+async fn all_tweet_models(db: &State<DatabaseConnection>) -> Vec<tweets::Model> {
+    Tweets::find()
+        .order_by_asc(tweets::Column::Id)
+        .all(db as &DatabaseConnection)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to get tweets from database. Error: {:?}", error))
+}
+
+// Returns the median time over RUNS reads, along with how many references were loaded.
+async fn time<F, Fut>(read: F) -> (Duration, usize)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Vec<TweetData>>,
+{
+    let mut durations: Vec<Duration> = Vec::with_capacity(RUNS);
+    let mut references = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let tweets = read().await;
+        durations.push(start.elapsed());
+        references = tweets
+            .iter()
+            .map(|tweet_data| tweet_data.references.len())
+            .sum();
+    }
+    durations.sort();
+    (durations[RUNS / 2], references)
+}
+
+// Fills the database with `tweet_count` made up tweets from one user, in threads of ten
+// where every tweet but the first replies to the one before it.
+async fn fixture(db: &State<DatabaseConnection>, tweet_count: usize) {
+    let db = db as &DatabaseConnection;
+    let author_id = 1;
+    Users::insert(users::ActiveModel {
+        id: ActiveValue::set(author_id),
+        name: ActiveValue::set("Fixture".to_string()),
+        username: ActiveValue::set("fixture".to_string()),
+        description: ActiveValue::set("Generated for benchmarks".to_string()),
+    })
+    .exec(db)
+    .await
+    .unwrap_or_else(|error| panic!("Failed to insert fixture user. Error: {:?}", error));
+
+    let start = chrono::DateTime::parse_from_rfc3339("2020-01-01T00:00:00+00:00")
+        .expect("Failed to parse fixture start date");
+    let ids: Vec<i64> = (1..=tweet_count as i64).collect();
+    for chunk in ids.chunks(FIXTURE_BATCH_SIZE) {
+        let conversation_ids: Vec<i64> = chunk
+            .iter()
+            .copied()
+            .filter(|id| fixture_conversation_id(*id) == *id)
+            .collect();
+        if !conversation_ids.is_empty() {
+            Conversations::insert_many(conversation_ids.into_iter().map(|id| {
+                conversations::ActiveModel {
+                    id: ActiveValue::set(id),
+                }
+            }))
+            .exec(db)
+            .await
+            .unwrap_or_else(|error| {
+                panic!("Failed to insert fixture conversations. Error: {:?}", error)
+            });
+        }
+        Tweets::insert_many(chunk.iter().map(|id| tweets::ActiveModel {
+            id: ActiveValue::set(*id),
+            content: ActiveValue::set(format!("Fixture tweet {id}")),
+            author_id: ActiveValue::set(author_id),
+            conversation_id: ActiveValue::set(fixture_conversation_id(*id)),
+            created_at: ActiveValue::set(start + chrono::Duration::minutes(*id)),
+        }))
+        .exec(db)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to insert fixture tweets. Error: {:?}", error));
+        let replies: Vec<tweet_references::ActiveModel> = chunk
+            .iter()
+            .filter(|id| fixture_conversation_id(**id) != **id)
+            .map(|id| tweet_references::ActiveModel {
+                source_tweet_id: ActiveValue::set(*id),
+                reference_type: ActiveValue::set("replied_to".to_string()),
+                referenced_tweet_id: ActiveValue::set(id - 1),
+            })
+            .collect();
+        if !replies.is_empty() {
+            TweetReferences::insert_many(replies)
+                .exec(db)
+                .await
+                .unwrap_or_else(|error| {
+                    panic!("Failed to insert fixture references. Error: {:?}", error)
+                });
+        }
+    }
+}
+
+fn fixture_conversation_id(id: i64) -> i64 {
+    id - (id - 1) % 10
+}
@@ -0,0 +1,507 @@
+use std::fs;
+
+use rocket::State;
+
+use crate::app;
+use crate::app::data::setup;
+use crate::app::export::dump::DumpFormat;
+use crate::app::export::network::NetworkFormat;
+use crate::app::keys::{self, Scope};
+use crate::app::repair::RepairSource;
+#[cfg(feature = "bench")]
+use crate::bench;
+use crate::seed;
+use crate::utils;
+
+pub const USAGE: &str = "Usage: better-twitter-archiver-server [command]
+
+Commands:
+    serve                          Run the server (the default when no command is given)
+    migrate                        Run the migrations the database hasn't had yet
+    seed <handle> --ids <file>     Load the tweets listed in a ron file of tweet ids
+    sync <handle>                  Fetch the user's tweets newer than the latest archived one
+    check-tweets <handle>          Record which of the user's archived tweets are gone from twitter
+    import-archive <zip>           Import the tweets from a twitter archive zip
+    export [--out <file>]          Write every archived tweet as ron to stdout or a file
+    export-site <handle> --out <dir>
+                                   Write the user's archive as a static html site
+    export-markdown <handle> --out <dir>
+                                   Write the user's conversations as markdown notes
+    dump [--format ndjson|csv] [--out <path>]
+                                   Stream every table as ndjson to stdout or a file, or as
+                                   one csv file per table into a directory
+    restore [--format ndjson|csv] <path>
+                                   Load a dump written by the dump command
+    export-network [--format graphml|gexf] --out <file>
+                                   Write who replies to and quotes whom as a graph for
+                                   Gephi (graphml by default)
+    export-collection <id> [--format ndjson|csv] [--out <file>]
+                                   Write a collection's tweets with their notes and tags
+                                   to stdout or a file
+    search <query>                 Search the archived tweets
+    index-embeddings               Index the tweets that semantic search doesn't cover yet
+                                   (needs the semantic-search feature)
+    stats                          Count the rows in each table
+    verify [--repair [--from <dump>]] [--json]
+                                   Report rows that point at missing rows, duplicate ids and
+                                   unreadable dates. --repair fetches what's missing from the
+                                   api, or from an ndjson dump with --from, and prunes the
+                                   rest. Exits with 1 if problems remain
+    api-key create <name> --scopes <scopes> [--rate-limit <requests per minute>]
+                                   Make a key and print it. Scopes are a comma separated
+                                   list of read-archive, trigger-fetch, curate and admin
+    api-key list                   List the keys without the keys themselves
+    api-key revoke <id>            Stop a key from working
+    bench-reads [--tweets <count>] Time reading tweets with their references on a fixture
+                                   database (10000 tweets by default, needs the bench
+                                   feature)";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Serve,
+    Migrate,
+    Seed {
+        twitter_handle: String,
+        id_file: String,
+    },
+    Sync {
+        twitter_handle: String,
+    },
+    CheckTweets {
+        twitter_handle: String,
+    },
+    ImportArchive {
+        zip_path: String,
+    },
+    Export {
+        out: Option<String>,
+    },
+    ExportSite {
+        twitter_handle: String,
+        out_dir: String,
+    },
+    ExportMarkdown {
+        twitter_handle: String,
+        out_dir: String,
+    },
+    Dump {
+        format: DumpFormat,
+        out: Option<String>,
+    },
+    Restore {
+        format: DumpFormat,
+        path: String,
+    },
+    ExportNetwork {
+        format: NetworkFormat,
+        out: String,
+    },
+    ExportCollection {
+        id: i32,
+        format: DumpFormat,
+        out: Option<String>,
+    },
+    Search {
+        query: String,
+    },
+    IndexEmbeddings,
+    Stats,
+    Verify {
+        repair: Option<RepairSource>,
+        json: bool,
+    },
+    CreateApiKey {
+        name: String,
+        scopes: Vec<Scope>,
+        requests_per_minute: Option<i32>,
+    },
+    ListApiKeys,
+    RevokeApiKey {
+        id: i32,
+    },
+    BenchReads {
+        tweet_count: Option<usize>,
+    },
+}
+
+impl Command {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        match args.as_slice() {
+            [] | ["serve"] => Ok(Self::Serve),
+            ["migrate"] => Ok(Self::Migrate),
+            ["seed", twitter_handle, "--ids", id_file] => Ok(Self::Seed {
+                twitter_handle: twitter_handle.to_string(),
+                id_file: id_file.to_string(),
+            }),
+            ["sync", twitter_handle] => Ok(Self::Sync {
+                twitter_handle: twitter_handle.to_string(),
+            }),
+            ["check-tweets", twitter_handle] => Ok(Self::CheckTweets {
+                twitter_handle: twitter_handle.to_string(),
+            }),
+            ["import-archive", zip_path] => Ok(Self::ImportArchive {
+                zip_path: zip_path.to_string(),
+            }),
+            ["export"] => Ok(Self::Export { out: None }),
+            ["export", "--out", out] => Ok(Self::Export {
+                out: Some(out.to_string()),
+            }),
+            ["export-site", twitter_handle, "--out", out_dir] => Ok(Self::ExportSite {
+                twitter_handle: twitter_handle.to_string(),
+                out_dir: out_dir.to_string(),
+            }),
+            ["export-markdown", twitter_handle, "--out", out_dir] => Ok(Self::ExportMarkdown {
+                twitter_handle: twitter_handle.to_string(),
+                out_dir: out_dir.to_string(),
+            }),
+            ["dump"] => Ok(Self::Dump {
+                format: DumpFormat::Ndjson,
+                out: None,
+            }),
+            ["dump", "--out", out] => Ok(Self::Dump {
+                format: DumpFormat::Ndjson,
+                out: Some(out.to_string()),
+            }),
+            ["dump", "--format", format] => match format.parse()? {
+                DumpFormat::Csv => Err("dump --format csv needs --out <dir>".to_string()),
+                format => Ok(Self::Dump { format, out: None }),
+            },
+            ["dump", "--format", format, "--out", out] => Ok(Self::Dump {
+                format: format.parse()?,
+                out: Some(out.to_string()),
+            }),
+            ["restore", path] => Ok(Self::Restore {
+                format: DumpFormat::Ndjson,
+                path: path.to_string(),
+            }),
+            ["restore", "--format", format, path] => Ok(Self::Restore {
+                format: format.parse()?,
+                path: path.to_string(),
+            }),
+            ["export-network", "--out", out] => Ok(Self::ExportNetwork {
+                format: NetworkFormat::Graphml,
+                out: out.to_string(),
+            }),
+            ["export-network", "--format", format, "--out", out] => Ok(Self::ExportNetwork {
+                format: format.parse()?,
+                out: out.to_string(),
+            }),
+            ["export-collection", id, flags @ ..] => Self::parse_export_collection(id, flags),
+            ["search", query @ ..] if !query.is_empty() => Ok(Self::Search {
+                query: query.join(" "),
+            }),
+            ["index-embeddings"] => Ok(Self::IndexEmbeddings),
+            ["stats"] => Ok(Self::Stats),
+            ["verify", flags @ ..] => Self::parse_verify(flags),
+            ["api-key", "create", name, flags @ ..] => Self::parse_create_api_key(name, flags),
+            ["api-key", "list"] => Ok(Self::ListApiKeys),
+            ["api-key", "revoke", id] => Ok(Self::RevokeApiKey {
+                id: id
+                    .parse()
+                    .map_err(|_| format!("Expected the id of a key, got {id}"))?,
+            }),
+            ["bench-reads"] => Ok(Self::BenchReads { tweet_count: None }),
+            ["bench-reads", "--tweets", tweet_count] => Ok(Self::BenchReads {
+                tweet_count: Some(
+                    tweet_count
+                        .parse()
+                        .map_err(|_| format!("Expected a number of tweets, got {tweet_count}"))?,
+                ),
+            }),
+            _ => Err(format!(
+                "Unrecognised command: {}\n\n{USAGE}",
+                args.join(" ")
+            )),
+        }
+    }
+
+    fn parse_export_collection(id: &str, flags: &[&str]) -> Result<Self, String> {
+        let id = id
+            .parse()
+            .map_err(|_| format!("Expected the id of a collection, got {id}"))?;
+        let mut format = DumpFormat::Ndjson;
+        let mut out = None;
+        let mut flags = flags.iter();
+        while let Some(flag) = flags.next() {
+            match *flag {
+                "--format" => {
+                    format = flags
+                        .next()
+                        .ok_or("export-collection --format needs ndjson or csv")?
+                        .parse()?
+                }
+                "--out" => {
+                    out = Some(
+                        flags
+                            .next()
+                            .ok_or("export-collection --out needs a file")?
+                            .to_string(),
+                    )
+                }
+                flag => {
+                    return Err(format!(
+                        "Unrecognised export-collection flag: {flag}\n\n{USAGE}"
+                    ))
+                }
+            }
+        }
+        Ok(Self::ExportCollection { id, format, out })
+    }
+
+    fn parse_verify(flags: &[&str]) -> Result<Self, String> {
+        let mut repair = false;
+        let mut from = None;
+        let mut json = false;
+        let mut flags = flags.iter();
+        while let Some(flag) = flags.next() {
+            match *flag {
+                "--repair" => repair = true,
+                "--json" => json = true,
+                "--from" => {
+                    from = Some(
+                        flags
+                            .next()
+                            .ok_or("verify --from needs the path of an ndjson dump")?
+                            .to_string(),
+                    )
+                }
+                flag => return Err(format!("Unrecognised verify flag: {flag}\n\n{USAGE}")),
+            }
+        }
+        let repair = match (repair, from) {
+            (false, Some(_)) => return Err("verify --from only applies with --repair".to_string()),
+            (false, None) => None,
+            (true, None) => Some(RepairSource::Api),
+            (true, Some(path)) => Some(RepairSource::Dump(path)),
+        };
+        Ok(Self::Verify { repair, json })
+    }
+
+    fn parse_create_api_key(name: &str, flags: &[&str]) -> Result<Self, String> {
+        let mut scopes = None;
+        let mut requests_per_minute = None;
+        let mut flags = flags.iter();
+        while let Some(flag) = flags.next() {
+            match *flag {
+                "--scopes" => {
+                    scopes = Some(keys::parse_scopes(
+                        flags.next().ok_or("api-key create --scopes needs a list of scopes")?,
+                    )?)
+                }
+                "--rate-limit" => {
+                    let limit = flags
+                        .next()
+                        .ok_or("api-key create --rate-limit needs a number of requests per minute")?;
+                    requests_per_minute = match limit.parse() {
+                        Ok(limit) if limit > 0 => Some(limit),
+                        _ => {
+                            return Err(format!(
+                                "Expected a positive number of requests per minute, got {limit}"
+                            ))
+                        }
+                    };
+                }
+                flag => return Err(format!("Unrecognised api-key flag: {flag}\n\n{USAGE}")),
+            }
+        }
+        Ok(Self::CreateApiKey {
+            name: name.to_string(),
+            scopes: scopes.ok_or("api-key create needs --scopes")?,
+            requests_per_minute,
+        })
+    }
+}
+
+// Runs every command except serve, which main handles since it owns the routes. The
+// database is migrated first, as it is for serve, so every command finds its tables.
+pub async fn run(command: Command) {
+    let db = match setup::set_up_db().await {
+        Ok(db) => db,
+        Err(err) => panic!("{}", err),
+    };
+    setup::migrate(&db)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to run migrations. Error: {:?}", error));
+    let db = State::from(&db);
+    match command {
+        Command::Serve => unreachable!("serve is handled by main"),
+        Command::Migrate => println!("Database is up to date"),
+        Command::Seed {
+            twitter_handle,
+            id_file,
+        } => seed::tweets_from_id_file(db, &twitter_handle, &id_file).await,
+        Command::Sync { twitter_handle } => {
+            match app::sync_users_new_tweets(db, &twitter_handle).await {
+                Ok(new_tweets) => println!(
+                    "Added {} new tweets for @{twitter_handle}",
+                    new_tweets.len()
+                ),
+                Err(error) => {
+                    eprintln!("{error}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::CheckTweets { twitter_handle } => println!(
+            "{}",
+            utils::to_ron(&app::check_users_tweets_status(db, &twitter_handle).await)
+        ),
+        Command::ImportArchive { zip_path } => {
+            match app::archive::import(db, &zip_path).await {
+                Ok(imported) => println!("Imported {imported} tweets from {zip_path}"),
+                Err(error) => {
+                    eprintln!("{error}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Export { out } => {
+            let output = utils::to_ron(&app::data::read::tweets(db).await);
+            match out {
+                Some(out) => fs::write(&out, output)
+                    .unwrap_or_else(|error| panic!("Failed to write {out}. Error: {:?}", error)),
+                None => println!("{output}"),
+            }
+        }
+        Command::ExportSite {
+            twitter_handle,
+            out_dir,
+        } => app::export::site::export(db, &twitter_handle, &out_dir).await,
+        Command::ExportMarkdown {
+            twitter_handle,
+            out_dir,
+        } => app::export::markdown::export(db, &twitter_handle, &out_dir).await,
+        Command::Dump { format, out } => match (format, out) {
+            (DumpFormat::Ndjson, Some(out)) => {
+                let file = fs::File::create(&out)
+                    .unwrap_or_else(|error| panic!("Failed to create {out}. Error: {:?}", error));
+                let written = app::export::dump::export_ndjson(db, file).await;
+                println!("Dumped {written} records to {out}");
+            }
+            // The dump itself goes to stdout, so the summary goes to stderr.
+            (DumpFormat::Ndjson, None) => {
+                let written = app::export::dump::export_ndjson(db, std::io::stdout()).await;
+                eprintln!("Dumped {written} records");
+            }
+            (DumpFormat::Csv, Some(out)) => {
+                let written = app::export::dump::export_csv(db, &out).await;
+                println!("Dumped {written} rows to {out}");
+            }
+            (DumpFormat::Csv, None) => unreachable!("csv dumps are parsed with an out dir"),
+        },
+        Command::Restore { format, path } => {
+            let imported = match format {
+                DumpFormat::Ndjson => app::export::dump::import_ndjson(db, &path).await,
+                DumpFormat::Csv => app::export::dump::import_csv(db, &path).await,
+            };
+            println!("Restored {imported} records from {path}");
+        }
+        Command::ExportNetwork { format, out } => {
+            let graph = app::network::interaction_graph(db).await;
+            fs::write(&out, app::export::network::to_format(&graph, format))
+                .unwrap_or_else(|error| panic!("Failed to write {out}. Error: {:?}", error));
+            println!(
+                "Wrote {} users and {} interactions to {out}",
+                graph.nodes.len(),
+                graph.edges.len()
+            );
+        }
+        Command::ExportCollection { id, format, out } => {
+            let collection = app::collections::load(db, id)
+                .await
+                .unwrap_or_else(|error| panic!("Failed to load collection {id}. Error: {:?}", error));
+            let export = app::export::collection::to_format(&collection, format);
+            match out {
+                Some(out) => {
+                    fs::write(&out, export).unwrap_or_else(|error| {
+                        panic!("Failed to write {out}. Error: {:?}", error)
+                    });
+                    println!(
+                        "Wrote {} tweets from {} to {out}",
+                        collection.tweets.len(),
+                        collection.collection.name
+                    );
+                }
+                None => print!("{export}"),
+            }
+        }
+        Command::Search { query } => {
+            println!(
+                "{}",
+                utils::to_ron(&app::search_tweets_in_db(db, &query, &Default::default()).await)
+            )
+        }
+        #[cfg(feature = "semantic-search")]
+        Command::IndexEmbeddings => {
+            let indexed = app::semantic::index_missing(db).await;
+            println!("Indexed {indexed} tweets for semantic search");
+        }
+        #[cfg(not(feature = "semantic-search"))]
+        Command::IndexEmbeddings => {
+            eprintln!("Semantic search isn't enabled, build with --features semantic-search");
+            std::process::exit(1);
+        }
+        Command::Stats => println!("{}", utils::to_ron(&app::data::read::stats(db).await)),
+        Command::Verify { repair, json } => {
+            let (output, is_ok) = match repair {
+                Some(source) => {
+                    let report = app::repair::repair(db, &source).await;
+                    let is_ok = report.after.is_ok();
+                    (to_report(&report, json), is_ok)
+                }
+                None => {
+                    let report = app::data::read::verify(db).await;
+                    (to_report(&report, json), report.is_ok())
+                }
+            };
+            println!("{output}");
+            if !is_ok {
+                std::process::exit(1);
+            }
+        }
+        Command::CreateApiKey {
+            name,
+            scopes,
+            requests_per_minute,
+        } => {
+            match keys::create(db, &name, &scopes, requests_per_minute).await {
+                Ok((key, model)) => {
+                    println!("{}", utils::to_ron(&model));
+                    println!("Key {}: {key}", model.id);
+                    println!("This is the only time the key is shown, so keep it somewhere safe");
+                }
+                Err(error) => {
+                    eprintln!("{error}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::ListApiKeys => println!("{}", utils::to_ron(&keys::list(db).await)),
+        Command::RevokeApiKey { id } => {
+            if keys::revoke(db, id).await {
+                println!("Revoked key {id}");
+            } else {
+                eprintln!("There is no key {id} that isn't already revoked");
+                std::process::exit(1);
+            }
+        }
+        #[cfg(feature = "bench")]
+        Command::BenchReads { tweet_count } => {
+            bench::reads(tweet_count.unwrap_or(bench::DEFAULT_TWEET_COUNT)).await
+        }
+        #[cfg(not(feature = "bench"))]
+        Command::BenchReads { .. } => {
+            eprintln!("Benchmarks aren't built in, build with --features bench");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn to_report<T: serde::Serialize>(report: &T, json: bool) -> String {
+    if json {
+        serde_json::to_string_pretty(report).expect("Failed to serialize the report")
+    } else {
+        utils::to_ron(report)
+    }
+}
@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rocket::fairing::AdHoc;
+
+use crate::auth::{self, SentKey};
+use crate::utils::ClientUsage;
+
+// Limits for clients without a key or whose key doesn't set its own, unless overridden by
+// the REQUESTS_PER_MINUTE and UPSTREAM_CALLS_PER_HOUR environment variables.
+pub const DEFAULT_REQUESTS_PER_MINUTE: u32 = 60;
+pub const DEFAULT_UPSTREAM_CALLS_PER_HOUR: u32 = 100;
+// How often clients that have gone quiet are forgotten.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+// Whoever a request is put down to: the key it was sent with, or the address it came from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Client {
+    Key(i32),
+    Address(IpAddr),
+    Unknown,
+}
+
+impl fmt::Display for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Key(id) => write!(f, "key {id}"),
+            Self::Address(address) => write!(f, "{address}"),
+            Self::Unknown => f.write_str("unknown client"),
+        }
+    }
+}
+
+// Token buckets per client for requests and for the api calls those requests cause, and a
+// running count of both. Kept in memory, so they start over when the server restarts.
+// Addresses are forgotten once both their buckets have refilled, as a fresh client would
+// start with full buckets anyway; keys are few, so they are kept for their usage.
+#[derive(Clone)]
+pub struct Limits {
+    clients: Arc<Mutex<Clients>>,
+    requests_per_minute: u32,
+    upstream_calls_per_hour: u32,
+}
+
+struct Clients {
+    states: HashMap<Client, ClientState>,
+    pruned_at: Instant,
+}
+
+struct ClientState {
+    requests: Bucket,
+    upstream: Bucket,
+    usage: ClientUsage,
+}
+
+impl Limits {
+    pub fn from_env() -> Self {
+        Self::new(
+            env_limit("REQUESTS_PER_MINUTE", DEFAULT_REQUESTS_PER_MINUTE),
+            env_limit("UPSTREAM_CALLS_PER_HOUR", DEFAULT_UPSTREAM_CALLS_PER_HOUR),
+        )
+    }
+
+    fn new(requests_per_minute: u32, upstream_calls_per_hour: u32) -> Self {
+        Self {
+            clients: Arc::new(Mutex::new(Clients {
+                states: HashMap::new(),
+                pruned_at: Instant::now(),
+            })),
+            requests_per_minute,
+            upstream_calls_per_hour,
+        }
+    }
+
+    // Takes a request token for the client, or says how long until there is one.
+    pub fn take_request(
+        &self,
+        client: &Client,
+        requests_per_minute: Option<u32>,
+    ) -> Result<(), Duration> {
+        self.with_client(client, |state| {
+            if let Some(requests_per_minute) = requests_per_minute {
+                state
+                    .requests
+                    .resize(requests_per_minute as f64, Duration::from_secs(60));
+            }
+            state.usage.requests += 1;
+            let taken = state.requests.take();
+            if taken.is_err() {
+                state.usage.limited_requests += 1;
+            }
+            taken
+        })
+    }
+
+    // Whether the client has any upstream budget left, or how long until it will.
+    pub fn check_upstream(&self, client: &Client) -> Result<(), Duration> {
+        self.with_client(client, |state| {
+            let wait = state.upstream.wait_for_one();
+            if wait.is_some() {
+                state.usage.limited_requests += 1;
+            }
+            wait.map_or(Ok(()), Err)
+        })
+    }
+
+    // Takes the calls a request made from the client's upstream budget. It can go below
+    // empty, so a request that made many calls keeps the client waiting longer.
+    pub fn charge_upstream(&self, client: &Client, calls: usize) {
+        if calls == 0 {
+            return;
+        }
+        self.with_client(client, |state| {
+            state.upstream.spend(calls as f64);
+            state.usage.upstream_calls += calls as u64;
+        })
+    }
+
+    pub fn usage(&self) -> Vec<ClientUsage> {
+        let mut usage: Vec<ClientUsage> = self
+            .lock()
+            .states
+            .values()
+            .map(|state| state.usage.clone())
+            .collect();
+        usage.sort_by(|a, b| {
+            b.upstream_calls
+                .cmp(&a.upstream_calls)
+                .then(b.requests.cmp(&a.requests))
+                .then(a.client.cmp(&b.client))
+        });
+        usage
+    }
+
+    fn with_client<T>(&self, client: &Client, f: impl FnOnce(&mut ClientState) -> T) -> T {
+        let mut clients = self.lock();
+        if clients.pruned_at.elapsed() >= PRUNE_INTERVAL {
+            clients.prune();
+        }
+        let state = clients
+            .states
+            .entry(client.clone())
+            .or_insert_with(|| ClientState {
+                requests: Bucket::full(self.requests_per_minute as f64, Duration::from_secs(60)),
+                upstream: Bucket::full(
+                    self.upstream_calls_per_hour as f64,
+                    Duration::from_secs(60 * 60),
+                ),
+                usage: ClientUsage {
+                    client: client.to_string(),
+                    requests: 0,
+                    limited_requests: 0,
+                    upstream_calls: 0,
+                },
+            });
+        f(state)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Clients> {
+        self.clients
+            .lock()
+            .unwrap_or_else(|error| panic!("Failed to lock the rate limits. Error: {:?}", error))
+    }
+}
+
+impl Clients {
+    fn prune(&mut self) {
+        self.states.retain(|client, state| {
+            matches!(client, Client::Key(_))
+                || !(state.requests.is_full() && state.upstream.is_full())
+        });
+        self.pruned_at = Instant::now();
+    }
+}
+
+// Holds up to `capacity` tokens and refills it over `period`, continuously.
+struct Bucket {
+    tokens: f64,
+    capacity: f64,
+    period: Duration,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn full(capacity: f64, period: Duration) -> Self {
+        Self {
+            tokens: capacity,
+            capacity,
+            period,
+            updated_at: Instant::now(),
+        }
+    }
+
+    // For a key whose own limit differs from the one the bucket was made with.
+    fn resize(&mut self, capacity: f64, period: Duration) {
+        if self.capacity != capacity || self.period != period {
+            self.refill();
+            self.capacity = capacity;
+            self.period = period;
+            self.tokens = self.tokens.min(capacity);
+        }
+    }
+
+    fn take(&mut self) -> Result<(), Duration> {
+        match self.wait_for_one() {
+            Some(wait) => Err(wait),
+            None => {
+                self.tokens -= 1.0;
+                Ok(())
+            }
+        }
+    }
+
+    fn spend(&mut self, tokens: f64) {
+        self.refill();
+        self.tokens -= tokens;
+    }
+
+    fn wait_for_one(&mut self) -> Option<Duration> {
+        self.refill();
+        if self.tokens >= 1.0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(
+                (1.0 - self.tokens) / self.per_second(),
+            ))
+        }
+    }
+
+    fn is_full(&mut self) -> bool {
+        self.refill();
+        self.tokens >= self.capacity
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second()).min(self.capacity);
+        self.updated_at = now;
+    }
+
+    fn per_second(&self) -> f64 {
+        self.capacity.max(1.0) / self.period.as_secs_f64()
+    }
+}
+
+// Takes a request token for each request's client before it's routed. Requests without
+// one are refused by the `auth::Caller` guard, which every route has, the index included.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_request("Rate limits", |request, _data| {
+        Box::pin(async move {
+            let limits = request
+                .rocket()
+                .state::<Limits>()
+                .expect("Rate limiting needs the limits to be managed")
+                .clone();
+            let (client, requests_per_minute) = match auth::sent_key(request).await {
+                SentKey::Known(key) => (
+                    Client::Key(key.id),
+                    key.requests_per_minute.map(|limit| limit.max(1) as u32),
+                ),
+                SentKey::Unknown | SentKey::None => (client_address(request), None),
+            };
+            if let Err(retry_after) = limits.take_request(&client, requests_per_minute) {
+                request.local_cache(|| auth::Throttled(Some(retry_after)));
+            }
+        })
+    })
+}
+
+pub fn client_address(request: &rocket::Request<'_>) -> Client {
+    request.client_ip().map_or(Client::Unknown, Client::Address)
+}
+
+fn env_limit(name: &str, default: u32) -> u32 {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{name} must be a whole number, got {value}")),
+        Err(_) => default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Moves the bucket's last refill into the past, as if that much time had gone by.
+    fn age(bucket: &mut Bucket, elapsed: Duration) {
+        bucket.updated_at -= elapsed;
+    }
+
+    #[test]
+    fn a_full_bucket_allows_a_burst_of_its_capacity() {
+        let mut bucket = Bucket::full(3.0, Duration::from_secs(60));
+        for _ in 0..3 {
+            assert!(bucket.take().is_ok());
+        }
+        assert!(bucket.take().is_err());
+    }
+
+    #[test]
+    fn an_empty_bucket_says_how_long_until_the_next_token() {
+        let mut bucket = Bucket::full(60.0, Duration::from_secs(60));
+        bucket.spend(60.0);
+        let wait = bucket.take().unwrap_err();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn a_bucket_refills_with_time_up_to_its_capacity() {
+        let mut bucket = Bucket::full(60.0, Duration::from_secs(60));
+        bucket.spend(60.0);
+        age(&mut bucket, Duration::from_secs(30));
+        bucket.refill();
+        assert!(bucket.tokens >= 30.0 && bucket.tokens < 31.0);
+        age(&mut bucket, Duration::from_secs(60 * 60));
+        assert!(bucket.is_full());
+        assert_eq!(bucket.tokens, 60.0);
+    }
+
+    #[test]
+    fn spending_past_empty_makes_the_wait_longer() {
+        let mut bucket = Bucket::full(60.0, Duration::from_secs(60));
+        bucket.spend(70.0);
+        let wait = bucket.wait_for_one().unwrap();
+        assert!(wait > Duration::from_secs(10) && wait <= Duration::from_secs(11));
+    }
+
+    #[test]
+    fn a_smaller_limit_caps_the_tokens_left() {
+        let mut bucket = Bucket::full(60.0, Duration::from_secs(60));
+        bucket.resize(2.0, Duration::from_secs(60));
+        assert!(bucket.take().is_ok());
+        assert!(bucket.take().is_ok());
+        assert!(bucket.take().is_err());
+    }
+
+    #[test]
+    fn clients_over_the_limit_are_refused_and_counted() {
+        let limits = Limits::new(2, 100);
+        let client = Client::Key(1);
+        assert!(limits.take_request(&client, None).is_ok());
+        assert!(limits.take_request(&client, None).is_ok());
+        assert!(limits.take_request(&client, None).is_err());
+        let usage = limits.usage();
+        assert_eq!(usage[0].requests, 3);
+        assert_eq!(usage[0].limited_requests, 1);
+    }
+
+    #[test]
+    fn pruning_forgets_only_addresses_with_full_buckets() {
+        let limits = Limits::new(60, 100);
+        let idle = Client::Address(IpAddr::from([127, 0, 0, 1]));
+        let busy = Client::Address(IpAddr::from([127, 0, 0, 2]));
+        let key = Client::Key(1);
+        for client in [&idle, &busy, &key] {
+            limits.take_request(client, None).unwrap();
+        }
+        limits.charge_upstream(&busy, 10);
+        {
+            let mut clients = limits.lock();
+            for client in [&idle, &key] {
+                let state = clients.states.get_mut(client).unwrap();
+                age(&mut state.requests, Duration::from_secs(60));
+            }
+            clients.prune();
+            assert!(!clients.states.contains_key(&idle));
+            assert!(clients.states.contains_key(&busy));
+            assert!(clients.states.contains_key(&key));
+        }
+    }
+}
@@ -1,127 +1,1113 @@
-use chrono::FixedOffset;
+use chrono::{DateTime, FixedOffset};
+use futures::StreamExt;
+use rocket::http::ContentType;
+use rocket::response::content::RawHtml;
+use rocket::response::stream::TextStream;
 use rocket::*;
 mod app;
+mod auth;
+#[cfg(feature = "bench")]
+mod bench;
+mod cli;
+mod limits;
+mod scheduler;
 mod seed;
 
 use app::data::setup;
 use dotenvy::dotenv;
 
 use sea_orm::{DatabaseConnection, DbErr};
+use utils::{TweetData, UserData};
 mod utils;
 
+// The reading interface: plain html pages for people rather than programs, under /ui.
 #[get("/")]
-async fn index() -> &'static str {
-    "Welcome to the better twitter archiver server!"
+async fn index(db: &State<DatabaseConnection>, _caller: auth::Caller) -> RawHtml<String> {
+    RawHtml(app::web::home(db).await)
 }
 
+#[get("/ui/user/<twitter_handle>?<page>")]
+async fn user_page(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+    twitter_handle: &str,
+    page: Option<usize>,
+) -> Result<RawHtml<String>, PageNotFoundResponder> {
+    match app::web::user(db, twitter_handle, app::web::page_index(page)).await {
+        Some(page) => Ok(RawHtml(page)),
+        None => Err(PageNotFoundResponder::new(&format!("user @{twitter_handle}"))),
+    }
+}
+
+#[get("/ui/conversation/<id>")]
+async fn conversation_page(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+    id: i64,
+) -> Result<RawHtml<String>, PageNotFoundResponder> {
+    match app::web::conversation(db, id).await {
+        Some(page) => Ok(RawHtml(page)),
+        None => Err(PageNotFoundResponder::new(&format!("conversation {id}"))),
+    }
+}
+
+#[get("/ui/tweet/<id>")]
+async fn tweet_page(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+    id: i64,
+) -> Result<RawHtml<String>, PageNotFoundResponder> {
+    match app::web::tweet(db, id).await {
+        Some(page) => Ok(RawHtml(page)),
+        None => Err(PageNotFoundResponder::new(&format!("tweet {id}"))),
+    }
+}
+
+// Empty fields of the search form are left out of the search.
+#[get("/ui/search?<q>&<collection>&<tag>&<page>")]
+async fn search_page(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+    q: Option<&str>,
+    collection: Option<&str>,
+    tag: Option<&str>,
+    page: Option<usize>,
+) -> RawHtml<String> {
+    let form = app::web::SearchForm::parse(q, collection, tag, page);
+    RawHtml(app::web::search(db, &form).await)
+}
+
+// Every archived tweet as ndjson, one line per tweet, read a page at a time as the client
+// consumes it so memory use doesn't grow with the archive.
 #[get("/tweets")]
-async fn tweets(db: &State<DatabaseConnection>) -> String {
-    utils::to_ron(&app::data::read::tweets(db).await)
+fn tweets(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+) -> (ContentType, TextStream![String]) {
+    let db = db.inner().clone();
+    (
+        ndjson(),
+        TextStream! {
+            let mut tweets = Box::pin(app::data::read::tweets_stream(&db));
+            while let Some(tweet_data) = tweets.next().await {
+                yield utils::to_ndjson_line(&tweet_data);
+            }
+        },
+    )
 }
 
 #[get("/users")]
-async fn users(db: &State<DatabaseConnection>) -> String {
-    utils::to_ron(&app::data::read::users(db).await)
+fn users(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+) -> (ContentType, TextStream![String]) {
+    let db = db.inner().clone();
+    (
+        ndjson(),
+        TextStream! {
+            let mut users = Box::pin(app::data::read::users_stream(&db));
+            while let Some(user_data) = users.next().await {
+                yield utils::to_ndjson_line(&user_data);
+            }
+        },
+    )
+}
+
+fn ndjson() -> ContentType {
+    ContentType::new("application", "x-ndjson")
 }
 
 #[get("/userbyid/<id>")]
-async fn user_by_id(db: &State<DatabaseConnection>, id: i64) -> String {
-    utils::to_ron(&app::load_user_from_id(db, id).await)
+async fn user_by_id(
+    db: &State<DatabaseConnection>,
+    caller: auth::Caller,
+    id: i64,
+) -> Result<String, RequestError> {
+    let mut user_data = app::data::read::user_by_id(db, id).await;
+    if user_data.user.is_none() {
+        caller.require_fetch()?;
+        user_data = caller.fetching(app::load_user_from_id(db, id)).await;
+    }
+    match &user_data.user {
+        Some(_user) => Ok(utils::to_ron(&user_data)),
+        None => Err(NotArchivedResponder::new(format!("user of id {id}")).into()),
+    }
 }
 
 #[get("/user/<twitter_handle>")]
-async fn user_by_twitter_handle(db: &State<DatabaseConnection>, twitter_handle: &str) -> String {
-    utils::to_ron(&app::load_user_from_twitter_handle(db, twitter_handle).await)
+async fn user_by_twitter_handle(
+    db: &State<DatabaseConnection>,
+    caller: auth::Caller,
+    twitter_handle: &str,
+) -> Result<String, RequestError> {
+    Ok(utils::to_ron(&archived_user(db, &caller, twitter_handle).await?))
 }
 
 #[get("/user/<twitter_handle>/info")]
 async fn user_info_by_twitter_handle(
     db: &State<DatabaseConnection>,
+    caller: auth::Caller,
     twitter_handle: &str,
-) -> String {
-    let output = utils::to_ron(&app::load_user_from_twitter_handle(db, twitter_handle).await);
+) -> Result<String, RequestError> {
+    let output = utils::to_ron(&archived_user(db, &caller, twitter_handle).await?);
     println!("{}", output);
-    output
+    Ok(output)
+}
+// Tweet counts over time, when in the week the user tweets, what kind of tweets they are,
+// their longest posting streaks and how long their threads run.
+#[get("/user/<twitter_handle>/stats")]
+async fn user_stats(
+    db: &State<DatabaseConnection>,
+    caller: auth::Caller,
+    twitter_handle: &str,
+) -> Result<String, RequestError> {
+    let user_id = archived_user_id(db, &caller, twitter_handle).await?;
+    Ok(utils::to_ron(&app::data::read::user_activity(db, user_id).await))
+}
+// The users this user replies to, quotes or hears from most, busiest first.
+#[get("/user/<twitter_handle>/interlocutors?<limit>")]
+async fn user_interlocutors(
+    db: &State<DatabaseConnection>,
+    caller: auth::Caller,
+    twitter_handle: &str,
+    limit: Option<usize>,
+) -> Result<String, RequestError> {
+    let user_id = archived_user_id(db, &caller, twitter_handle).await?;
+    let limit = limit.unwrap_or(app::network::DEFAULT_INTERLOCUTORS);
+    Ok(utils::to_ron(
+        &app::network::top_interlocutors(db, user_id, limit).await,
+    ))
+}
+
+// The user's most used words and word pairs, overall and by year or month in `tz`, between
+// the optional `from` and `to` dates.
+#[get("/user/<twitter_handle>/terms?<from>&<to>&<tz>&<period>&<limit>")]
+#[allow(clippy::too_many_arguments)]
+async fn user_terms(
+    db: &State<DatabaseConnection>,
+    caller: auth::Caller,
+    twitter_handle: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+    tz: Option<&str>,
+    period: Option<&str>,
+    limit: Option<usize>,
+) -> Result<String, RequestError> {
+    let (window, period) = term_query(from, to, tz, period)?;
+    let user_id = archived_user_id(db, &caller, twitter_handle).await?;
+    let limit = limit.unwrap_or(app::vocabulary::DEFAULT_TERM_LIMIT);
+    Ok(utils::to_ron(
+        &app::vocabulary::user_terms(db, user_id, &window, period, limit).await,
+    ))
 }
+
+// Words the user started using much more in each period than in the one before.
+#[get("/user/<twitter_handle>/terms/emerging?<from>&<to>&<tz>&<period>&<limit>")]
+#[allow(clippy::too_many_arguments)]
+async fn user_emerging_terms(
+    db: &State<DatabaseConnection>,
+    caller: auth::Caller,
+    twitter_handle: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+    tz: Option<&str>,
+    period: Option<&str>,
+    limit: Option<usize>,
+) -> Result<String, RequestError> {
+    let (window, period) = term_query(from, to, tz, period)?;
+    let user_id = archived_user_id(db, &caller, twitter_handle).await?;
+    let limit = limit.unwrap_or(app::vocabulary::DEFAULT_TERM_LIMIT);
+    Ok(utils::to_ron(
+        &app::vocabulary::emerging_terms(db, user_id, &window, period, limit).await,
+    ))
+}
+
+fn term_query(
+    from: Option<&str>,
+    to: Option<&str>,
+    tz: Option<&str>,
+    period: Option<&str>,
+) -> Result<(app::dates::DateWindow, app::vocabulary::TermPeriod), BadRequestResponder> {
+    let window = app::dates::DateWindow::parse(from, to, tz).map_err(BadRequestResponder::new)?;
+    let period = period
+        .unwrap_or("year")
+        .parse()
+        .map_err(BadRequestResponder::new)?;
+    Ok((window, period))
+}
+
 //you may wish to get rid of this route
 #[get("/user/<twitter_handle>/latest")]
-async fn users_latest_tweet_by_id(db: &State<DatabaseConnection>, twitter_handle: &str) -> String {
-    utils::to_ron(&app::load_offset_datetime_for_users_latest_tweet(db, twitter_handle).await)
+async fn users_latest_tweet_by_id(
+    db: &State<DatabaseConnection>,
+    caller: auth::Caller,
+    twitter_handle: &str,
+) -> Result<String, RequestError> {
+    let user_id = archived_user_id(db, &caller, twitter_handle).await?;
+    let latest = app::load_offset_datetime_for_users_latest_tweet_in_database(db, user_id)
+        .await
+        .ok_or_else(|| NotArchivedResponder::new(format!("tweets of @{twitter_handle}")))?;
+    Ok(utils::to_ron(&latest))
 }
 
-#[get("/user/<twitter_handle>/has_tweeted_since/<rfc3339_date>")]
+// The date can be anything `from` takes on the routes below.
+#[get("/user/<twitter_handle>/has_tweeted_since/<date>?<sync>&<tz>")]
 async fn has_user_tweeted_since_date(
     db: &State<DatabaseConnection>,
+    caller: auth::Caller,
     twitter_handle: &str,
-    rfc3339_date: &str,
-) -> String {
-    let date_timestamp = chrono::DateTime::<FixedOffset>::parse_from_rfc3339(rfc3339_date)
-        .expect("Failed to parse date")
-        .timestamp();
-    utils::to_ron(&app::has_user_tweeted_since_date(db, twitter_handle, date_timestamp).await)
+    date: &str,
+    sync: bool,
+    tz: Option<&str>,
+) -> Result<String, RequestError> {
+    let date = since_query(date, tz)?;
+    allow_sync(&caller, sync)?;
+    let user_id = archived_user_id(db, &caller, twitter_handle).await?;
+    Ok(utils::to_ron(
+        &caller
+            .fetching(app::has_user_tweeted_since_date(
+                db,
+                twitter_handle,
+                user_id,
+                date.timestamp(),
+                sync,
+            ))
+            .await,
+    ))
 }
 
-#[get("/user/<twitter_handle>/tweets-since/<rfc3339_date>")]
+#[get("/user/<twitter_handle>/tweets-since/<date>?<sync>&<tz>")]
 async fn users_tweets_since_date(
     db: &State<DatabaseConnection>,
+    caller: auth::Caller,
+    twitter_handle: &str,
+    date: &str,
+    sync: bool,
+    tz: Option<&str>,
+) -> Result<String, RequestError> {
+    let date = since_query(date, tz)?;
+    allow_sync(&caller, sync)?;
+    let user_id = archived_user_id(db, &caller, twitter_handle).await?;
+    Ok(utils::to_ron(
+        &caller
+            .fetching(app::load_users_tweets_since_date(
+                db,
+                twitter_handle,
+                user_id,
+                date,
+                sync,
+            ))
+            .await,
+    ))
+}
+
+fn since_query(
+    date: &str,
+    tz: Option<&str>,
+) -> Result<DateTime<FixedOffset>, BadRequestResponder> {
+    let window =
+        app::dates::DateWindow::parse(Some(date), None, tz).map_err(BadRequestResponder::new)?;
+    window
+        .from
+        .ok_or_else(|| BadRequestResponder::new(format!("Expected a date, got {date}")))
+}
+
+// `from` and `to` can each be an RFC3339 date and time, a date and time or a YYYY-MM-DD
+// date read in `tz`, now, today, yesterday, or a time ago like 7d. A plain date as `to`
+// includes that whole day. `tz` is an offset like +05:30 and defaults to UTC.
+#[get("/user/<twitter_handle>/tweets?<sync>&<from>&<to>&<tz>")]
+async fn users_tweets(
+    db: &State<DatabaseConnection>,
+    caller: auth::Caller,
+    twitter_handle: &str,
+    sync: bool,
+    from: Option<&str>,
+    to: Option<&str>,
+    tz: Option<&str>,
+) -> Result<String, RequestError> {
+    let window = app::dates::DateWindow::parse(from, to, tz).map_err(BadRequestResponder::new)?;
+    allow_sync(&caller, sync)?;
+    let user_id = archived_user_id(db, &caller, twitter_handle).await?;
+    if window.is_unbounded() {
+        let tweets = caller
+            .fetching(app::load_user_tweets_from_twitter_handle(db, twitter_handle, sync))
+            .await
+            .ok_or_else(|| NotArchivedResponder::new(format!("user @{twitter_handle}")))?;
+        return Ok(utils::to_ron(&tweets));
+    }
+    Ok(utils::to_ron(
+        &caller
+            .fetching(app::load_users_tweets_between(
+                db,
+                twitter_handle,
+                user_id,
+                &window,
+                sync,
+            ))
+            .await,
+    ))
+}
+
+#[get("/user/<twitter_handle>/has_tweeted?<sync>&<from>&<to>&<tz>")]
+async fn has_user_tweeted_between(
+    db: &State<DatabaseConnection>,
+    caller: auth::Caller,
+    twitter_handle: &str,
+    sync: bool,
+    from: Option<&str>,
+    to: Option<&str>,
+    tz: Option<&str>,
+) -> Result<String, RequestError> {
+    let window = app::dates::DateWindow::parse(from, to, tz).map_err(BadRequestResponder::new)?;
+    allow_sync(&caller, sync)?;
+    let user_id = archived_user_id(db, &caller, twitter_handle).await?;
+    Ok(utils::to_ron(
+        &caller
+            .fetching(app::has_user_tweeted_between(
+                db,
+                twitter_handle,
+                user_id,
+                &window,
+                sync,
+            ))
+            .await,
+    ))
+}
+
+// The user's tweets on a YYYY-MM-DD day in `tz`, and on the same day in earlier years.
+#[get("/user/<twitter_handle>/on/<date>?<tz>")]
+async fn users_tweets_on_day(
+    db: &State<DatabaseConnection>,
+    caller: auth::Caller,
+    twitter_handle: &str,
+    date: &str,
+    tz: Option<&str>,
+) -> Result<String, RequestError> {
+    let day = app::dates::parse_day(date).map_err(BadRequestResponder::new)?;
+    let offset = app::dates::parse_tz(tz).map_err(BadRequestResponder::new)?;
+    let user_id = archived_user_id(db, &caller, twitter_handle).await?;
+    Ok(utils::to_ron(
+        &app::load_users_tweets_on_day(db, user_id, day, offset).await,
+    ))
+}
+
+#[get("/user/<twitter_handle>/conversations?<sync>")]
+async fn users_conversations(
+    db: &State<DatabaseConnection>,
+    caller: auth::Caller,
+    twitter_handle: &str,
+    sync: bool,
+) -> Result<String, RequestError> {
+    allow_sync(&caller, sync)?;
+    archived_user(db, &caller, twitter_handle).await?;
+    let conversations = caller
+        .fetching(app::load_user_conversations_from_twitter_handle(
+            db,
+            twitter_handle,
+            sync,
+        ))
+        .await
+        .ok_or_else(|| NotArchivedResponder::new(format!("user @{twitter_handle}")))?;
+    Ok(utils::to_ron(&conversations))
+}
+
+// With the annotations on the tweet when `annotations` is true.
+#[get("/tweet/<id>?<annotations>")]
+async fn tweet_by_id(
+    db: &State<DatabaseConnection>,
+    caller: auth::Caller,
+    id: i64,
+    annotations: Option<bool>,
+) -> Result<String, RequestError> {
+    let annotations = annotations.unwrap_or(false);
+    allow_annotations(&caller, annotations)?;
+    let tweet_data = archived_tweet(db, &caller, id).await?;
+    if annotations {
+        return Ok(utils::to_ron(
+            &app::annotations::annotated_tweet(db, tweet_data).await,
+        ));
+    }
+    Ok(utils::to_ron(&tweet_data))
+}
+
+// When the api was last asked about the tweet and what it said, including for tweets that
+// are archived but have since been deleted on twitter. Hidden tweets have no status to
+// show, like any other tweet that isn't archived.
+#[get("/tweet/<id>/status")]
+async fn tweet_status_by_id(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+    id: i64,
+) -> Result<String, NotArchivedResponder> {
+    if app::data::read::hidden_tweet(db, id).await.is_some() {
+        return Err(NotArchivedResponder::new(format!("status of tweet {id}")));
+    }
+    match app::data::read::tweet_status(db, id).await {
+        Some(status) => Ok(utils::to_ron(&status)),
+        None => Err(NotArchivedResponder::new(format!("status of tweet {id}"))),
+    }
+}
+
+// Every version of the tweet seen so far and how each differs from the one before.
+#[get("/tweet/<id>/history")]
+async fn tweet_history(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+    id: i64,
+) -> Result<String, NotArchivedResponder> {
+    match app::history::tweet_history(db, id).await {
+        Some(history) => Ok(utils::to_ron(&history)),
+        None => Err(NotArchivedResponder::new(format!("tweet of id {id}"))),
+    }
+}
+
+#[get("/network/communities")]
+async fn network_communities(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+) -> String {
+    utils::to_ron(&app::network::communities(db).await)
+}
+
+#[get("/network/graph.graphml")]
+async fn network_graphml(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+) -> (ContentType, String) {
+    (
+        ContentType::new("application", "graphml+xml"),
+        app::export::network::graphml(&app::network::interaction_graph(db).await),
+    )
+}
+
+#[get("/network/graph.gexf")]
+async fn network_gexf(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+) -> (ContentType, String) {
+    (
+        ContentType::new("application", "gexf+xml"),
+        app::export::network::gexf(&app::network::interaction_graph(db).await),
+    )
+}
+
+// With the annotations on the conversation and its tweets when `annotations` is true.
+#[get("/conversation/<id>?<annotations>")]
+async fn conversation_by_tweet_id(
+    db: &State<DatabaseConnection>,
+    caller: auth::Caller,
+    id: i64,
+    annotations: Option<bool>,
+) -> Result<String, RequestError> {
+    let annotations = annotations.unwrap_or(false);
+    allow_annotations(&caller, annotations)?;
+    archived_tweet(db, &caller, id).await?;
+    let conversation = app::load_twitter_conversation_from_tweet_id(db, id)
+        .await
+        .ok_or_else(|| NotArchivedResponder::new(format!("tweet of id {id}")))?;
+    if annotations {
+        return Ok(utils::to_ron(
+            &app::annotations::annotated_conversation(db, conversation).await,
+        ));
+    }
+    Ok(utils::to_ron(&conversation))
+}
+
+// Narrowed down to a collection, a tag or both when they're given.
+#[get("/search/<query>?<collection>&<tag>")]
+async fn search_tweets_in_db(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+    query: &str,
+    collection: Option<i32>,
+    tag: Option<&str>,
+) -> Result<String, RequestError> {
+    let filter = app::data::read::SearchFilter {
+        collection_id: collection,
+        tag: tag.map(app::collections::normalize_tag).transpose()?,
+    };
+    Ok(utils::to_ron(
+        &app::search_tweets_in_db(db, query, &filter).await,
+    ))
+}
+
+// Tweets closest in meaning to `q`, rather than containing it, from the embeddings index.
+#[cfg(feature = "semantic-search")]
+#[get("/search/semantic?<q>&<limit>")]
+async fn semantic_search(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+    q: &str,
+    limit: Option<usize>,
+) -> String {
+    let limit = limit.unwrap_or(app::semantic::DEFAULT_RESULTS);
+    utils::to_ron(&app::semantic::search(db, q, limit).await)
+}
+
+#[cfg(feature = "semantic-search")]
+#[get("/tweet/<id>/similar?<limit>")]
+async fn similar_tweets(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+    id: i64,
+    limit: Option<usize>,
+) -> Result<String, NotArchivedResponder> {
+    let limit = limit.unwrap_or(app::semantic::DEFAULT_RESULTS);
+    match app::semantic::similar(db, id, limit).await {
+        Some(similar) => Ok(utils::to_ron(&similar)),
+        None => Err(NotArchivedResponder::new(format!("tweet of id {id}"))),
+    }
+}
+
+#[get("/user/<twitter_handle>/feed.atom")]
+async fn users_atom_feed(
+    db: &State<DatabaseConnection>,
+    caller: auth::Caller,
+    twitter_handle: &str,
+) -> Result<(ContentType, String), RequestError> {
+    archived_user(db, &caller, twitter_handle).await?;
+    Ok((
+        ContentType::new("application", "atom+xml"),
+        app::export::feed::user_atom(db, twitter_handle)
+            .await
+            .ok_or_else(|| NotArchivedResponder::new(format!("user @{twitter_handle}")))?,
+    ))
+}
+
+#[get("/user/<twitter_handle>/feed.rss")]
+async fn users_rss_feed(
+    db: &State<DatabaseConnection>,
+    caller: auth::Caller,
+    twitter_handle: &str,
+) -> Result<(ContentType, String), RequestError> {
+    archived_user(db, &caller, twitter_handle).await?;
+    Ok((
+        ContentType::new("application", "rss+xml"),
+        app::export::feed::user_rss(db, twitter_handle)
+            .await
+            .ok_or_else(|| NotArchivedResponder::new(format!("user @{twitter_handle}")))?,
+    ))
+}
+
+#[get("/search/<query>/feed.atom")]
+async fn search_atom_feed(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+    query: &str,
+) -> (ContentType, String) {
+    (
+        ContentType::new("application", "atom+xml"),
+        app::export::feed::search_atom(db, query).await,
+    )
+}
+
+#[get("/watchlist")]
+async fn watchlist(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+) -> String {
+    utils::to_ron(&app::load_watched_accounts(db).await)
+}
+
+#[get("/watchlist/<twitter_handle>")]
+async fn watched_account(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+    twitter_handle: &str,
+) -> String {
+    utils::to_ron(&app::load_watched_account(db, twitter_handle).await)
+}
+
+// Watched accounts are synced from the api on a schedule, so changing them is for admins.
+#[post("/watchlist/<twitter_handle>?<interval_seconds>")]
+async fn watch_account(
+    db: &State<DatabaseConnection>,
+    _admin: auth::Admin,
+    twitter_handle: &str,
+    interval_seconds: Option<i64>,
+) -> Result<String, BadRequestResponder> {
+    let interval_seconds = interval_seconds.unwrap_or(scheduler::DEFAULT_INTERVAL_SECONDS);
+    let account = app::watch_account(db, twitter_handle, interval_seconds)
+        .await
+        .map_err(BadRequestResponder::new)?;
+    Ok(utils::to_ron(&account))
+}
+
+#[delete("/watchlist/<twitter_handle>")]
+async fn unwatch_account(
+    db: &State<DatabaseConnection>,
+    _admin: auth::Admin,
+    twitter_handle: &str,
+) -> String {
+    utils::to_ron(&app::unwatch_account(db, twitter_handle).await)
+}
+
+// How many requests each client has made since the server started, how many were refused
+// for going over a limit and how many api calls they caused.
+#[get("/admin/usage")]
+fn client_usage(limits: &State<limits::Limits>, _admin: auth::Admin) -> String {
+    utils::to_ron(&limits.usage())
+}
+
+// Deletes the tweet for good, along with what it references, its versions and its search
+// index entry, and keeps it hidden should it ever be fetched again.
+#[delete("/admin/tweet/<id>?<reason>")]
+async fn delete_tweet(
+    db: &State<DatabaseConnection>,
+    admin: auth::Admin,
+    id: i64,
+    reason: &str,
+) -> String {
+    utils::to_ron(&app::moderation::delete_tweet(db, id, reason, &admin.actor()).await)
+}
+
+// Leaves the tweet archived but out of everything served to clients.
+#[post("/admin/tweet/<id>/hide?<reason>")]
+async fn hide_tweet(
+    db: &State<DatabaseConnection>,
+    admin: auth::Admin,
+    id: i64,
+    reason: &str,
+) -> String {
+    utils::to_ron(&app::moderation::hide_tweet(db, id, reason, &admin.actor()).await)
+}
+
+#[delete("/admin/tweet/<id>/hide")]
+async fn unhide_tweet(db: &State<DatabaseConnection>, admin: auth::Admin, id: i64) -> String {
+    utils::to_ron(&app::moderation::unhide_tweet(db, id, &admin.actor()).await)
+}
+
+#[get("/admin/hidden")]
+async fn hidden_tweets(db: &State<DatabaseConnection>, _admin: auth::Admin) -> String {
+    utils::to_ron(&app::moderation::hidden_tweets(db).await)
+}
+
+// Removes the user with all their tweets and keeps them from being archived again.
+#[post("/admin/user/<twitter_handle>/redact?<reason>")]
+async fn redact_user(
+    db: &State<DatabaseConnection>,
+    admin: auth::Admin,
     twitter_handle: &str,
-    rfc3339_date: &str,
+    reason: &str,
 ) -> String {
-    utils::to_ron(&app::load_users_tweets_since_date(db, twitter_handle, rfc3339_date).await)
+    let target = app::moderation::RedactionTarget::Handle(twitter_handle.to_owned());
+    utils::to_ron(&app::moderation::redact_user(db, &target, reason, &admin.actor()).await)
+}
+
+#[post("/admin/userbyid/<id>/redact?<reason>")]
+async fn redact_user_by_id(
+    db: &State<DatabaseConnection>,
+    admin: auth::Admin,
+    id: i64,
+    reason: &str,
+) -> String {
+    let target = app::moderation::RedactionTarget::Id(id);
+    utils::to_ron(&app::moderation::redact_user(db, &target, reason, &admin.actor()).await)
+}
+
+#[get("/admin/audit?<limit>")]
+async fn audit_log(
+    db: &State<DatabaseConnection>,
+    _admin: auth::Admin,
+    limit: Option<u64>,
+) -> String {
+    utils::to_ron(&app::moderation::audit_log(db, limit.unwrap_or(100)).await)
+}
+
+#[get("/collections")]
+async fn collections(db: &State<DatabaseConnection>, _caller: auth::Caller) -> String {
+    utils::to_ron(&app::collections::list(db).await)
+}
+
+#[post("/collections/<name>?<description>")]
+async fn create_collection(
+    db: &State<DatabaseConnection>,
+    _curator: auth::Curator,
+    name: &str,
+    description: Option<&str>,
+) -> Result<String, RequestError> {
+    Ok(utils::to_ron(
+        &app::collections::create(db, name, description).await?,
+    ))
 }
 
-#[get("/user/<twitter_handle>/tweets")]
-async fn users_tweets(db: &State<DatabaseConnection>, twitter_handle: &str) -> String {
-    utils::to_ron(&app::load_user_tweets_from_twitter_handle(db, twitter_handle).await)
+#[get("/collection/<id>")]
+async fn collection(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+    id: i32,
+) -> Result<String, RequestError> {
+    Ok(utils::to_ron(&app::collections::load(db, id).await?))
 }
 
+// Renames the collection or changes its description.
+#[patch("/collection/<id>?<name>&<description>")]
+async fn update_collection(
+    db: &State<DatabaseConnection>,
+    _curator: auth::Curator,
+    id: i32,
+    name: Option<&str>,
+    description: Option<&str>,
+) -> Result<String, RequestError> {
+    Ok(utils::to_ron(
+        &app::collections::update(db, id, name, description).await?,
+    ))
+}
 
-#[get("/user/<twitter_handle>/conversations")]
-async fn users_conversations(db: &State<DatabaseConnection>, twitter_handle: &str) -> String {
-    utils::to_ron(&app::load_user_conversations_from_twitter_handle(db, twitter_handle).await)
+#[delete("/collection/<id>")]
+async fn delete_collection(
+    db: &State<DatabaseConnection>,
+    _curator: auth::Curator,
+    id: i32,
+) -> String {
+    utils::to_ron(&app::collections::delete(db, id).await)
 }
 
-#[get("/tweet/<id>")]
-async fn tweet_by_id(db: &State<DatabaseConnection>, id: i64) -> String {
-    utils::to_ron(&app::load_tweet_from_id(db, id).await)
+// Adds the tweet to the collection, or replaces its note if it's already there.
+#[put("/collection/<id>/tweet/<tweet_id>?<note>")]
+async fn add_to_collection(
+    db: &State<DatabaseConnection>,
+    _curator: auth::Curator,
+    id: i32,
+    tweet_id: i64,
+    note: Option<&str>,
+) -> Result<String, RequestError> {
+    Ok(utils::to_ron(
+        &app::collections::add_tweet(db, id, tweet_id, note).await?,
+    ))
 }
 
-#[get("/conversation/<id>")]
-async fn conversation_by_tweet_id(db: &State<DatabaseConnection>, id: i64) -> String {
-    utils::to_ron(&app::load_twitter_conversation_from_tweet_id(db, id).await)
+#[delete("/collection/<id>/tweet/<tweet_id>")]
+async fn remove_from_collection(
+    db: &State<DatabaseConnection>,
+    _curator: auth::Curator,
+    id: i32,
+    tweet_id: i64,
+) -> Result<String, RequestError> {
+    Ok(utils::to_ron(
+        &app::collections::remove_tweet(db, id, tweet_id).await?,
+    ))
 }
 
-#[get("/search/<query>")]
-async fn search_tweets_in_db(db: &State<DatabaseConnection>, query: &str) -> String {
-    utils::to_ron(&app::search_tweets_in_db(db, query).await)
+// The collection's tweets with their notes and tags, as ndjson (the default) or csv.
+#[get("/collection/<id>/export?<format>")]
+async fn export_collection(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+    id: i32,
+    format: Option<&str>,
+) -> Result<(ContentType, String), RequestError> {
+    let format = match format {
+        Some(format) => format.parse().map_err(BadRequestResponder::new)?,
+        None => app::export::dump::DumpFormat::Ndjson,
+    };
+    let collection = app::collections::load(db, id).await?;
+    let content_type = match format {
+        app::export::dump::DumpFormat::Ndjson => ndjson(),
+        app::export::dump::DumpFormat::Csv => ContentType::CSV,
+    };
+    Ok((
+        content_type,
+        app::export::collection::to_format(&collection, format),
+    ))
+}
+
+// Every tag in use, with how many tweets have it.
+#[get("/tags")]
+async fn tags(db: &State<DatabaseConnection>, _caller: auth::Caller) -> String {
+    utils::to_ron(&app::collections::tags(db).await)
 }
 
-#[launch]
-async fn rocket() -> _ {
+#[get("/tweet/<id>/tags")]
+async fn tweet_tags(
+    db: &State<DatabaseConnection>,
+    _caller: auth::Caller,
+    id: i64,
+) -> Result<String, NotArchivedResponder> {
+    if app::data::read::visible_tweet_by_id(db, id).await.tweet.is_none() {
+        return Err(NotArchivedResponder::new(format!("tweet of id {id}")));
+    }
+    Ok(utils::to_ron(&app::collections::tweet_tags(db, id).await))
+}
+
+#[put("/tweet/<id>/tags/<tag>")]
+async fn tag_tweet(
+    db: &State<DatabaseConnection>,
+    _curator: auth::Curator,
+    id: i64,
+    tag: &str,
+) -> Result<String, RequestError> {
+    Ok(utils::to_ron(&app::collections::tag_tweet(db, id, tag).await?))
+}
+
+#[delete("/tweet/<id>/tags/<tag>")]
+async fn untag_tweet(
+    db: &State<DatabaseConnection>,
+    _curator: auth::Curator,
+    id: i64,
+    tag: &str,
+) -> Result<String, RequestError> {
+    Ok(utils::to_ron(
+        &app::collections::untag_tweet(db, id, tag).await?,
+    ))
+}
+
+// Annotations are markdown, sent as the body of the request.
+#[post("/tweet/<id>/annotations", data = "<body>")]
+async fn annotate_tweet(
+    db: &State<DatabaseConnection>,
+    curator: auth::Curator,
+    id: i64,
+    body: String,
+) -> Result<String, RequestError> {
+    let anchor = app::annotations::Anchor::Tweet(id);
+    Ok(utils::to_ron(
+        &app::annotations::annotate(db, anchor, &curator.author(), &body).await?,
+    ))
+}
+
+// `id` is the conversation's id, which is that of the tweet that started it.
+#[post("/conversation/<id>/annotations", data = "<body>")]
+async fn annotate_conversation(
+    db: &State<DatabaseConnection>,
+    curator: auth::Curator,
+    id: i64,
+    body: String,
+) -> Result<String, RequestError> {
+    let anchor = app::annotations::Anchor::Conversation(id);
+    Ok(utils::to_ron(
+        &app::annotations::annotate(db, anchor, &curator.author(), &body).await?,
+    ))
+}
+
+#[get("/annotation/<id>")]
+async fn annotation(
+    db: &State<DatabaseConnection>,
+    _curator: auth::Curator,
+    id: i32,
+) -> Result<String, RequestError> {
+    Ok(utils::to_ron(&app::annotations::annotation(db, id).await?))
+}
+
+#[put("/annotation/<id>", data = "<body>")]
+async fn edit_annotation(
+    db: &State<DatabaseConnection>,
+    curator: auth::Curator,
+    id: i32,
+    body: String,
+) -> Result<String, RequestError> {
+    Ok(utils::to_ron(
+        &app::annotations::edit(db, id, &curator.author(), &body).await?,
+    ))
+}
+
+#[delete("/annotation/<id>")]
+async fn delete_annotation(
+    db: &State<DatabaseConnection>,
+    curator: auth::Curator,
+    id: i32,
+) -> Result<String, RequestError> {
+    Ok(utils::to_ron(
+        &app::annotations::delete(db, id, &curator.author()).await?,
+    ))
+}
+
+#[get("/annotations/search/<query>")]
+async fn search_annotations(
+    db: &State<DatabaseConnection>,
+    _curator: auth::Curator,
+    query: &str,
+) -> String {
+    utils::to_ron(&app::annotations::search(db, query).await)
+}
+
+// Users and tweets that aren't archived are fetched from the api, which callers need the
+// trigger-fetch scope for.
+async fn archived_user(
+    db: &State<DatabaseConnection>,
+    caller: &auth::Caller,
+    twitter_handle: &str,
+) -> Result<UserData, RequestError> {
+    let user_data = app::data::read::user_by_twitter_handle(db, twitter_handle).await;
+    if user_data.user.is_some() {
+        return Ok(user_data);
+    }
+    caller.require_fetch()?;
+    let user_data = caller
+        .fetching(app::load_user_from_twitter_handle(db, twitter_handle))
+        .await;
+    match &user_data.user {
+        Some(_user) => Ok(user_data),
+        None => Err(NotArchivedResponder::new(format!("user @{twitter_handle}")).into()),
+    }
+}
+
+async fn archived_user_id(
+    db: &State<DatabaseConnection>,
+    caller: &auth::Caller,
+    twitter_handle: &str,
+) -> Result<i64, RequestError> {
+    let user = archived_user(db, caller, twitter_handle)
+        .await?
+        .user
+        .unwrap_or_else(|| panic!("Failed to get @{twitter_handle} after archiving them"));
+    Ok(user.id)
+}
+
+async fn archived_tweet(
+    db: &State<DatabaseConnection>,
+    caller: &auth::Caller,
+    id: i64,
+) -> Result<TweetData, RequestError> {
+    if app::moderation::hidden_tweet(db, id).await.is_some() {
+        return Err(NotArchivedResponder::hidden(format!("tweet of id {id}")).into());
+    }
+    let mut tweet_data = app::load_tweet_from_archive(db, id).await;
+    if tweet_data.tweet.is_none() && tweet_data.status.is_none() {
+        caller.require_fetch()?;
+        tweet_data = caller.fetching(app::load_tweet_from_id(db, id)).await;
+    }
+    match &tweet_data.tweet {
+        Some(_tweet) => Ok(tweet_data),
+        None => Err(NotArchivedResponder::with_status(
+            format!("tweet of id {id}"),
+            tweet_data.status,
+        )
+        .into()),
+    }
+}
+
+// Annotations are only shown to those who may write them.
+fn allow_annotations(caller: &auth::Caller, annotations: bool) -> Result<(), ForbiddenResponder> {
+    if annotations {
+        caller
+            .require(app::keys::Scope::Curate)
+            .map_err(ForbiddenResponder::new)?;
+    }
+    Ok(())
+}
+
+// Syncing asks the api for new tweets.
+fn allow_sync(caller: &auth::Caller, sync: bool) -> Result<(), auth::FetchRefused> {
+    if sync {
+        caller.require_fetch()?;
+    }
+    Ok(())
+}
+
+#[rocket::main]
+async fn main() {
     dotenv().ok();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match cli::Command::parse(&args) {
+        Ok(cli::Command::Serve) => {
+            if let Err(error) = rocket().await.launch().await {
+                panic!("Failed to launch the server. Error: {}", error);
+            }
+        }
+        Ok(command) => cli::run(command).await,
+        Err(usage) => {
+            eprintln!("{usage}");
+            std::process::exit(2);
+        }
+    }
+}
+
+async fn rocket() -> Rocket<Build> {
     let db = match setup::set_up_db().await {
         Ok(db) => db,
         Err(err) => panic!("{}", err),
     };
-    rocket::build().manage(db).mount(
+    setup::migrate(&db)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to run migrations. Error: {:?}", error));
+    // Takedowns are applied before anything is served.
+    if let Ok(path) = std::env::var("REDACTION_LIST") {
+        let applied = app::moderation::apply_redaction_list(State::from(&db), &path).await;
+        println!("Applied {applied} redactions from {path}");
+    }
+    let rocket = rocket::build()
+        .manage(db)
+        .manage(limits::Limits::from_env())
+        .attach(limits::fairing())
+        .register("/", auth::catchers());
+    let rocket = if app::api::is_offline() {
+        println!("Running in offline mode, serving from the archive only");
+        rocket
+    } else {
+        rocket.attach(scheduler::fairing())
+    };
+    let rocket = rocket.mount(
         "/",
         // Don't forget to mount the new endpoint handlers
         routes![
             index,
+            user_page,
+            conversation_page,
+            tweet_page,
+            search_page,
             tweets,
             tweet_by_id,
+            tweet_status_by_id,
+            tweet_history,
             users,
             user_by_id,
             user_by_twitter_handle,
             users_tweets,
             users_conversations,
             user_info_by_twitter_handle,
+            user_stats,
+            user_interlocutors,
+            user_terms,
+            user_emerging_terms,
+            network_communities,
+            network_graphml,
+            network_gexf,
             conversation_by_tweet_id,
             users_tweets_since_date,
+            users_latest_tweet_by_id,
             has_user_tweeted_since_date,
-            search_tweets_in_db
+            has_user_tweeted_between,
+            users_tweets_on_day,
+            search_tweets_in_db,
+            collections,
+            create_collection,
+            collection,
+            update_collection,
+            delete_collection,
+            add_to_collection,
+            remove_from_collection,
+            export_collection,
+            tags,
+            tweet_tags,
+            tag_tweet,
+            untag_tweet,
+            annotate_tweet,
+            annotate_conversation,
+            annotation,
+            edit_annotation,
+            delete_annotation,
+            search_annotations,
+            users_atom_feed,
+            users_rss_feed,
+            search_atom_feed,
+            watchlist,
+            watched_account,
+            watch_account,
+            unwatch_account,
+            client_usage,
+            delete_tweet,
+            hide_tweet,
+            unhide_tweet,
+            hidden_tweets,
+            redact_user,
+            redact_user_by_id,
+            audit_log
         ],
-    )
+    );
+    #[cfg(feature = "semantic-search")]
+    let rocket = rocket.mount("/", routes![semantic_search, similar_tweets]);
+    rocket
 }
 
 #[derive(Responder)]
@@ -130,6 +1116,141 @@ struct ErrorResponder {
     message: String,
 }
 
+#[derive(Responder)]
+#[response(status = 404)]
+struct NotArchivedResponder {
+    message: String,
+}
+
+#[derive(Responder)]
+#[response(status = 404)]
+struct PageNotFoundResponder {
+    page: RawHtml<String>,
+}
+
+impl PageNotFoundResponder {
+    fn new(description: &str) -> Self {
+        Self {
+            page: RawHtml(app::web::not_found(description)),
+        }
+    }
+}
+
+#[derive(Responder)]
+#[response(status = 400)]
+struct BadRequestResponder {
+    message: String,
+}
+
+impl BadRequestResponder {
+    fn new(message: String) -> Self {
+        Self { message }
+    }
+}
+
+#[derive(Responder)]
+#[response(status = 403)]
+struct ForbiddenResponder {
+    message: String,
+}
+
+impl ForbiddenResponder {
+    fn new(message: String) -> Self {
+        Self { message }
+    }
+}
+
+// For routes that can fail more than one way.
+#[derive(Responder)]
+enum RequestError {
+    NotArchived(NotArchivedResponder),
+    BadRequest(BadRequestResponder),
+    Forbidden(ForbiddenResponder),
+    TooManyRequests(auth::TooManyRequests),
+}
+
+impl From<NotArchivedResponder> for RequestError {
+    fn from(responder: NotArchivedResponder) -> Self {
+        Self::NotArchived(responder)
+    }
+}
+
+impl From<BadRequestResponder> for RequestError {
+    fn from(responder: BadRequestResponder) -> Self {
+        Self::BadRequest(responder)
+    }
+}
+
+impl From<ForbiddenResponder> for RequestError {
+    fn from(responder: ForbiddenResponder) -> Self {
+        Self::Forbidden(responder)
+    }
+}
+
+impl From<app::collections::CollectionError> for RequestError {
+    fn from(error: app::collections::CollectionError) -> Self {
+        if error.is_not_found() {
+            Self::NotArchived(NotArchivedResponder::new(error.to_string()))
+        } else {
+            Self::BadRequest(BadRequestResponder::new(error.to_string()))
+        }
+    }
+}
+
+impl From<app::annotations::AnnotationError> for RequestError {
+    fn from(error: app::annotations::AnnotationError) -> Self {
+        match error {
+            app::annotations::AnnotationError::NotTheAuthor(_id) => {
+                Self::Forbidden(ForbiddenResponder::new(error.to_string()))
+            }
+            error if error.is_not_found() => {
+                Self::NotArchived(NotArchivedResponder::new(error.to_string()))
+            }
+            error => Self::BadRequest(BadRequestResponder::new(error.to_string())),
+        }
+    }
+}
+
+impl From<auth::FetchRefused> for RequestError {
+    fn from(refused: auth::FetchRefused) -> Self {
+        match refused {
+            auth::FetchRefused::MissingScope(message) => {
+                Self::Forbidden(ForbiddenResponder::new(message))
+            }
+            auth::FetchRefused::OverBudget(responder) => Self::TooManyRequests(responder),
+        }
+    }
+}
+
+impl NotArchivedResponder {
+    fn new(description: String) -> Self {
+        Self::with_status(description, None)
+    }
+
+    fn with_status(
+        description: String,
+        status: Option<app::data::entities::tweet_status::Model>,
+    ) -> Self {
+        Self {
+            message: utils::to_ron(&utils::NotArchived {
+                not_archived: description,
+                status,
+                hidden: false,
+            }),
+        }
+    }
+
+    fn hidden(description: String) -> Self {
+        Self {
+            message: utils::to_ron(&utils::NotArchived {
+                not_archived: description,
+                status: None,
+                hidden: true,
+            }),
+        }
+    }
+}
+
 // The following impl's are for easy conversion of error types.
 
 #[allow(clippy::from_over_into)]
@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use rocket::{fairing::AdHoc, Shutdown, State};
+use sea_orm::DatabaseConnection;
+
+use crate::app;
+use crate::app::data::entities::watched_accounts;
+
+pub const DEFAULT_INTERVAL_SECONDS: i64 = 60 * 60;
+// Accounts are only looked at once per tick, so a shorter interval would mean nothing.
+pub const MIN_INTERVAL_SECONDS: i64 = TICK.as_secs() as i64;
+const TICK: Duration = Duration::from_secs(60);
+
+// Syncs every watched account whose interval has elapsed, checking once per TICK.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_liftoff("Watched account scheduler", |rocket| {
+        Box::pin(async move {
+            let db = rocket
+                .state::<DatabaseConnection>()
+                .expect("The scheduler needs a managed database connection")
+                .clone();
+            tokio::spawn(run(db, rocket.shutdown()));
+        })
+    })
+}
+
+async fn run(db: DatabaseConnection, shutdown: Shutdown) {
+    loop {
+        tokio::select! {
+            _ = shutdown.clone() => break,
+            _ = tokio::time::sleep(TICK) => sync_due_accounts(&db).await,
+        }
+    }
+}
+
+async fn sync_due_accounts(db: &DatabaseConnection) {
+    let now = chrono::Utc::now().timestamp();
+    for account in app::load_watched_accounts(State::from(db)).await {
+        let is_due = account.last_synced_at.is_none_or(|last_synced_at| {
+            now - last_synced_at.timestamp() >= account.interval_seconds
+        });
+        if is_due {
+            sync_account(db, account).await;
+        }
+    }
+}
+
+pub async fn sync_account(
+    db: &DatabaseConnection,
+    account: watched_accounts::Model,
+) -> watched_accounts::Model {
+    println!("Syncing watched account @{}", account.username);
+    let task_db = db.clone();
+    let username = account.username.clone();
+    // The api layer panics on failure, so the sync runs in its own task to catch it.
+    let result =
+        tokio::spawn(
+            async move { app::sync_users_new_tweets(State::from(&task_db), &username).await },
+        )
+        .await;
+
+    let account = match result {
+        Ok(Ok(_new_tweets)) => watched_accounts::Model {
+            last_synced_at: Some(chrono::Utc::now().into()),
+            last_seen_tweet_id: app::load_users_latest_archived_tweet_id(
+                State::from(db),
+                &account.username,
+            )
+            .await,
+            last_error: None,
+            ..account
+        },
+        Ok(Err(error)) => watched_accounts::Model {
+            last_synced_at: Some(chrono::Utc::now().into()),
+            last_error: Some(error),
+            ..account
+        },
+        Err(error) => watched_accounts::Model {
+            last_synced_at: Some(chrono::Utc::now().into()),
+            last_error: Some(panic_message(error)),
+            ..account
+        },
+    };
+    app::data::write::watched_account(State::from(db), &account).await;
+    account
+}
+
+fn panic_message(error: tokio::task::JoinError) -> String {
+    match error.try_into_panic() {
+        Ok(payload) => payload
+            .downcast_ref::<String>()
+            .cloned()
+            .or_else(|| {
+                payload
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+            })
+            .unwrap_or_else(|| "Sync panicked".to_string()),
+        Err(error) => error.to_string(),
+    }
+}
@@ -3,11 +3,18 @@ use std::fs;
 
 use rocket::State;
 use sea_orm::DatabaseConnection;
-pub async fn all_tweets(db: &State<DatabaseConnection>) {
+
+// Loads every tweet in a ron file of tweet ids (like yudapearl_tweet_id_vec.ron) into the database.
+pub async fn tweets_from_id_file(
+    db: &State<DatabaseConnection>,
+    twitter_handle: &str,
+    id_file: &str,
+) {
     let skip = 0;
-    let id_vec_ron =
-        fs::read_to_string("yudapearl_tweet_id_vec.ron").expect("Failed to read ron file");
+    let id_vec_ron = fs::read_to_string(id_file)
+        .unwrap_or_else(|error| panic!("Failed to read ron file {id_file}. Error: {:?}", error));
     let id_vec: Vec<i64> = ron::from_str(&id_vec_ron).expect("Failed to parse ids from ron");
+    app::load_user_from_twitter_handle(db, twitter_handle).await;
     for (i, id) in id_vec.into_iter().enumerate().skip(skip) {
         println!("{i} Loading tweet {id}");
         app::load_tweet_from_id(db, id).await;
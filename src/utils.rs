@@ -1,11 +1,14 @@
+use std::collections::HashMap;
+
 use chrono::{DateTime, FixedOffset};
-use futures::{future::join_all, StreamExt};
 use rocket::{
     serde::Serialize,
     time::{format_description, OffsetDateTime},
     State,
 };
-use sea_orm::{ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use sea_orm::{
+    ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, FromQueryResult, QueryFilter,
+};
 use serde::Deserialize;
 use twitter_v2::{
     data::{ReferencedTweet, ReferencedTweetKind},
@@ -13,13 +16,29 @@ use twitter_v2::{
     Tweet, User,
 };
 
+use crate::app::data;
 use crate::app::data::entities::prelude::*;
 use crate::app::data::entities::*;
 
+// Sqlite refuses statements with more than 32766 bound parameters, so `IN` lists are
+// split into chunks below that.
+pub const MAX_QUERY_PARAMETERS: usize = 30_000;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TweetData {
     pub tweet: Option<tweets::Model>,
     pub references: Vec<tweet_references::Model>,
+    // Set when the tweet has since become unavailable on twitter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<tweet_status::Model>,
+    // The ids of every version of the tweet, oldest first, when the source says it was
+    // edited. Only archive imports carry it, and only on the way in; what was learnt from
+    // it lives in tweet_versions.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edit_history_tweet_ids: Vec<i64>,
+    // Notes on the tweet, only filled in for callers who ask for them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<annotations::Model>,
 }
 
 impl TweetData {
@@ -27,6 +46,9 @@ impl TweetData {
         TweetData {
             tweet: Some(tweet),
             references,
+            status: None,
+            edit_history_tweet_ids: Vec::new(),
+            annotations: Vec::new(),
         }
     }
 
@@ -34,6 +56,9 @@ impl TweetData {
         Self {
             tweet: None,
             references: Vec::new(),
+            status: None,
+            edit_history_tweet_ids: Vec::new(),
+            annotations: Vec::new(),
         }
     }
 
@@ -58,7 +83,13 @@ impl TweetData {
                 panic!("Failed to get tweet {id} from database. Error: {:?}", error)
             });
 
-        Self { tweet, references }
+        Self {
+            tweet,
+            references,
+            status: unavailable_status(db, id).await,
+            edit_history_tweet_ids: Vec::new(),
+            annotations: Vec::new(),
+        }
     }
 
     pub async fn read_from_data_model(
@@ -78,10 +109,54 @@ impl TweetData {
             })
             .into_iter()
             .collect();
+        let status = unavailable_status(db, tweet_model.id).await;
         Self {
-            tweet: Some(tweet_model),
-            references,
+            status,
+            ..Self::new(tweet_model, references)
+        }
+    }
+
+    // Loads the references for a whole list of tweets in one query per chunk of ids rather
+    // than one per tweet, keeping the order of `tweet_models`. Statuses for tweets that have
+    // gone from twitter take one more query.
+    pub async fn read_many_from_data_models(
+        db: &State<DatabaseConnection>,
+        tweet_models: Vec<tweets::Model>,
+    ) -> Vec<Self> {
+        let db = db as &DatabaseConnection;
+        let ids: Vec<i64> = tweet_models.iter().map(|tweet| tweet.id).collect();
+        let mut references: HashMap<i64, Vec<tweet_references::Model>> = HashMap::new();
+        for chunk in ids.chunks(MAX_QUERY_PARAMETERS) {
+            for reference in TweetReferences::find()
+                .filter(tweet_references::Column::SourceTweetId.is_in(chunk.to_vec()))
+                .all(db)
+                .await
+                .unwrap_or_else(|error| {
+                    panic!(
+                        "Failed to get tweet references for {} tweets. Error: {:?}",
+                        chunk.len(),
+                        error
+                    )
+                })
+            {
+                references
+                    .entry(reference.source_tweet_id)
+                    .or_default()
+                    .push(reference);
+            }
         }
+        let mut statuses = unavailable_statuses(db, &ids).await;
+        tweet_models
+            .into_iter()
+            .map(|tweet_model| {
+                let references = references.remove(&tweet_model.id).unwrap_or_default();
+                let status = statuses.remove(&tweet_model.id);
+                Self {
+                    status,
+                    ..Self::new(tweet_model, references)
+                }
+            })
+            .collect()
     }
 
     pub async fn from_api_tweet(tweet: Option<Tweet>) -> Self {
@@ -128,67 +203,103 @@ impl TweetData {
                     created_at: convert_date_to_chrono(tweet.created_at),
                 }),
                 references,
+                status: None,
+                // twitter-v2 can't request edit_history_tweet_ids, so a tweet from the api
+                // is never known to be an edit. Its versions are only noticed when a
+                // re-fetch of the same id brings new text.
+                edit_history_tweet_ids: Vec::new(),
+                annotations: Vec::new(),
             }
         } else {
-            TweetData {
-                tweet: None,
-                references: Vec::new(),
-            }
+            TweetData::empty()
         }
     }
 
     pub async fn read_many(db: &State<DatabaseConnection>, ids: &[i64]) -> Vec<Self> {
-        join_all(ids.iter().map(|id| Self::read(db, *id))).await
+        let mut tweet_models = Vec::new();
+        for chunk in ids.chunks(MAX_QUERY_PARAMETERS) {
+            tweet_models.extend(
+                Tweets::find()
+                    .filter(tweets::Column::Id.is_in(chunk.to_vec()))
+                    .all(db as &DatabaseConnection)
+                    .await
+                    .unwrap_or_else(|error| {
+                        panic!("Failed to get {} tweets from database. Error: {:?}", chunk.len(), error)
+                    }),
+            );
+        }
+        let tweets: HashMap<i64, Self> = Self::read_many_from_data_models(db, tweet_models)
+            .await
+            .into_iter()
+            .filter_map(|tweet_data| Some((tweet_data.tweet.as_ref()?.id, tweet_data)))
+            .collect();
+        ids.iter()
+            .map(|id| {
+                tweets.get(id).cloned().unwrap_or_else(Self::empty)
+            })
+            .collect()
     }
+}
 
-    pub async fn write(&self, db: &State<DatabaseConnection>) {
-        if let Some(tweet) = self.tweet.clone() {
-            let to_write = tweets::ActiveModel {
-                id: ActiveValue::set(tweet.id),
-                conversation_id: ActiveValue::set(tweet.conversation_id),
-                content: ActiveValue::set(tweet.content),
-                author_id: ActiveValue::set(tweet.author_id),
-                created_at: ActiveValue::set(tweet.created_at),
-            };
-
-            let res = Tweets::insert(to_write).exec(db.inner()).await;
+async fn unavailable_status(db: &DatabaseConnection, id: i64) -> Option<tweet_status::Model> {
+    unavailable_statuses(db, &[id]).await.remove(&id)
+}
 
-            match res {
-                Ok(_res) => (),
-                Err(_error) => println!(
-                    "Failed to to write tweet of id {} to the database.",
-                    tweet.id
-                ),
-            }
-            let tweet_reference_stream = futures::stream::iter(self.references.iter());
-            tweet_reference_stream
-                .for_each(|tweet_ref| async {
-                    let to_write = tweet_references::ActiveModel {
-                        source_tweet_id: ActiveValue::set(tweet_ref.source_tweet_id),
-                        reference_type: ActiveValue::set(tweet_ref.reference_type.clone()),
-                        referenced_tweet_id: ActiveValue::set(tweet_ref.referenced_tweet_id),
-                    };
-                    let res = TweetReferences::insert(to_write).exec(db.inner()).await;
-
-                    match res {
-                        Ok(_res) => (),
-                        Err(_e) => println!(
-                            "Failed to add tweet references for tweet {} to the database.",
-                            tweet.id
-                        ),
-                    }
+// Statuses are only attached to tweets that are no longer available, so tweets that are
+// still up read the same as before they were checked.
+async fn unavailable_statuses(
+    db: &DatabaseConnection,
+    ids: &[i64],
+) -> HashMap<i64, tweet_status::Model> {
+    let mut statuses = HashMap::new();
+    for chunk in ids.chunks(MAX_QUERY_PARAMETERS) {
+        statuses.extend(
+            TweetStatus::find()
+                .filter(tweet_status::Column::TweetId.is_in(chunk.to_vec()))
+                .filter(tweet_status::Column::Status.ne(TweetStatusKind::Available.as_str()))
+                .all(db)
+                .await
+                .unwrap_or_else(|error| {
+                    panic!("Failed to get tweet statuses from database. Error: {:?}", error)
                 })
-                .await;
+                .into_iter()
+                .map(|status| (status.tweet_id, status)),
+        );
+    }
+    statuses
+}
+
+// What the api said about a tweet the last time it was asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TweetStatusKind {
+    Available,
+    Deleted,
+    Protected,
+    Suspended,
+    NotFound,
+}
+
+impl TweetStatusKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Available => "available",
+            Self::Deleted => "deleted",
+            Self::Protected => "protected",
+            Self::Suspended => "suspended",
+            Self::NotFound => "not_found",
         }
     }
 
-    pub async fn write_many(db: &State<DatabaseConnection>, tweets: Vec<&Self>) {
-        let tweets = futures::stream::iter(tweets.iter());
-        tweets
-            .for_each(|tweet| async {
-                tweet.write(db).await;
-            })
-            .await;
+    pub fn kind_from_string(status: &str) -> Option<Self> {
+        match status {
+            "available" => Some(Self::Available),
+            "deleted" => Some(Self::Deleted),
+            "protected" => Some(Self::Protected),
+            "suspended" => Some(Self::Suspended),
+            "not_found" => Some(Self::NotFound),
+            _ => None,
+        }
     }
 }
 
@@ -250,6 +361,13 @@ impl UserData {
 
     pub async fn write(&self, db: &State<DatabaseConnection>) {
         if let Some(user) = self.user.clone() {
+            if data::read::redaction(db, Some(user.id), Some(&user.username))
+                .await
+                .is_some()
+            {
+                println!("Not writing redacted user @{}", user.username);
+                return;
+            }
             let to_write = users::ActiveModel {
                 id: ActiveValue::set(user.id),
                 name: ActiveValue::set(user.name),
@@ -274,6 +392,350 @@ impl UserData {
 pub struct ConversationData {
     pub id: i64,
     pub tweets: Vec<TweetData>,
+    // Notes on the conversation as a whole, only filled in for callers who ask for them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<annotations::Model>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TweetHistory {
+    pub tweet_id: i64,
+    pub versions: Vec<TweetVersionData>,
+}
+
+// A version of a tweet along with what changed from the one before it, word by word.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TweetVersionData {
+    pub version: i32,
+    pub edit_tweet_id: i64,
+    pub content: String,
+    pub recorded_at: DateTime<FixedOffset>,
+    pub changes: Vec<TextChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextChange {
+    Kept(String),
+    Removed(String),
+    Added(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveStats {
+    pub users: usize,
+    pub conversations: usize,
+    pub tweets: usize,
+    pub tweet_references: usize,
+    pub watched_accounts: usize,
+    pub tweet_statuses: usize,
+    pub tweet_versions: usize,
+    pub embeddings: usize,
+}
+
+// A user's tweets on one day and on the same day of each earlier year they tweeted in, with
+// days running midnight to midnight at `utc_offset`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OnThisDay {
+    pub user_id: i64,
+    pub date: String,
+    pub utc_offset: String,
+    pub tweets: Vec<TweetData>,
+    pub previous_years: Vec<DayTweets>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DayTweets {
+    pub date: String,
+    pub tweets: Vec<TweetData>,
+}
+
+// When and how a user tweets, from their archived tweets. Times are in UTC.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserActivity {
+    pub user_id: i64,
+    pub tweets: i64,
+    pub per_day: Vec<PeriodCount>,
+    pub per_week: Vec<PeriodCount>,
+    pub per_month: Vec<PeriodCount>,
+    // Indexed by hour, 0 to 23.
+    pub per_hour: Vec<i64>,
+    // Monday first, each with 24 hourly counts.
+    pub weekday_hours: Vec<WeekdayHours>,
+    pub kinds: TweetKindCounts,
+    pub longest_streaks: Vec<PostingStreak>,
+    // Threads are conversations the user started, measured in the user's own tweets.
+    pub threads: i64,
+    pub average_thread_length: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromQueryResult)]
+pub struct PeriodCount {
+    pub period: String,
+    pub tweets: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeekdayHours {
+    pub weekday: String,
+    pub hours: Vec<i64>,
+}
+
+// Each count comes with its share of all the user's tweets.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TweetKindCounts {
+    pub original: i64,
+    pub replies: i64,
+    pub quotes: i64,
+    pub retweets: i64,
+    pub original_ratio: f64,
+    pub reply_ratio: f64,
+    pub quote_ratio: f64,
+    pub retweet_ratio: f64,
+}
+
+// A run of consecutive days with at least one tweet, first and last day inclusive.
+#[derive(Debug, Clone, Serialize, Deserialize, FromQueryResult)]
+pub struct PostingStreak {
+    pub first_day: String,
+    pub last_day: String,
+    pub days: i64,
+}
+
+// Everyone who replied to or quoted another archived user, and how often. Users only
+// appear once they have an interaction with someone else.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InteractionGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<InteractionEdge>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphNode {
+    pub user_id: i64,
+    // None when the user's tweets are archived but the user isn't.
+    pub username: Option<String>,
+    pub community: i64,
+}
+
+// The replies and quotes from one user's tweets to another's, with when the first and
+// last of them were posted, in UTC.
+#[derive(Debug, Clone, Serialize, Deserialize, FromQueryResult)]
+pub struct InteractionEdge {
+    pub source_user_id: i64,
+    pub target_user_id: i64,
+    pub replies: i64,
+    pub quotes: i64,
+    pub weight: i64,
+    pub first_at: String,
+    pub last_at: String,
+}
+
+// Someone a user talks with, counting both directions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interlocutor {
+    pub user_id: i64,
+    pub username: Option<String>,
+    pub replies_to: i64,
+    pub replies_from: i64,
+    pub quotes_to: i64,
+    pub quotes_from: i64,
+    pub total: i64,
+    pub first_at: String,
+    pub last_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Community {
+    pub id: i64,
+    pub members: Vec<GraphNode>,
+    // The interactions between members of the community.
+    pub internal_weight: i64,
+}
+
+// The words and word pairs a user used most over a date range, overall and in each period.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TermReport {
+    pub user_id: i64,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub tweets: usize,
+    pub top_terms: Vec<TermCount>,
+    pub top_bigrams: Vec<TermCount>,
+    pub periods: Vec<PeriodTerms>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeriodTerms {
+    pub period: String,
+    pub tweets: usize,
+    pub top_terms: Vec<TermCount>,
+    pub top_bigrams: Vec<TermCount>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TermCount {
+    pub term: String,
+    pub count: usize,
+}
+
+// Terms used much more in a period than in the one before it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmergingTerms {
+    pub period: String,
+    pub previous_period: String,
+    pub terms: Vec<EmergingTerm>,
+}
+
+// `growth` is how many times more often the term was used, per word written, than in the
+// previous period, counting an unused term as used once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmergingTerm {
+    pub term: String,
+    pub count: usize,
+    pub previous_count: usize,
+    pub growth: f64,
+}
+
+// A tweet found by semantic search, with its cosine similarity to what was searched for.
+#[cfg(feature = "semantic-search")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimilarTweet {
+    pub score: f64,
+    pub tweet: TweetData,
+}
+
+// What one client has asked of the server since it started. `limited_requests` were refused
+// for going over the request rate or the api call budget.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientUsage {
+    pub client: String,
+    pub requests: u64,
+    pub limited_requests: u64,
+    pub upstream_calls: u64,
+}
+
+// A page of tweets out of `pages`, counting from 0.
+#[derive(Debug, Clone, Serialize)]
+pub struct TweetPage {
+    pub tweets: Vec<TweetData>,
+    pub page: usize,
+    pub pages: usize,
+}
+
+impl TweetPage {
+    // Cuts a page out of tweets that were all read at once.
+    pub fn of(tweets: Vec<TweetData>, page: usize, per_page: usize) -> Self {
+        let pages = tweets.len().div_ceil(per_page);
+        TweetPage {
+            tweets: tweets
+                .into_iter()
+                .skip(page * per_page)
+                .take(per_page)
+                .collect(),
+            page,
+            pages,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CollectionSummary {
+    pub collection: collections::Model,
+    pub tweets: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CollectionData {
+    pub collection: collections::Model,
+    pub tweets: Vec<CollectedTweet>,
+}
+
+// A tweet as it appears in a collection, with the curator's note and the tweet's tags.
+#[derive(Debug, Clone, Serialize)]
+pub struct CollectedTweet {
+    pub tweet: TweetData,
+    pub note: Option<String>,
+    pub added_at: DateTime<FixedOffset>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, FromQueryResult)]
+pub struct TagCount {
+    pub tag: String,
+    pub tweets: i64,
+}
+
+// What redacting a user removed. `unwatched` is whether they were on the watchlist.
+#[derive(Debug, Clone, Serialize)]
+pub struct RedactionReport {
+    pub redaction: redacted_users::Model,
+    pub user_removed: bool,
+    pub tweets_removed: usize,
+    pub unwatched: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifyReport {
+    pub tweets_missing_author: Vec<i64>,
+    pub tweets_missing_conversation: Vec<i64>,
+    pub references_missing_source_tweet: Vec<tweet_references::Model>,
+    pub references_missing_tweet: Vec<tweet_references::Model>,
+    pub conversations_without_tweets: Vec<i64>,
+    pub duplicate_user_ids: Vec<i64>,
+    pub duplicate_conversation_ids: Vec<i64>,
+    pub duplicate_tweet_ids: Vec<i64>,
+    // Tweets with the same reference stored more than once.
+    pub duplicate_reference_source_ids: Vec<i64>,
+    pub duplicate_usernames: Vec<String>,
+    pub malformed_dates: Vec<MalformedDate>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.tweets_missing_author.is_empty()
+            && self.tweets_missing_conversation.is_empty()
+            && self.references_missing_source_tweet.is_empty()
+            && self.references_missing_tweet.is_empty()
+            && self.conversations_without_tweets.is_empty()
+            && self.duplicate_user_ids.is_empty()
+            && self.duplicate_conversation_ids.is_empty()
+            && self.duplicate_tweet_ids.is_empty()
+            && self.duplicate_reference_source_ids.is_empty()
+            && self.duplicate_usernames.is_empty()
+            && self.malformed_dates.is_empty()
+    }
+}
+
+// A tweet whose created_at can't be read back as a date.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MalformedDate {
+    pub tweet_id: i64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairReport {
+    pub before: VerifyReport,
+    pub fetched_users: Vec<i64>,
+    pub fetched_tweets: Vec<i64>,
+    pub created_conversations: Vec<i64>,
+    pub redated_tweets: Vec<i64>,
+    pub pruned_tweets: Vec<i64>,
+    pub pruned_references: Vec<i64>,
+    pub pruned_conversations: Vec<i64>,
+    pub after: VerifyReport,
+}
+
+// Returned in place of a tweet or user that isn't in the archive.
+#[derive(Debug, Clone, Serialize)]
+pub struct NotArchived {
+    pub not_archived: String,
+    // Why the api couldn't return it either, when that's known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<tweet_status::Model>,
+    // Set when an admin has hidden the tweet. Why is only kept in the audit log.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
 }
 
 pub fn convert_date_to_chrono(date: Option<OffsetDateTime>) -> DateTime<FixedOffset> {
@@ -302,6 +764,13 @@ pub fn to_ron<T: ?Sized + Serialize>(item: &T) -> String {
     ron::ser::to_string_pretty(item, ron::ser::PrettyConfig::new())
         .expect("Failed to parse tweet into string")
 }
+
+pub fn to_ndjson_line<T: ?Sized + Serialize>(item: &T) -> String {
+    let mut line = serde_json::to_string(item).expect("Failed to parse item into json");
+    line.push('\n');
+    line
+}
+
 #[derive(Debug, Serialize)]
 pub struct TweetReferenceData {
     pub reference_type: ReferencedTweetKind,